@@ -0,0 +1,772 @@
+use bevy::camera::primitives::Aabb;
+use bevy::image::ImageSampler;
+use bevy::mesh::{Indices, VertexAttributeValues};
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use std::ops::Range;
+use voronoi_vivarium::Simulation;
+use voronoi_vivarium::foam::Solid;
+use voronoi_vivarium::voronoi::DOMAIN_SIZE;
+
+// Brightest glow the emission texture holds, in multiples of a cell's colour
+const MAX_GLOW: f32 = 8.0;
+
+// Texels per row of the emission texture
+const GLOW_WIDTH: usize = 64;
+
+// --- Resources ---
+
+// Every cell is drawn in one mesh: each vertex carries its cell's colour,
+// and a UV pointing at its cell's texel in an emission texture
+#[derive(Resource, Default)]
+pub struct CellMap {
+    pub entity: Option<Entity>,
+    pub mesh: Handle<Mesh>,
+    pub material: Handle<StandardMaterial>,
+    pub glow: Handle<Image>,
+    // Vertices of each cell, whose colours are rewritten in place
+    pub vertices: Vec<Range<usize>>,
+    // Indices of each cell, so a changed cell can be rewritten in place
+    pub triangles: Vec<Range<usize>>,
+    // Cell under the pointer when the current drag began
+    pub dragged: Option<usize>,
+    // Topology generation the mesh was last built from
+    pub generation: u64,
+}
+
+// The quad the raster tessellation is painted on, and its texture
+#[derive(Resource, Default)]
+pub struct RasterView {
+    pub entity: Option<Entity>,
+    pub image: Handle<Image>,
+}
+
+// Debug drawing, toggled from the UI
+#[derive(Resource, Default)]
+pub struct DebugOverlay {
+    pub ghosts: bool,
+}
+
+// How cells in the 3D domain are drawn: whole and see-through, or cut by
+// a horizontal plane
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Default)]
+pub struct VolumeView {
+    pub slice: bool,
+    pub height: f32,
+}
+
+// Obstacle being drawn: while active, clicks on the cells add corners
+// instead of splashing
+#[derive(Resource, Default)]
+pub struct ObstacleDraft {
+    pub active: bool,
+    pub points: Vec<Vec2>,
+}
+
+// --- Systems ---
+
+pub fn spawn_mesh_system(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    sim: Res<Simulation>,
+    view: Res<VolumeView>,
+    mut cell_map: ResMut<CellMap>,
+) {
+    if cell_map.generation == sim.generation && !view.is_changed() {
+        return;
+    }
+    let built = std::mem::replace(&mut cell_map.generation, sim.generation);
+
+    // Raster cells are painted by `raster_view_system` instead
+    if sim.topology.raster().is_some() {
+        if let Some(e) = cell_map.entity.take() {
+            commands.entity(e).despawn();
+        }
+        cell_map.vertices.clear();
+        cell_map.triangles.clear();
+        return;
+    }
+
+    // 1. The cells that changed shape since the mesh was built: every one
+    // when there is no mesh yet, the view changed or cells came and went
+    let count = sim.topology.polygons.len();
+    let kept = cell_map.entity.is_some()
+        && !view.is_changed()
+        && cell_map.vertices.len() == count
+        && sim.cell_generations.len() == count;
+    let parts: Vec<(usize, Part)> = (0..count)
+        .filter(|&i| !kept || sim.cell_generations[i] > built)
+        .map(|i| (i, part_of(&sim, &view, i)))
+        .collect();
+    if kept && parts.is_empty() {
+        return;
+    }
+
+    // 2. Cells keeping their vertex and index counts are written over in place
+    if kept
+        && let Some(e) = cell_map.entity
+        && let Some(mesh) = meshes.get_mut(&cell_map.mesh)
+        && rewrite(mesh, &parts, &cell_map.vertices, &cell_map.triangles)
+    {
+        // CRITICAL: Force AABB regeneration for picking!
+        commands.entity(e).remove::<Aabb>();
+        return;
+    }
+
+    // 3. Otherwise every cell into a new mesh, the unchanged ones copied
+    // over from the old
+    let old = meshes.get(&cell_map.mesh).filter(|_| kept);
+    let mut parts = parts.into_iter().peekable();
+    let mut batch = Batch::new(count);
+    for i in 0..count {
+        let part = match parts.next_if(|(j, _)| *j == i) {
+            Some((_, part)) => part,
+            None => old
+                .and_then(|mesh| copied_part(mesh, &cell_map.vertices[i], &cell_map.triangles[i]))
+                .unwrap_or_else(|| part_of(&sim, &view, i)),
+        };
+        batch.push(i, part);
+    }
+    let (mesh, vertices, triangles) = batch.into_mesh();
+    cell_map.vertices = vertices;
+    cell_map.triangles = triangles;
+
+    // 4. One texel of light per cell, row by row
+    let size = Extent3d {
+        width: GLOW_WIDTH as u32,
+        height: glow_rows(count) as u32,
+        depth_or_array_layers: 1,
+    };
+
+    // 5. Swap the new mesh in, or spawn the entity the first time
+    if let Some(e) = cell_map.entity {
+        if let Some(old) = meshes.get_mut(&cell_map.mesh) {
+            *old = mesh;
+        }
+        if let Some(glow) = images.get_mut(&cell_map.glow)
+            && glow.height() != size.height
+        {
+            glow.resize(size);
+        }
+        // CRITICAL: Force AABB regeneration for picking!
+        commands.entity(e).remove::<Aabb>();
+    } else {
+        let mut glow = Image::new_fill(
+            size,
+            TextureDimension::D2,
+            &[0, 0, 0, 255],
+            TextureFormat::Rgba8UnormSrgb,
+            bevy::asset::RenderAssetUsages::RENDER_WORLD
+                | bevy::asset::RenderAssetUsages::MAIN_WORLD,
+        );
+        glow.sampler = ImageSampler::nearest();
+        cell_map.glow = images.add(glow);
+        cell_map.mesh = meshes.add(mesh);
+        cell_map.material = materials.add(StandardMaterial {
+            metallic: 0.1,
+            perceptual_roughness: 0.8,
+            cull_mode: None,
+            emissive: LinearRgba::rgb(MAX_GLOW, MAX_GLOW, MAX_GLOW),
+            emissive_texture: Some(cell_map.glow.clone()),
+            ..default()
+        });
+        let id = commands
+            .spawn((
+                Mesh3d(cell_map.mesh.clone()),
+                MeshMaterial3d(cell_map.material.clone()),
+                Transform::default(),
+                Visibility::default(),
+            ))
+            .observe(on_click_splash) // Left Click
+            .observe(on_cell_drag_start)
+            .observe(on_cell_drag) // Drag
+            .id();
+        cell_map.entity = Some(id);
+    }
+}
+
+// Rows of the emission texture for `count` cells
+fn glow_rows(count: usize) -> usize {
+    count.div_ceil(GLOW_WIDTH).max(1)
+}
+
+// Cell `i` as drawn: its polyhedron (or its cut) in 3D, a patch on the
+// globe for the sphere, a flat polygon otherwise
+fn part_of(sim: &Simulation, view: &VolumeView, i: usize) -> Part {
+    let topology = &sim.topology;
+    if let Some(solid) = topology.solids.get(i) {
+        if view.slice {
+            cell_part(&solid.slice(view.height), view.height)
+        } else {
+            solid_part(solid)
+        }
+    } else if let Some(corners) = topology.globe.get(i) {
+        globe_part(corners)
+    } else {
+        cell_part(&topology.polygons[i], 0.0)
+    }
+}
+
+// Writes each part over its cell's vertices and indices in `mesh`, unless
+// one no longer has as many as before
+fn rewrite(
+    mesh: &mut Mesh,
+    parts: &[(usize, Part)],
+    vertices: &[Range<usize>],
+    triangles: &[Range<usize>],
+) -> bool {
+    let fits = parts.iter().all(|(i, part)| {
+        part.points.len() == vertices[*i].len() && part.indices.len() == triangles[*i].len()
+    });
+    if !fits {
+        return false;
+    }
+    if let Some(VertexAttributeValues::Float32x3(points)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION)
+    {
+        for (i, part) in parts {
+            for (slot, point) in points[vertices[*i].clone()].iter_mut().zip(&part.points) {
+                *slot = point.to_array();
+            }
+        }
+    }
+    if let Some(VertexAttributeValues::Float32x3(normals)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_NORMAL)
+    {
+        for (i, part) in parts {
+            for (slot, normal) in normals[vertices[*i].clone()].iter_mut().zip(&part.normals) {
+                *slot = normal.to_array();
+            }
+        }
+    }
+    if let Some(Indices::U32(indices)) = mesh.indices_mut() {
+        for (i, part) in parts {
+            let first = vertices[*i].start as u32;
+            for (slot, &k) in indices[triangles[*i].clone()].iter_mut().zip(&part.indices) {
+                *slot = first + k;
+            }
+        }
+    }
+    true
+}
+
+// A cell's part as it stands in `mesh`, with its indices made its own again
+fn copied_part(mesh: &Mesh, vertices: &Range<usize>, triangles: &Range<usize>) -> Option<Part> {
+    let Some(VertexAttributeValues::Float32x3(points)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return None;
+    };
+    let Some(VertexAttributeValues::Float32x3(normals)) = mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+    else {
+        return None;
+    };
+    let Some(Indices::U32(indices)) = mesh.indices() else {
+        return None;
+    };
+    let first = vertices.start as u32;
+    Some(Part {
+        points: points
+            .get(vertices.clone())?
+            .iter()
+            .map(|&p| p.into())
+            .collect(),
+        normals: normals
+            .get(vertices.clone())?
+            .iter()
+            .map(|&n| n.into())
+            .collect(),
+        indices: indices
+            .get(triangles.clone())?
+            .iter()
+            .map(|&k| k - first)
+            .collect(),
+    })
+}
+
+// One cell's corners, their normals and its triangles
+struct Part {
+    points: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    indices: Vec<u32>,
+}
+
+// The parts of every cell gathered into one mesh
+struct Batch {
+    points: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+    indices: Vec<u32>,
+    vertices: Vec<Range<usize>>,
+    triangles: Vec<Range<usize>>,
+    rows: usize,
+}
+
+impl Batch {
+    fn new(count: usize) -> Self {
+        Self {
+            points: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices: Vec::new(),
+            vertices: Vec::with_capacity(count),
+            triangles: Vec::with_capacity(count),
+            rows: glow_rows(count),
+        }
+    }
+
+    // Appends cell `i`, its vertices looking up its texel
+    fn push(&mut self, i: usize, part: Part) {
+        let first = self.points.len();
+        let texel = Vec2::new((i % GLOW_WIDTH) as f32, (i / GLOW_WIDTH) as f32) + 0.5;
+        let uv = texel / Vec2::new(GLOW_WIDTH as f32, self.rows as f32);
+        let first_index = self.indices.len();
+        self.points.extend(part.points);
+        self.normals.extend(part.normals);
+        self.uvs.resize(self.points.len(), uv);
+        self.indices
+            .extend(part.indices.iter().map(|&k| first as u32 + k));
+        self.vertices.push(first..self.points.len());
+        self.triangles.push(first_index..self.indices.len());
+    }
+
+    // The mesh, white until the colours are written, and each cell's
+    // vertices and indices
+    fn into_mesh(self) -> (Mesh, Vec<Range<usize>>, Vec<Range<usize>>) {
+        let colors = vec![[1.0; 4]; self.points.len()];
+        let mut mesh = Mesh::new(
+            bevy::mesh::PrimitiveTopology::TriangleList,
+            bevy::asset::RenderAssetUsages::RENDER_WORLD
+                | bevy::asset::RenderAssetUsages::MAIN_WORLD,
+        );
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.points);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        mesh.insert_indices(Indices::U32(self.indices));
+        (mesh, self.vertices, self.triangles)
+    }
+}
+
+// Triangulates a Voronoi polygon on the XZ plane, lifted to `height`
+fn cell_part(polygon: &[Vec2], height: f32) -> Part {
+    let points: Vec<Vec3> = polygon
+        .iter()
+        .map(|p| Vec3::new(p.x, height, p.y))
+        .collect();
+
+    // Degenerate cells keep no triangles so indices stay aligned
+    let indices = triangulate(polygon);
+
+    // One normal for the whole flat cell, facing as its triangles wind
+    let normal = indices
+        .chunks_exact(3)
+        .map(|t| {
+            let [a, b, c] = [t[0], t[1], t[2]].map(|k| points[k as usize]);
+            (b - a).cross(c - a)
+        })
+        .sum::<Vec3>()
+        .normalize_or_zero();
+    Part {
+        normals: vec![normal; points.len()],
+        points,
+        indices,
+    }
+}
+
+// Fans a spherical cell out from its middle, with normals pointing away
+// from the centre of the globe
+fn globe_part(corners: &[Vec3]) -> Part {
+    let radius = corners.first().map_or(0.0, |c| c.length());
+    let middle = corners.iter().sum::<Vec3>().normalize_or_zero() * radius;
+    let points: Vec<Vec3> = std::iter::once(middle)
+        .chain(corners.iter().copied())
+        .collect();
+    let normals: Vec<Vec3> = points.iter().map(|p| p.normalize_or_zero()).collect();
+
+    // Degenerate cells keep no triangles so indices stay aligned
+    let n = corners.len() as u32;
+    let indices = if n < 3 {
+        Vec::new()
+    } else {
+        (0..n).flat_map(|k| [0, k + 1, (k + 1) % n + 1]).collect()
+    };
+    Part {
+        points,
+        normals,
+        indices,
+    }
+}
+
+// Every face fanned out from its first corner, with flat normals
+fn solid_part(solid: &Solid) -> Part {
+    let mut points: Vec<Vec3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+    for face in &solid.faces {
+        let first = points.len() as u32;
+        let n = face.corners.len() as u32;
+        points.extend(&face.corners);
+        normals.extend(std::iter::repeat_n(face.normal(), face.corners.len()));
+        indices.extend((1..n.saturating_sub(1)).flat_map(|k| [first, first + k, first + k + 1]));
+    }
+    Part {
+        points,
+        normals,
+        indices,
+    }
+}
+
+// Ear clipping, since cells clipped to a concave domain can be concave
+// (with zero-width seams where the domain cuts them in two)
+fn triangulate(polygon: &[Vec2]) -> Vec<u32> {
+    let mut ring: Vec<usize> = (0..polygon.len()).collect();
+    let mut indices = Vec::new();
+    while ring.len() >= 3 {
+        let n = ring.len();
+        let corner = |k: usize| (ring[(k + n - 1) % n], ring[k], ring[(k + 1) % n]);
+        // A convex corner with nothing inside its triangle, or a flat one
+        // (to rounding) that can go without losing any area
+        let ear = (0..n)
+            .find(|&k| {
+                let (a, b, c) = corner(k);
+                let (a, b, c) = (polygon[a], polygon[b], polygon[c]);
+                let turn = (b - a).perp_dot(c - b);
+                let tolerance = 1e-5 * (b - a).length() * (c - b).length();
+                turn.abs() <= tolerance
+                    || (turn > 0.0
+                        && !ring.iter().any(|&q| {
+                            let q = polygon[q];
+                            (b - a).perp_dot(q - a) > 0.0
+                                && (c - b).perp_dot(q - b) > 0.0
+                                && (a - c).perp_dot(q - c) > 0.0
+                        }))
+            })
+            .unwrap_or(0);
+        let (a, b, c) = corner(ear);
+        indices.extend([a as u32, b as u32, c as u32]);
+        ring.remove(ear);
+    }
+    indices
+}
+
+pub fn state_update_system(
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    sim: Res<Simulation>,
+    view: Res<VolumeView>,
+    cell_map: Res<CellMap>,
+) {
+    if cell_map.entity.is_none() {
+        return;
+    }
+
+    // Whole 3D cells are see-through, so the inner ones show
+    let translucent = sim.state.domain.is_3d() && !view.slice;
+    let (alpha, alpha_mode) = if translucent {
+        (0.25, AlphaMode::Blend)
+    } else {
+        (1.0, AlphaMode::Opaque)
+    };
+    // Only touched on a switch, since touching it re-uploads it
+    if materials
+        .get(&cell_map.material)
+        .is_some_and(|mat| mat.alpha_mode != alpha_mode)
+        && let Some(mat) = materials.get_mut(&cell_map.material)
+    {
+        mat.alpha_mode = alpha_mode;
+    }
+
+    let Some(VertexAttributeValues::Float32x4(colors)) = meshes
+        .get_mut(&cell_map.mesh)
+        .and_then(|mesh| mesh.attribute_mut(Mesh::ATTRIBUTE_COLOR))
+    else {
+        return;
+    };
+    let Some(glow) = images
+        .get_mut(&cell_map.glow)
+        .and_then(|image| image.data.as_mut())
+    else {
+        return;
+    };
+    for (i, vertices) in cell_map.vertices.iter().enumerate() {
+        let Some(current) = sim.chemicals.get(i) else {
+            continue;
+        };
+
+        let color = sim.state.cell_color(current);
+        let emission = sim.state.cell_emission(current);
+        let base = Color::srgba(color.x, color.y, color.z, alpha).to_linear();
+        if let Some(cell_colors) = colors.get_mut(vertices.clone()) {
+            cell_colors.fill(base.to_f32_array());
+        }
+        // Each cell shines in its own colour, brighter with the emission species
+        let light = color * (1.0 + emission * 2.0).min(MAX_GLOW) / MAX_GLOW;
+        let texel = LinearRgba::rgb(light.x, light.y, light.z);
+        if let Some(bytes) = glow.get_mut(4 * i..4 * i + 4) {
+            bytes.copy_from_slice(&Srgba::from(texel).to_u8_array());
+        }
+    }
+}
+
+// Paints the raster tessellation onto a quad over the domain, one texel
+// per pixel in its cell's colour, spawning the quad when the raster
+// appears and dropping it when it goes
+pub fn raster_view_system(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    sim: Res<Simulation>,
+    mut view: ResMut<RasterView>,
+) {
+    let Some(raster) = sim.topology.raster() else {
+        if let Some(e) = view.entity.take() {
+            commands.entity(e).despawn();
+        }
+        return;
+    };
+    let side = raster.resolution as u32;
+    let size = Extent3d {
+        width: side,
+        height: side,
+        depth_or_array_layers: 1,
+    };
+
+    if view.entity.is_none() {
+        let mut image = Image::new_fill(
+            size,
+            TextureDimension::D2,
+            &[0, 0, 0, 255],
+            TextureFormat::Rgba8UnormSrgb,
+            bevy::asset::RenderAssetUsages::RENDER_WORLD
+                | bevy::asset::RenderAssetUsages::MAIN_WORLD,
+        );
+        // Crisp cell borders when zoomed in
+        image.sampler = ImageSampler::nearest();
+        view.image = images.add(image);
+
+        // The plane's texture rows run along +Z, as the raster's run along +Y
+        let half = DOMAIN_SIZE as f32 / 2.0;
+        let id = commands
+            .spawn((
+                Mesh3d(meshes.add(Plane3d::new(Vec3::Y, Vec2::splat(half)))),
+                MeshMaterial3d(materials.add(StandardMaterial {
+                    base_color_texture: Some(view.image.clone()),
+                    unlit: true,
+                    cull_mode: None,
+                    ..default()
+                })),
+                Transform::default(),
+                Visibility::default(),
+            ))
+            .observe(on_click_splash)
+            .observe(on_cell_drag_start)
+            .observe(on_cell_drag)
+            .id();
+        view.entity = Some(id);
+    }
+
+    let Some(image) = images.get_mut(&view.image) else {
+        return;
+    };
+    if image.width() != side {
+        image.resize(size);
+    }
+    let colors: Vec<[u8; 4]> = sim
+        .chemicals
+        .iter()
+        .map(|current| {
+            let color = sim.state.cell_color(current);
+            let [r, g, b] = color.to_array().map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8);
+            [r, g, b, 255]
+        })
+        .collect();
+    if let Some(data) = image.data.as_mut() {
+        for (texel, &label) in data.chunks_exact_mut(4).zip(&raster.labels) {
+            // Black outside the domain and in obstacles
+            let color = colors.get(label as usize).unwrap_or(&[0, 0, 0, 255]);
+            texel.copy_from_slice(color);
+        }
+    }
+}
+
+// Marks the ghost points, the domain outline and the ghosted band
+pub fn debug_overlay_system(mut gizmos: Gizmos, sim: Res<Simulation>, overlay: Res<DebugOverlay>) {
+    // Nothing to mark off the plane
+    if !overlay.ghosts || !sim.state.domain.is_flat() {
+        return;
+    }
+
+    let outline = sim.state.domain.outline();
+    gizmos.linestrip(
+        outline
+            .iter()
+            .chain(outline.first())
+            .map(|p| Vec3::new(p.x, 0.0, p.y)),
+        Color::WHITE,
+    );
+
+    // Gizmo circles and rects lie in XY; the cells lie in XZ
+    let flat = Quat::from_rotation_x(std::f32::consts::FRAC_PI_2);
+    let size = DOMAIN_SIZE as f32;
+    if let Some(margin) = sim.topology.ghost_margin() {
+        gizmos.rect(
+            Isometry3d::new(Vec3::ZERO, flat),
+            Vec2::splat(size + 2.0 * margin),
+            Color::srgb(0.4, 0.4, 0.4),
+        );
+    }
+    for ghost in sim.topology.ghosts() {
+        gizmos.circle(
+            Isometry3d::new(Vec3::new(ghost.x, 0.0, ghost.y), flat),
+            0.08,
+            Color::srgb(1.0, 0.6, 0.2),
+        );
+    }
+}
+
+// Outlines the cube of the 3D domain, and the slice plane through it
+pub fn volume_gizmo_system(mut gizmos: Gizmos, sim: Res<Simulation>, view: Res<VolumeView>) {
+    if !sim.state.domain.is_3d() {
+        return;
+    }
+    let size = DOMAIN_SIZE as f32;
+    gizmos.cuboid(
+        Transform::from_scale(Vec3::splat(size)),
+        Color::srgb(0.6, 0.6, 0.6),
+    );
+    if view.slice {
+        let flat = Quat::from_rotation_x(std::f32::consts::FRAC_PI_2);
+        gizmos.rect(
+            Isometry3d::new(Vec3::Y * view.height, flat),
+            Vec2::splat(size),
+            Color::srgb(1.0, 0.9, 0.2),
+        );
+    }
+}
+
+// Outlines the obstacles and the one being drawn
+pub fn obstacle_gizmo_system(mut gizmos: Gizmos, sim: Res<Simulation>, draft: Res<ObstacleDraft>) {
+    let to_world = |p: &Vec2| Vec3::new(p.x, 0.0, p.y);
+    for obstacle in &sim.state.obstacles {
+        let closing = obstacle
+            .vertices
+            .first()
+            .filter(|_| obstacle.vertices.len() > 2);
+        gizmos.linestrip(
+            obstacle.vertices.iter().chain(closing).map(to_world),
+            Color::srgb(0.8, 0.8, 0.8),
+        );
+    }
+    if draft.active {
+        let color = Color::srgb(1.0, 0.9, 0.2);
+        gizmos.linestrip(draft.points.iter().map(to_world), color);
+        for point in &draft.points {
+            let flat = Quat::from_rotation_x(std::f32::consts::FRAC_PI_2);
+            gizmos.circle(Isometry3d::new(to_world(point), flat), 0.1, color);
+        }
+    }
+}
+
+pub fn on_click_splash(
+    trigger: On<Pointer<Press>>,
+    mut sim: ResMut<Simulation>,
+    mut draft: ResMut<ObstacleDraft>,
+) {
+    let press = trigger.event();
+    let Some(hit) = press.hit.position else {
+        return;
+    };
+    if draft.active {
+        if press.button == PointerButton::Primary {
+            draft.points.push(Vec2::new(hit.x, hit.z));
+        }
+        return;
+    }
+
+    let cell = cell_under(&sim, hit);
+    let emission = sim.state.emission_species;
+    if let Some(i) = cell
+        && let Some(chem) = sim.chemicals.get_mut(i)
+    {
+        for (s, value) in chem.iter_mut().enumerate() {
+            // Flash the emission species
+            *value += if Some(s) == emission { 50.0 } else { 5.0 };
+        }
+        info!("Splashed cell");
+    }
+}
+
+// The cells share one mesh (or the raster quad), so look up the one hit
+fn cell_under(sim: &Simulation, hit: Vec3) -> Option<usize> {
+    match sim.topology.raster() {
+        Some(raster) => raster.label_at(Vec2::new(hit.x, hit.z)),
+        None => sim.state.cell_at(hit),
+    }
+}
+
+// Remembers the cell a drag starts on
+pub fn on_cell_drag_start(
+    trigger: On<Pointer<DragStart>>,
+    sim: Res<Simulation>,
+    mut cell_map: ResMut<CellMap>,
+) {
+    cell_map.dragged = trigger
+        .event()
+        .hit
+        .position
+        .and_then(|hit| cell_under(&sim, hit));
+}
+
+// The Drag Handler
+pub fn on_cell_drag(
+    trigger: On<Pointer<Drag>>,
+    mut sim: ResMut<Simulation>,
+    draft: Res<ObstacleDraft>,
+    cell_map: Res<CellMap>,
+) {
+    if draft.active {
+        return;
+    }
+
+    // Identify which site we are dragging
+    if let Some(idx) = cell_map.dragged {
+        // Get drag delta (screen space)
+        let drag_event = trigger.event();
+        let delta = drag_event.delta;
+
+        // Map Screen Delta to World Plane (X/Z)
+        // Camera is looking DOWN Y axis.
+        // Screen X+ (Right) -> World X+ (Right)
+        // Screen Y+ (Down)  -> World Z+ (Toward/Back depending on camera up)
+        // With standard look_at(ZERO, Y), screen Up (Y-) moves into the screen (Z-)
+
+        // Sensitivity factor (approximate for height=20.0)
+        let sensitivity = 0.05;
+
+        if let Some(&site) = sim.state.sites.get(idx) {
+            let step = Vec2::new(delta.x, -delta.y) * sensitivity;
+
+            if sim.state.domain.is_3d() {
+                // Across the screen, at the same height
+                let position = sim.state.position(idx) + Vec3::new(step.x, 0.0, step.y);
+                sim.state.place(idx, position);
+            } else {
+                sim.state.sites[idx] = if sim.state.domain.is_sphere() {
+                    // Along the globe, in the cell's own east and north
+                    sim.state.displace(site, step)
+                } else {
+                    // Clamp to domain, outside any obstacle
+                    let moved = sim.state.domain.confine(site + step, false);
+                    sim.state.obstacles.iter().fold(moved, |p, o| o.push_out(p))
+                };
+            }
+        }
+
+        // Request immediate rebuild
+        sim.state.rebuild_requested = true;
+    }
+}
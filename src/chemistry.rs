@@ -1,160 +1,638 @@
+use crate::kinetics::Local;
+use crate::mechanics;
+use crate::species::Boundary;
+use crate::sphere;
 use crate::state::SimState;
-use crate::voronoi::{CellIndex, DOMAIN_SIZE};
+use crate::voronoi::{DOMAIN_SIZE, Topology};
 use bevy::prelude::*;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-// --- Components ---
+// --- Data ---
 
-#[derive(Component, Default, Debug, Clone, Copy)]
+/// Concentration of every species in every cell, stored cell by cell.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Chemicals {
-    pub r: f32,
-    pub g: f32,
-    pub b: f32,
-    pub e: f32, // Emission
+    species: usize,
+    values: Vec<f32>,
 }
 
-#[derive(Component, Default, Debug, Clone, Copy)]
-pub struct NextChemicals {
-    pub r: f32,
-    pub g: f32,
-    pub b: f32,
-    pub e: f32,
+impl Chemicals {
+    pub fn zeros(cells: usize, species: usize) -> Self {
+        Self {
+            species,
+            values: vec![0.0; cells * species],
+        }
+    }
+
+    /// Gradient initialisation of every site in `state`:
+    /// species 0 follows X, species 1 follows Y, species 2 peaks at the
+    /// centre, and the emission species (and any others) start random.
+    pub fn gradient(state: &SimState, rng: &mut impl Rng) -> Self {
+        let species = state.species.len();
+        let mut chemicals = Self::zeros(state.sites.len(), species);
+        if species == 0 {
+            return chemicals;
+        }
+
+        let range_max = (DOMAIN_SIZE / 2.0) as f32;
+        // Add Noise
+        let noise = 0.05;
+
+        for (&pos, cell) in state
+            .sites
+            .iter()
+            .zip(chemicals.values.chunks_exact_mut(species))
+        {
+            // Normalize -10..10 to 0..1
+            let nx = (pos.x / range_max + 1.0) * 0.5;
+            let ny = (pos.y / range_max + 1.0) * 0.5;
+            let dist_center = 1.0 - (pos.length() / range_max).clamp(0.0, 1.0);
+
+            for (s, value) in cell.iter_mut().enumerate() {
+                let base = match s {
+                    _ if state.emission_species == Some(s) => None,
+                    0 => Some(nx),
+                    1 => Some(ny),
+                    2 => Some(dist_center),
+                    _ => None,
+                };
+                *value = match base {
+                    Some(base) => (base + rng.gen_range(-noise..noise)).clamp(0.0, 1.0),
+                    None => rng.r#gen(), // Emission can be random
+                };
+            }
+        }
+        chemicals
+    }
+
+    pub fn species(&self) -> usize {
+        self.species
+    }
+
+    /// Number of cells.
+    pub fn len(&self) -> usize {
+        self.values.len().checked_div(self.species).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn get(&self, cell: usize) -> Option<&[f32]> {
+        self.values
+            .get(cell * self.species..(cell + 1) * self.species)
+    }
+
+    pub fn get_mut(&mut self, cell: usize) -> Option<&mut [f32]> {
+        self.values
+            .get_mut(cell * self.species..(cell + 1) * self.species)
+    }
+
+    /// Per-cell concentration slices.
+    pub fn iter(&self) -> std::slice::ChunksExact<'_, f32> {
+        self.values.chunks_exact(self.species.max(1))
+    }
+
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    /// Appends a cell with the given concentrations (one per species).
+    pub fn push_cell(&mut self, cell: &[f32]) {
+        self.values.extend_from_slice(&cell[..self.species]);
+    }
+
+    /// Keeps only the cells for which `keep(index)` is true, in order.
+    pub fn retain_cells(&mut self, mut keep: impl FnMut(usize) -> bool) {
+        let species = self.species.max(1);
+        let mut index = 0;
+        let mut cell = 0;
+        let mut kept = true;
+        self.values.retain(|_| {
+            if index == 0 {
+                kept = keep(cell);
+            }
+            index += 1;
+            if index == species {
+                index = 0;
+                cell += 1;
+            }
+            kept
+        });
+    }
+
+    /// Gives every cell from `first` on the mean concentrations of its
+    /// neighbours below `first`, working outwards through cells that only
+    /// border other new ones. Cells with no such path get the overall mean.
+    pub fn interpolate_from(&mut self, first: usize, topology: &Topology) {
+        let species = self.species;
+        let count = self.len();
+        let mut known: Vec<bool> = (0..count).map(|i| i < first).collect();
+        let mut pending: Vec<usize> = (first..count).collect();
+
+        // 1. Fill in rings; cells filled in one pass count from the next
+        while !pending.is_empty() {
+            let filled: Vec<(usize, Vec<f32>)> = pending
+                .iter()
+                .filter_map(|&i| {
+                    let mut sum = vec![0.0; species];
+                    let mut n = 0;
+                    for &j in topology.neighbors.get(i)?.iter().filter(|&&j| known[j]) {
+                        let neighbor = self.get(j)?;
+                        sum.iter_mut().zip(neighbor).for_each(|(s, c)| *s += c);
+                        n += 1;
+                    }
+                    (n > 0).then(|| (i, sum.into_iter().map(|s| s / n as f32).collect()))
+                })
+                .collect();
+            if filled.is_empty() {
+                break;
+            }
+            for (i, cell) in filled {
+                if let Some(target) = self.get_mut(i) {
+                    target.copy_from_slice(&cell);
+                }
+                known[i] = true;
+            }
+            pending.retain(|&i| !known[i]);
+        }
+
+        // 2. Isolated leftovers
+        let sources = known.iter().filter(|&&k| k).count();
+        if pending.is_empty() || sources == 0 {
+            return;
+        }
+        let mut mean = vec![0.0; species];
+        for (cell, _) in self.iter().zip(&known).filter(|(_, k)| **k) {
+            mean.iter_mut()
+                .zip(cell)
+                .for_each(|(m, c)| *m += c / sources as f32);
+        }
+        for i in pending {
+            if let Some(target) = self.get_mut(i) {
+                target.copy_from_slice(&mean);
+            }
+        }
+    }
+
+    /// Appends a species with zero concentration everywhere.
+    pub fn push_species(&mut self) {
+        let old = self.species;
+        self.values = self
+            .values
+            .chunks_exact(old.max(1))
+            .flat_map(|cell| cell.iter().copied().chain([0.0]))
+            .collect();
+        self.species += 1;
+    }
+
+    /// Drops species `index` from every cell.
+    pub fn remove_species(&mut self, index: usize) {
+        let old = self.species;
+        self.values = self
+            .values
+            .chunks_exact(old.max(1))
+            .flat_map(|cell| {
+                cell.iter()
+                    .enumerate()
+                    .filter(move |&(s, _)| s != index)
+                    .map(|(_, &v)| v)
+            })
+            .collect();
+        self.species = old.saturating_sub(1);
+    }
 }
 
-#[derive(Component, Default, Debug, Clone)]
-pub struct Neighbors {
-    pub indices: Vec<usize>,
+// --- Integrators ---
+
+/// Time-stepping scheme for the reaction-diffusion update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Integrator {
+    #[default]
+    ForwardEuler,
+    Heun,
+    Rk4,
+    /// Diffusion solved implicitly (unconditionally stable), reaction and decay explicitly.
+    SemiImplicit,
 }
 
-// --- Resources ---
+impl Integrator {
+    pub const ALL: [Self; 4] = [
+        Self::ForwardEuler,
+        Self::Heun,
+        Self::Rk4,
+        Self::SemiImplicit,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::ForwardEuler => "Forward Euler",
+            Self::Heun => "RK2 (Heun)",
+            Self::Rk4 => "RK4",
+            Self::SemiImplicit => "Semi-Implicit Diffusion",
+        }
+    }
 
-#[derive(Resource, Default)]
-pub struct CellMap {
-    pub entities: Vec<Entity>,
+    // Extent of the stability region along the negative real axis
+    fn stability_radius(self) -> f32 {
+        match self {
+            Self::ForwardEuler | Self::Heun | Self::SemiImplicit => 2.0,
+            Self::Rk4 => 2.78,
+        }
+    }
+}
+
+/// Jacobi sweeps used to solve the implicit diffusion system.
+const JACOBI_ITERATIONS: usize = 8;
+
+/// Upper bound on the substeps the stability check may split a step into.
+pub const MAX_ADAPTIVE_SUBSTEPS: u32 = 256;
+
+/// Scratch buffers reused between steps so integration does not allocate.
+#[derive(Debug, Default)]
+pub struct Workspace {
+    start: Vec<f32>,
+    stages: [Vec<f32>; 4],
+    probe: Vec<f32>,
+    result: Vec<f32>,
 }
 
-// --- Systems ---
+/// Largest step the selected integrator can take without going unstable.
+/// Uses a Gershgorin bound on the Jacobian: diffusion contributes at most
+/// `2 * rate * max_coupling`, decay its rate, and the reaction kernel its
+/// own stiffness estimate.
+pub fn stable_dt(state: &SimState, topology: &Topology) -> f32 {
+    let implicit_diffusion = state.integrator == Integrator::SemiImplicit;
+    let coupling = topology.max_coupling(state.laplacian);
+    let mut stiffness: f32 = 0.0;
+    for species in &state.species {
+        let diffusion = if implicit_diffusion {
+            0.0
+        } else {
+            2.0 * species.diffusion.abs() * coupling
+        };
+        stiffness = stiffness.max(diffusion + species.decay.abs());
+    }
+    stiffness += state.reaction_kernel().stiffness();
+
+    if stiffness > 0.0 {
+        state.integrator.stability_radius() / stiffness
+    } else {
+        f32::INFINITY
+    }
+}
 
-pub fn reaction_diffusion_system(
-    mut query: Query<(Entity, &Neighbors, &Chemicals, &mut NextChemicals)>,
-    all_chemicals: Query<&Chemicals>,
-    cell_map: Res<CellMap>,
-    time: Res<Time>,
-    state: Res<SimState>,
+/// Number of equal substeps needed to keep a step of `dt` stable.
+pub fn stable_substeps(state: &SimState, topology: &Topology, dt: f32) -> u32 {
+    // Stay a little inside the stability boundary
+    let limit = 0.9 * stable_dt(state, topology);
+    let substeps = (dt / limit).ceil();
+    if substeps.is_finite() {
+        (substeps as u32).clamp(1, MAX_ADAPTIVE_SUBSTEPS)
+    } else {
+        1
+    }
+}
+
+// --- Update Rules ---
+
+// dC/dt of every cell: diffusion (optional) + reaction - decay
+fn derivative(
+    state: &SimState,
+    topology: &Topology,
+    current: &[f32],
+    out: &mut [f32],
+    with_diffusion: bool,
 ) {
-    let dt = time.delta_secs();
-    let diff = state.diffusion_rates;
-    let decay = state.decay_rates;
-    let reaction = state.reaction_matrix;
-
-    for (_entity, neighbors, my_chem, mut next_chem) in query.iter_mut() {
-        // 1. Laplacian (Diffusion)
-        let mut laplacian_r = 0.0;
-        let mut laplacian_g = 0.0;
-        let mut laplacian_b = 0.0;
-        let mut laplacian_e = 0.0;
-
-        for &neighbor_idx in &neighbors.indices {
-            if let Some(neighbor_entity) = cell_map.entities.get(neighbor_idx)
-                && let Ok(neighbor_chem) = all_chemicals.get(*neighbor_entity)
-            {
-                laplacian_r += neighbor_chem.r - my_chem.r;
-                laplacian_g += neighbor_chem.g - my_chem.g;
-                laplacian_b += neighbor_chem.b - my_chem.b;
-                laplacian_e += neighbor_chem.e - my_chem.e;
-            }
-        }
-
-        // 2. Reaction (Alchemy)
-        // dC/dt = Matrix * C
-        let my_rgb = Vec3::new(my_chem.r, my_chem.g, my_chem.b);
-        let delta_rgb = reaction * my_rgb; // Mat3 * Vec3
-
-        // 3. Integration
-        next_chem.r = my_chem.r + (diff.x * laplacian_r * dt) + (delta_rgb.x * dt);
-        next_chem.g = my_chem.g + (diff.y * laplacian_g * dt) + (delta_rgb.y * dt);
-        next_chem.b = my_chem.b + (diff.z * laplacian_b * dt) + (delta_rgb.z * dt);
-        next_chem.e = my_chem.e + (diff.w * laplacian_e * dt);
-
-        // 4. Decay
-        next_chem.r -= decay.x * dt * next_chem.r;
-        next_chem.g -= decay.y * dt * next_chem.g;
-        next_chem.b -= decay.z * dt * next_chem.b;
-        next_chem.e -= decay.w * dt * next_chem.e;
-
-        // Clamp
-        next_chem.r = next_chem.r.clamp(0.0, 1.0);
-        next_chem.g = next_chem.g.clamp(0.0, 1.0);
-        next_chem.b = next_chem.b.clamp(0.0, 1.0);
-        next_chem.e = next_chem.e.clamp(0.0, 1.0);
-    }
-}
-
-pub fn chemical_motility_system(
-    query: Query<(&Chemicals, &Neighbors, &CellIndex)>,
-    all_chemicals: Query<&Chemicals>,
-    cell_map: Res<CellMap>,
-    mut state: ResMut<SimState>,
-    time: Res<Time>,
+    let n = state.species.len();
+    let kernel = state.reaction_kernel();
+    let needs_neighbors = kernel.needs_neighbors();
+    let mut neighbor_mean = vec![0.0; if needs_neighbors { n } else { 0 }];
+    for (i, (me, rate)) in current
+        .chunks_exact(n)
+        .zip(out.chunks_exact_mut(n))
+        .enumerate()
+    {
+        // 1. Reaction (Alchemy)
+        if needs_neighbors {
+            mean_of_neighbors(topology, current, i, me, &mut neighbor_mean);
+        }
+        let local = Local {
+            cell: me,
+            neighbor_mean: if needs_neighbors { &neighbor_mean } else { me },
+            area: topology.areas.get(i).copied().unwrap_or(0.0),
+        };
+        kernel.react_local(&local, rate);
+
+        // 2. Decay
+        for ((r, &c), species) in rate.iter_mut().zip(me).zip(&state.species) {
+            *r -= species.decay * c;
+        }
+
+        // 3. Laplacian (Diffusion)
+        if with_diffusion {
+            for (neighbor_idx, weight) in topology.couplings(i, state.laplacian) {
+                if let Some(neighbor) = current.get(neighbor_idx * n..(neighbor_idx + 1) * n) {
+                    for (s, species) in state.species.iter().enumerate() {
+                        rate[s] += species.diffusion * weight * (neighbor[s] - me[s]);
+                    }
+                }
+            }
+
+            // 4. Outflow through absorbing walls
+            let wall = topology.boundary_coupling(i, state.laplacian);
+            for ((r, &c), species) in rate.iter_mut().zip(me).zip(&state.species) {
+                if species.boundary == Boundary::Absorbing {
+                    *r -= species.diffusion * wall * c;
+                }
+            }
+        }
+    }
+}
+
+// Unweighted mean over the neighbours of `cell`; a cell without neighbours
+// sees only itself
+fn mean_of_neighbors(
+    topology: &Topology,
+    current: &[f32],
+    cell: usize,
+    me: &[f32],
+    out: &mut [f32],
+) {
+    let n = me.len();
+    out.fill(0.0);
+    let mut count = 0;
+    for &j in topology.neighbors.get(cell).into_iter().flatten() {
+        if let Some(neighbor) = current.get(j * n..(j + 1) * n) {
+            for (o, &c) in out.iter_mut().zip(neighbor) {
+                *o += c;
+            }
+            count += 1;
+        }
+    }
+    if count == 0 {
+        out.copy_from_slice(me);
+    } else {
+        for o in out.iter_mut() {
+            *o /= count as f32;
+        }
+    }
+}
+
+// Solves (I - dt * D * L) x = rhs by Jacobi iteration, starting from `x = rhs`
+fn implicit_diffusion(
+    state: &SimState,
+    topology: &Topology,
+    rhs: &[f32],
+    x: &mut [f32],
+    scratch: &mut [f32],
+    dt: f32,
 ) {
-    // Don't simulate physics if dragging/rebuilding
-    // if state.rebuild_requested { return; }
+    let n = state.species.len();
+    x.copy_from_slice(rhs);
+
+    for _ in 0..JACOBI_ITERATIONS {
+        for (i, (out, b)) in scratch
+            .chunks_exact_mut(n)
+            .zip(rhs.chunks_exact(n))
+            .enumerate()
+        {
+            out.copy_from_slice(b);
+            let mut total_weight = 0.0;
+            for (neighbor_idx, weight) in topology.couplings(i, state.laplacian) {
+                if let Some(neighbor) = x.get(neighbor_idx * n..(neighbor_idx + 1) * n) {
+                    for (s, species) in state.species.iter().enumerate() {
+                        out[s] += species.diffusion * dt * weight * neighbor[s];
+                    }
+                    total_weight += weight;
+                }
+            }
+            // Absorbing walls couple to an outside held at zero
+            let wall = topology.boundary_coupling(i, state.laplacian);
+            for (o, species) in out.iter_mut().zip(&state.species) {
+                let outflow = if species.boundary == Boundary::Absorbing {
+                    wall
+                } else {
+                    0.0
+                };
+                *o /= 1.0 + species.diffusion * dt * (total_weight + outflow);
+            }
+        }
+        x.copy_from_slice(scratch);
+    }
+}
+
+// out = y + d * h
+fn offset(out: &mut [f32], y: &[f32], d: &[f32], h: f32) {
+    for ((o, &y), &d) in out.iter_mut().zip(y).zip(d) {
+        *o = y + d * h;
+    }
+}
 
-    let dt = time.delta_secs();
-    let forces = state.force_matrix;
+/// Advances `chemicals` by one reaction-diffusion step of `dt`
+/// with the integrator selected in `state`.
+pub fn reaction_diffusion(
+    state: &SimState,
+    topology: &Topology,
+    chemicals: &mut Chemicals,
+    work: &mut Workspace,
+    dt: f32,
+) {
+    if chemicals.species() != state.species.len() || chemicals.species() == 0 {
+        return;
+    }
+
+    let Workspace {
+        start,
+        stages: [k1, k2, k3, k4],
+        probe,
+        result,
+    } = work;
+    start.clear();
+    start.extend_from_slice(chemicals.values());
+    for buffer in [
+        &mut *k1,
+        &mut *k2,
+        &mut *k3,
+        &mut *k4,
+        &mut *probe,
+        &mut *result,
+    ] {
+        buffer.resize(start.len(), 0.0);
+    }
+
+    match state.integrator {
+        Integrator::ForwardEuler => {
+            derivative(state, topology, start, k1, true);
+            offset(result, start, k1, dt);
+        }
+        Integrator::Heun => {
+            derivative(state, topology, start, k1, true);
+            offset(probe, start, k1, dt);
+            derivative(state, topology, probe, k2, true);
+            for (i, out) in result.iter_mut().enumerate() {
+                *out = start[i] + (k1[i] + k2[i]) * (dt / 2.0);
+            }
+        }
+        Integrator::Rk4 => {
+            derivative(state, topology, start, k1, true);
+            offset(probe, start, k1, dt / 2.0);
+            derivative(state, topology, probe, k2, true);
+            offset(probe, start, k2, dt / 2.0);
+            derivative(state, topology, probe, k3, true);
+            offset(probe, start, k3, dt);
+            derivative(state, topology, probe, k4, true);
+            for (i, out) in result.iter_mut().enumerate() {
+                *out = start[i] + (k1[i] + 2.0 * k2[i] + 2.0 * k3[i] + k4[i]) * (dt / 6.0);
+            }
+        }
+        Integrator::SemiImplicit => {
+            // Explicit reaction and decay, then implicit diffusion
+            derivative(state, topology, start, k1, false);
+            offset(probe, start, k1, dt);
+            implicit_diffusion(state, topology, probe, result, k2, dt);
+        }
+    }
+
+    // Cells on the walls of fixed-value species are reservoirs
+    let n = state.species.len();
+    for (cell, &wall) in result.chunks_exact_mut(n).zip(&topology.boundary_weights) {
+        if wall <= 0.0 {
+            continue;
+        }
+        for (value, species) in cell.iter_mut().zip(&state.species) {
+            if let Boundary::Fixed(fixed) = species.boundary {
+                *value = fixed;
+            }
+        }
+    }
+
+    // Keep values in the kernel's valid range (non-negative), capped at 1 only if asked
+    let (lower, upper) = state.reaction_kernel().clamp_range(state.saturate);
+    for (value, &v) in chemicals.values.iter_mut().zip(result.iter()) {
+        *value = v.clamp(lower, upper);
+    }
+}
+
+// --- Motility ---
+
+/// How forces move the sites.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Motility {
+    /// Velocity follows the force each step, scaled down by friction.
+    #[default]
+    Overdamped,
+    /// Forces accelerate a persistent, damped velocity.
+    Inertial,
+}
+
+impl Motility {
+    pub const ALL: [Self; 2] = [Self::Overdamped, Self::Inertial];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Overdamped => "Overdamped",
+            Self::Inertial => "Inertial",
+        }
+    }
+}
+
+/// What sets a cell's mass in [`Motility::Inertial`] mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MassSource {
+    #[default]
+    Uniform,
+    /// Proportional to the cell's area, with the average cell at 1.
+    Area,
+    /// 1 plus the total concentration in the cell.
+    Concentration,
+}
+
+impl MassSource {
+    pub const ALL: [Self; 3] = [Self::Uniform, Self::Area, Self::Concentration];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Uniform => "Uniform",
+            Self::Area => "Cell Area",
+            Self::Concentration => "Concentration",
+        }
+    }
+}
+
+/// Lightest mass a cell can have, so tiny cells are not flung away.
+const MIN_MASS: f32 = 0.1;
+
+/// Distance from an obstacle within which sites are pushed away from it.
+const OBSTACLE_RANGE: f32 = 0.5;
+
+/// Push on a site touching an obstacle, fading out at [`OBSTACLE_RANGE`].
+const OBSTACLE_REPULSION: f32 = 20.0;
+
+/// Moves every site according to the chemical force matrix.
+/// Returns `true` if any site moved (and the topology needs rebuilding).
+pub fn chemical_motility(
+    state: &mut SimState,
+    topology: &Topology,
+    chemicals: &Chemicals,
+    rng: &mut impl Rng,
+    dt: f32,
+) -> bool {
+    if state.domain.is_3d() {
+        return volume_motility(state, topology, chemicals, rng, dt);
+    }
+    let forces = &state.force_matrix;
     let friction = state.friction;
     let jitter = state.emission_jitter;
+    let mean_area = match topology.areas.len() {
+        0 => 1.0,
+        n => topology.areas.iter().sum::<f32>() / n as f32,
+    };
+
+    let tissue = mechanics::tissue_forces(
+        &state.mechanics,
+        &state.sites,
+        &state.domain,
+        state.wraps(),
+        topology,
+        chemicals,
+    );
 
-    // Pre-fetch sites to avoid borrow issues or cloning entire vector inside loop?
     // We have to clone the sites to mutate them safely while reading.
     let mut next_sites = state.sites.clone();
+    let mut next_velocities = vec![Vec2::ZERO; state.sites.len()];
     let mut moved = false;
-    let bound = (DOMAIN_SIZE / 2.0) as f32;
-    let domain_width = DOMAIN_SIZE as f32;
 
     // Iterate all cells
-    for (chem, neighbors, cell_index) in query.iter() {
-        let idx = cell_index.0;
+    for (idx, chem) in chemicals.iter().enumerate() {
         // Safety check
         if idx >= state.sites.len() {
             continue;
         }
 
         let my_pos = state.sites[idx];
-        let my_rgb = Vec3::new(chem.r, chem.g, chem.b);
         let mut total_force = Vec2::ZERO;
 
         // 1. Temperature / Brownian Motion
-        // Driven by Emission channel
-        if jitter > 0.0 {
-            let mut rng = rand::thread_rng();
+        // Driven by the emission species (uniform if there is none, and
+        // skipped if it names a species the cells do not have)
+        let emission = state
+            .emission_species
+            .map_or(Some(1.0), |s| chem.get(s).copied());
+        if jitter > 0.0
+            && let Some(emission) = emission
+        {
             // Random direction * intensity * emission
             let noise = Vec2::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
-            total_force += noise * jitter * chem.e * 50.0;
+            total_force += noise * jitter * emission * 50.0;
         }
 
         // 2. Interactive Forces
-        for &n_idx in &neighbors.indices {
-            if let Some(n_entity) = cell_map.entities.get(n_idx)
-                && let Ok(n_chem) = all_chemicals.get(*n_entity)
-            {
-                let n_pos = state.sites[n_idx];
-                let mut dir = n_pos - my_pos;
-
-                // Torus Wrap Distance Logic
-                if state.wrap_enabled {
-                    if dir.x > bound {
-                        dir.x -= domain_width;
-                    } else if dir.x < -bound {
-                        dir.x += domain_width;
-                    }
-
-                    if dir.y > bound {
-                        dir.y -= domain_width;
-                    } else if dir.y < -bound {
-                        dir.y += domain_width;
-                    }
-                }
+        for &n_idx in topology.neighbors.get(idx).into_iter().flatten() {
+            if let (Some(n_chem), Some(&n_pos)) = (chemicals.get(n_idx), state.sites.get(n_idx)) {
+                // Torus Wrap (or Geodesic) Distance Logic
+                let dir = state.offset(my_pos, n_pos);
 
                 let dist_sq = dir.length_squared();
                 if dist_sq > 0.0001 {
@@ -162,14 +640,9 @@ pub fn chemical_motility_system(
                     let norm_dir = dir / dist;
 
                     // Force Calculation:
-                    // Strength = Self(RGB) * Matrix * Neighbor(RGB)
+                    // Strength = Self dot (Matrix * Neighbor)
                     // This gives a scalar: >0 Attract, <0 Repel
-                    let n_rgb = Vec3::new(n_chem.r, n_chem.g, n_chem.b);
-
-                    // Transpose isn't built-in for Vec3 dot logic easily in this algebra,
-                    // so: Strength = Self dot (Matrix * Neighbor)
-                    let interaction_vec = forces * n_rgb;
-                    let strength = my_rgb.dot(interaction_vec);
+                    let strength = forces.bilinear(chem, n_chem);
 
                     // Normalize by distance?
                     // Usually force falls off with distance (gravity/magnetic)
@@ -182,66 +655,213 @@ pub fn chemical_motility_system(
             }
         }
 
-        // 3. Integrate Position
-        // F = ma, assume unit mass. V += F * dt.
-        // Friction: V *= (1 - friction)
-        // Position += V * dt.
-        // Simplified: Position += TotalForce * (1-friction) * dt
+        // 3. Centroidal (Lloyd) Relaxation
+        // Pulls the site toward the centre of its cell, evening out cell sizes
+        if let Some(&centroid) = topology.centroids.get(idx) {
+            total_force += state.offset(my_pos, centroid) * state.relaxation;
+        }
+
+        // 4. Tissue Pressure (area and perimeter elasticity)
+        if let Some(&force) = tissue.get(idx) {
+            total_force += force;
+        }
+
+        // 5. Obstacle Repulsion
+        for obstacle in &state.obstacles {
+            let away = my_pos - obstacle.nearest(my_pos);
+            let distance = away.length();
+            if distance < OBSTACLE_RANGE {
+                let outward = if obstacle.contains(my_pos) {
+                    -away
+                } else {
+                    away
+                };
+                total_force += outward.normalize_or_zero()
+                    * OBSTACLE_REPULSION
+                    * (1.0 - distance / OBSTACLE_RANGE);
+            }
+        }
 
-        let velocity = total_force * (1.0 - friction);
+        // 6. Integrate Velocity
+        let mut velocity = match state.motility {
+            // No memory: Position += TotalForce * (1-friction) * dt
+            Motility::Overdamped => total_force * (1.0 - friction),
+            // F = ma, then exponential damping: V' = F/m - damping * V
+            Motility::Inertial => {
+                let mass = match state.mass_source {
+                    MassSource::Uniform => 1.0,
+                    MassSource::Area => {
+                        topology.areas.get(idx).copied().unwrap_or(mean_area) / mean_area
+                    }
+                    MassSource::Concentration => 1.0 + chem.iter().sum::<f32>(),
+                }
+                .max(MIN_MASS);
+                let previous = state.velocities.get(idx).copied().unwrap_or_default();
+                (previous + total_force / mass * dt) * (-state.damping * dt).exp()
+            }
+        };
 
+        // 7. Integrate Position
         if velocity.length_squared() > 0.00001 {
-            let mut new_pos = my_pos + velocity * dt;
+            // On the globe, follow the great circle and turn the velocity with it
+            if state.domain.is_sphere() {
+                let (new_pos, carried) = sphere::travel(my_pos, velocity * dt, velocity);
+                next_sites[idx] = new_pos;
+                next_velocities[idx] = carried;
+                moved = true;
+                continue;
+            }
+
+            let free_pos = my_pos + velocity * dt;
 
-            // 4. Boundary Wrapping
-            if state.wrap_enabled {
-                // rem_euclid handles negative wrapping correctly
-                new_pos.x = (new_pos.x + bound).rem_euclid(domain_width) - bound;
-                new_pos.y = (new_pos.y + bound).rem_euclid(domain_width) - bound;
-            } else {
-                // Hard clamp
-                new_pos.x = new_pos.x.clamp(-bound, bound);
-                new_pos.y = new_pos.y.clamp(-bound, bound);
+            // 8. Obstacles stop the move outright, bouncing moving cells off
+            if let Some(obstacle) = state.obstacles.iter().find(|o| o.blocks(my_pos, free_pos)) {
+                let normal = (my_pos - obstacle.nearest(my_pos)).normalize_or_zero();
+                let inward = velocity.dot(normal);
+                if inward < 0.0 {
+                    velocity -= 2.0 * inward * normal;
+                }
+                next_velocities[idx] = velocity;
+                continue;
+            }
+
+            // 9. Boundary Wrapping (or hard clamp at the walls)
+            let new_pos = state.domain.confine(free_pos, state.wraps());
+            if !state.wraps() {
+                // Moving cells bounce off the walls, reflected about the
+                // direction they were pushed back in
+                let normal = (free_pos - new_pos).normalize_or_zero();
+                let outward = velocity.dot(normal);
+                if outward > 0.0 {
+                    velocity -= 2.0 * outward * normal;
+                }
             }
 
             next_sites[idx] = new_pos;
             moved = true;
         }
+        next_velocities[idx] = velocity;
     }
 
+    state.velocities = next_velocities;
     if moved {
         state.sites = next_sites;
         state.rebuild_requested = true;
     }
+    moved
 }
 
-pub fn state_update_system(
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut query: Query<(
-        &mut Chemicals,
-        &NextChemicals,
-        &MeshMaterial3d<StandardMaterial>,
-    )>,
-) {
-    for (mut current, next, mat_handle) in query.iter_mut() {
-        current.r = next.r;
-        current.g = next.g;
-        current.b = next.b;
-        current.e = next.e;
+// The same rules as `chemical_motility` for sites in the 3D domain, which
+// has no obstacles and no wrap
+fn volume_motility(
+    state: &mut SimState,
+    topology: &Topology,
+    chemicals: &Chemicals,
+    rng: &mut impl Rng,
+    dt: f32,
+) -> bool {
+    let forces = &state.force_matrix;
+    let jitter = state.emission_jitter;
+    let half_size = (DOMAIN_SIZE / 2.0) as f32;
+    let mean_volume = match topology.areas.len() {
+        0 => 1.0,
+        n => topology.areas.iter().sum::<f32>() / n as f32,
+    };
+    let positions = state.positions();
+    let tissue = mechanics::tissue_forces_3d(&state.mechanics, &positions, topology, chemicals);
+
+    let mut next_positions = positions.clone();
+    let mut next_velocities = vec![Vec3::ZERO; positions.len()];
+    let mut moved = false;
+
+    for (idx, chem) in chemicals.iter().enumerate() {
+        let Some(&my_pos) = positions.get(idx) else {
+            continue;
+        };
+        let mut total_force = Vec3::ZERO;
 
-        if let Some(mat) = materials.get_mut(&mat_handle.0) {
-            mat.base_color = Color::srgb(current.r, current.g, current.b);
-            mat.emissive = LinearRgba::new(current.r, current.g, current.b, current.e * 2.0);
+        // 1. Temperature / Brownian Motion
+        let emission = state
+            .emission_species
+            .map_or(Some(1.0), |s| chem.get(s).copied());
+        if jitter > 0.0
+            && let Some(emission) = emission
+        {
+            let noise = Vec3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+            total_force += noise * jitter * emission * 50.0;
         }
+
+        // 2. Interactive Forces
+        for &n_idx in topology.neighbors.get(idx).into_iter().flatten() {
+            if let (Some(n_chem), Some(&n_pos)) = (chemicals.get(n_idx), positions.get(n_idx)) {
+                let dir = n_pos - my_pos;
+                let dist_sq = dir.length_squared();
+                if dist_sq > 0.0001 {
+                    let dist = dist_sq.sqrt();
+                    let strength = forces.bilinear(chem, n_chem);
+                    total_force += dir / dist * (strength / dist.max(0.1)) * 10.0;
+                }
+            }
+        }
+
+        // 3. Centroidal (Lloyd) Relaxation
+        if let Some(centroid) = topology.solids.get(idx).and_then(|s| s.centroid()) {
+            total_force += (centroid - my_pos) * state.relaxation;
+        }
+
+        // 4. Tissue Pressure (volume and surface elasticity)
+        if let Some(&force) = tissue.get(idx) {
+            total_force += force;
+        }
+
+        // 5. Integrate Velocity
+        let mut velocity = match state.motility {
+            Motility::Overdamped => total_force * (1.0 - state.friction),
+            Motility::Inertial => {
+                let mass = match state.mass_source {
+                    MassSource::Uniform => 1.0,
+                    MassSource::Area => {
+                        topology.areas.get(idx).copied().unwrap_or(mean_volume) / mean_volume
+                    }
+                    MassSource::Concentration => 1.0 + chem.iter().sum::<f32>(),
+                }
+                .max(MIN_MASS);
+                let flat = state.velocities.get(idx).copied().unwrap_or_default();
+                let climb = state.vertical_velocities.get(idx).copied().unwrap_or(0.0);
+                let previous = Vec3::new(flat.x, climb, flat.y);
+                (previous + total_force / mass * dt) * (-state.damping * dt).exp()
+            }
+        };
+
+        // 6. Integrate Position, bouncing off the walls of the cube
+        if velocity.length_squared() > 0.00001 {
+            let free_pos = my_pos + velocity * dt;
+            let new_pos = free_pos.clamp(Vec3::splat(-half_size), Vec3::splat(half_size));
+            for axis in 0..3 {
+                if new_pos[axis] != free_pos[axis] {
+                    velocity[axis] = -velocity[axis];
+                }
+            }
+            next_positions[idx] = new_pos;
+            moved = true;
+        }
+        next_velocities[idx] = velocity;
     }
-}
 
-pub fn on_click_splash(trigger: On<Pointer<Press>>, mut query: Query<&mut Chemicals>) {
-    if let Ok(mut chem) = query.get_mut(trigger.original_event_target()) {
-        chem.r += 5.0;
-        chem.g += 5.0;
-        chem.b += 5.0;
-        chem.e += 50.0; // Flash
-        info!("Splashed cell");
+    state.velocities = next_velocities
+        .iter()
+        .map(|v| Vec2::new(v.x, v.z))
+        .collect();
+    state.vertical_velocities = next_velocities.iter().map(|v| v.y).collect();
+    if moved {
+        for (i, &position) in next_positions.iter().enumerate() {
+            state.place(i, position);
+        }
+        state.rebuild_requested = true;
     }
+    moved
 }
@@ -0,0 +1,77 @@
+use bevy::prelude::*;
+use voronoi_vivarium::Simulation;
+
+// --- Resources ---
+
+// Controls how simulated time advances relative to wall-clock time.
+// The model always steps with `1 / tick_rate / substeps`, independent of frame rate.
+// Substeps divide each fixed tick, not each frame: a frame runs however many
+// ticks of simulated time have passed.
+#[derive(Resource)]
+pub struct SimClock {
+    pub tick_rate: f64,       // Fixed ticks per simulated second
+    pub substeps: u32,        // Model steps per tick
+    pub speed: f32,           // Simulated seconds per real second
+    pub paused: bool,         // Freezes the model (camera and UI keep running)
+    pub step_requested: bool, // Advance exactly one tick while paused
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        Self {
+            tick_rate: 60.0,
+            substeps: 1,
+            speed: 1.0,
+            paused: false,
+            step_requested: false,
+        }
+    }
+}
+
+// --- Systems ---
+
+// Mirrors the clock settings onto Bevy's virtual and fixed clocks.
+// Speed and pause act on `Time<Virtual>`, which drives `FixedUpdate`.
+pub fn clock_control_system(
+    clock: Res<SimClock>,
+    mut fixed: ResMut<Time<Fixed>>,
+    mut virt: ResMut<Time<Virtual>>,
+) {
+    if !clock.is_changed() {
+        return;
+    }
+
+    fixed.set_timestep_hz(clock.tick_rate);
+    virt.set_relative_speed(clock.speed);
+    if clock.paused {
+        virt.pause();
+    } else {
+        virt.unpause();
+    }
+}
+
+// Runs in FixedUpdate, where `Time` is the fixed clock.
+pub fn fixed_step_system(mut sim: ResMut<Simulation>, clock: Res<SimClock>, time: Res<Time>) {
+    advance(&mut sim, time.delta_secs(), clock.substeps);
+}
+
+// Advances one tick on request, only while paused (running, the fixed
+// clock is already stepping)
+pub fn single_step_system(mut sim: ResMut<Simulation>, mut clock: ResMut<SimClock>) {
+    if !clock.step_requested {
+        return;
+    }
+    clock.step_requested = false;
+    if !clock.paused {
+        return;
+    }
+    advance(&mut sim, (1.0 / clock.tick_rate) as f32, clock.substeps);
+}
+
+fn advance(sim: &mut Simulation, tick: f32, substeps: u32) {
+    let substeps = substeps.max(1);
+    let dt = tick / substeps as f32;
+    for _ in 0..substeps {
+        sim.step(dt);
+    }
+}
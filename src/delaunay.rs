@@ -0,0 +1,789 @@
+use bevy::prelude::*;
+use voronator::delaunator::{self, INVALID_INDEX, Point, next_halfedge, prev_halfedge};
+
+use crate::voronoi::DOMAIN_SIZE;
+
+// Four fixed points this many domain widths out enclose everything, so the
+// hull never changes and its slivers never need repairing
+const FRAME_SIZE: f32 = 4.0;
+
+// Initial ghost margin, in typical cell widths
+const GHOST_CELLS: f32 = 4.0;
+
+// Smallest fraction of a move tried before giving up on a repair
+const MIN_STEP: f32 = 1.0 / 64.0;
+
+// Flips allowed per point before a repair is abandoned for a full rebuild
+const FLIPS_PER_POINT: usize = 8;
+
+/// Edge of a Voronoi cell shared with another site.
+#[derive(Debug, Clone, Copy)]
+pub struct Edge {
+    pub neighbor: usize,
+    pub start: Vec2,
+    pub end: Vec2,
+    /// Distance between the two sites (across the seam when wrapping).
+    pub distance: f32,
+}
+
+/// One Voronoi cell read off the triangulation.
+#[derive(Debug, Clone, Default)]
+pub struct Cell {
+    /// Corners counter-clockwise, starting from the lowest (then leftmost).
+    pub polygon: Vec<Vec2>,
+    pub edges: Vec<Edge>,
+    /// Segments of the domain's outline bounding the cell once it has been
+    /// clipped to it; always empty here.
+    pub walls: Vec<(Vec2, Vec2)>,
+}
+
+/// Delaunay triangulation of the sites and their ghosts that can be
+/// repaired with edge flips after the sites move, instead of being rebuilt.
+///
+/// Sites can carry weights, which make it the regular triangulation whose
+/// dual is the power diagram: circumcircles become the circles orthogonal
+/// to the three corners' circles of radius √weight. Every weight must stay
+/// below the squared distance to the site's nearest neighbour: then every
+/// site keeps a cell, and flips reach the regular triangulation (they
+/// cannot drop a site left with none).
+///
+/// When wrapping, every site has a point that moves continuously (it may
+/// leave the domain) plus periodic copies across the edges it is near.
+/// Four fixed corners far outside keep the hull out of the way, so every
+/// cell is closed (if huge at the edges of a walled domain).
+#[derive(Debug, Clone)]
+pub struct Delaunay {
+    wrap: bool,
+    /// Tile offset of each site's own point from its wrapped position.
+    shifts: Vec<IVec2>,
+    /// Own point of each site when the triangulation was last built.
+    anchors: Vec<Vec2>,
+    /// Sites within this distance of an edge have ghosts beyond it.
+    margin: f32,
+    /// How far a site may drift from its anchor before a rebuild, so the
+    /// ghosts still cover everything within `margin - slack` of the domain.
+    slack: f32,
+    /// Site and tile offset (from the site's own point) of every periodic
+    /// copy, which follow the sites' own points. Sorted by site.
+    images: Vec<(usize, IVec2)>,
+    /// Where the copies of each site start in `images`, plus the end.
+    copies: Vec<usize>,
+    positions: Vec<Vec2>,
+    points: Vec<Point>,
+    /// Weight of every point, parallel to `points`.
+    weights: Vec<f64>,
+    /// Largest weight.
+    reach: f64,
+    triangles: Vec<usize>,
+    halfedges: Vec<usize>,
+    /// A half-edge leaving every point ([`INVALID_INDEX`] for one left out
+    /// of the triangulation), kept up to date through the flips so the
+    /// triangles around a point can be walked without a search.
+    incident: Vec<usize>,
+    /// Circumcentre of every triangle, redone for the ones that change.
+    centres: Vec<Vec2>,
+    /// Sign of the orientation of every valid triangle.
+    orientation: f64,
+}
+
+impl Delaunay {
+    /// Triangulates `sites` from scratch, or `None` if delaunator fails.
+    ///
+    /// When wrapping, only sites within a margin of the edges get ghosts.
+    /// The margin starts at a few typical cell widths and doubles until
+    /// every cell touching the domain is provably the same as with ghosts
+    /// everywhere.
+    pub fn build(sites: &[Vec2], weights: &[f32], wrap: bool) -> Option<Self> {
+        if !wrap {
+            return Self::build_with_margin(sites, weights, false, 0.0);
+        }
+        let domain = DOMAIN_SIZE as f32;
+        let spacing = domain / (sites.len().max(1) as f32).sqrt();
+        let mut margin = GHOST_CELLS * spacing;
+        loop {
+            let delaunay = Self::build_with_margin(sites, weights, wrap, margin)?;
+            if delaunay.covered(0..delaunay.triangles.len() / 3) {
+                return Some(delaunay);
+            }
+            margin *= 2.0;
+        }
+    }
+
+    fn build_with_margin(sites: &[Vec2], weights: &[f32], wrap: bool, margin: f32) -> Option<Self> {
+        let half_width = DOMAIN_SIZE as f32 / 2.0;
+        // Whether a copy of `value` this many domain widths away along one
+        // axis lands within the margin
+        let near = |value: f32, tile: i32| match tile {
+            1 => value < margin - half_width,
+            -1 => value > half_width - margin,
+            _ => true,
+        };
+
+        let tiles: Vec<IVec2> = (-1..=1)
+            .flat_map(|y| (-1..=1).map(move |x| IVec2::new(x, y)))
+            .filter(|&tile| wrap && tile != IVec2::ZERO)
+            .collect();
+        let mut images = Vec::new();
+        let mut copies = vec![0];
+        for (i, site) in sites.iter().enumerate() {
+            images.extend(
+                tiles
+                    .iter()
+                    .filter(|tile| near(site.x, tile.x) && near(site.y, tile.y))
+                    .map(|&tile| (i, tile)),
+            );
+            copies.push(images.len());
+        }
+
+        let mut delaunay = Self {
+            wrap,
+            shifts: vec![IVec2::ZERO; sites.len()],
+            anchors: sites.to_vec(),
+            margin,
+            slack: margin.min(DOMAIN_SIZE as f32) / 4.0,
+            images,
+            copies,
+            positions: Vec::new(),
+            points: Vec::new(),
+            weights: Vec::new(),
+            reach: 0.0,
+            triangles: Vec::new(),
+            halfedges: Vec::new(),
+            incident: Vec::new(),
+            centres: Vec::new(),
+            orientation: 0.0,
+        };
+        let positions = delaunay.targets(sites);
+        for position in positions {
+            delaunay.points.push(Point {
+                x: position.x as f64,
+                y: position.y as f64,
+            });
+            delaunay.positions.push(position);
+        }
+        delaunay.weights = delaunay.point_weights(weights);
+        delaunay.reach = delaunay.weights.iter().copied().fold(0.0, f64::max);
+
+        let triangulation = delaunator::triangulate(&delaunay.points)?;
+        delaunay.triangles = triangulation.triangles;
+        delaunay.halfedges = triangulation.halfedges;
+        delaunay.incident = vec![INVALID_INDEX; delaunay.points.len()];
+        for (e, &p) in delaunay.triangles.iter().enumerate() {
+            delaunay.incident[p] = e;
+        }
+        delaunay.orientation = (0..delaunay.triangles.len() / 3)
+            .map(|t| delaunay.triangle_orientation(t))
+            .find(|o| *o != 0.0)?
+            .signum();
+        // Settles near-cocircular quads the way repairs would, and turns
+        // Delaunay into regular where there are weights
+        let edges = (0..delaunay.halfedges.len())
+            .filter(|&e| delaunay.halfedges[e] != INVALID_INDEX && e < delaunay.halfedges[e])
+            .collect();
+        delaunay.legalize(edges, &mut Vec::new());
+        delaunay.centres = (0..delaunay.triangles.len() / 3)
+            .map(|t| delaunay.circumcentre(t))
+            .collect();
+        Some(delaunay)
+    }
+
+    /// Distance beyond the edges within which sites were ghosted, or `None`
+    /// without wrapping.
+    pub fn margin(&self) -> Option<f32> {
+        self.wrap.then_some(self.margin)
+    }
+
+    /// Positions of the ghosts: periodic copies, plus any site's own point
+    /// that has drifted out of the domain.
+    pub fn ghosts(&self) -> impl Iterator<Item = Vec2> + '_ {
+        self.positions[..self.shifts.len() + self.images.len()]
+            .iter()
+            .copied()
+            .filter(|&p| !in_domain(p))
+    }
+
+    /// Whether this triangulation was built for `count` sites in `wrap` mode.
+    pub fn fits(&self, count: usize, wrap: bool) -> bool {
+        self.wrap == wrap && self.shifts.len() == count
+    }
+
+    /// Moves every point to the new `sites` with their new `weights` and
+    /// flips edges until the triangulation is Delaunay (or regular) again.
+    /// Only the triangles around the points that moved are looked at.
+    /// Returns the sites whose cells may have changed, in ascending order,
+    /// or `None` when the motion was too large to repair, leaving `self`
+    /// unusable.
+    pub fn update(&mut self, sites: &[Vec2], weights: &[f32]) -> Option<Vec<usize>> {
+        if sites.len() != self.shifts.len() {
+            return None;
+        }
+
+        // 1. Follow sites across the torus seam so their points move continuously
+        let half_width = DOMAIN_SIZE as f32 / 2.0;
+        for (i, &site) in sites.iter().enumerate().filter(|_| self.wrap) {
+            let jump = site + tile_offset(self.shifts[i]) - self.positions[i];
+            let shift = &mut self.shifts[i];
+            if jump.x > half_width {
+                shift.x -= 1;
+            } else if jump.x < -half_width {
+                shift.x += 1;
+            }
+            if jump.y > half_width {
+                shift.y -= 1;
+            } else if jump.y < -half_width {
+                shift.y += 1;
+            }
+            if (site + tile_offset(self.shifts[i])).distance(self.anchors[i]) > self.slack {
+                return None;
+            }
+        }
+
+        // 2. The points that move or change weight. Weights cannot turn
+        // triangles over, so they change at once
+        let target = self.targets(sites);
+        let weights = self.point_weights(weights);
+        let moving: Vec<usize> = (0..self.points.len())
+            .filter(|&p| target[p] != self.positions[p] || weights[p] != self.weights[p])
+            .collect();
+        let reach = weights.iter().copied().fold(0.0, f64::max);
+        let reach_changed = reach != self.reach;
+        self.weights = weights;
+        self.reach = reach;
+
+        // 3. Move in steps small enough that no triangle turns inside out
+        // (which flips cannot repair), settling the edges around the moving
+        // points after each one. Every triangle they had or gained is kept
+        let start: Vec<Vec2> = moving.iter().map(|&p| self.positions[p]).collect();
+        let mut touched = Vec::new();
+        let mut done = 0.0;
+        let mut step = 1.0;
+        while done < 1.0 && !moving.is_empty() {
+            let t = f32::min(done + step, 1.0);
+            for (&p, &from) in moving.iter().zip(&start) {
+                let to = target[p];
+                self.place(p, if t < 1.0 { from.lerp(to, t) } else { to });
+            }
+            let around = self.around(&moving);
+            if around
+                .iter()
+                .any(|&t| self.triangle_orientation(t) * self.orientation <= 0.0)
+            {
+                step *= 0.5;
+                if step < MIN_STEP {
+                    return None;
+                }
+                continue;
+            }
+            // Each edge once: from its lower half-edge, unless the other
+            // one's triangle is not around
+            let edges = around
+                .iter()
+                .flat_map(|&t| [3 * t, 3 * t + 1, 3 * t + 2])
+                .filter(|&e| {
+                    let twin = self.halfedges[e];
+                    twin == INVALID_INDEX || e < twin || around.binary_search(&(twin / 3)).is_err()
+                })
+                .collect();
+            touched.extend(around);
+            if !self.legalize(edges, &mut touched) {
+                return None;
+            }
+            done = t;
+        }
+        touched.sort_unstable();
+        touched.dedup();
+        for &t in &touched {
+            self.centres[t] = self.circumcentre(t);
+        }
+
+        // 4. Cells near the edges may now reach past the ghosts. Only the
+        // changed circles need checking, unless the widening did too
+        let covered = if reach_changed {
+            self.covered(0..self.triangles.len() / 3)
+        } else {
+            self.covered(touched.iter().copied())
+        };
+        if !covered {
+            return None;
+        }
+
+        // 5. Every site with a corner of a changed triangle
+        let mut changed: Vec<usize> = touched
+            .iter()
+            .flat_map(|&t| [0, 1, 2].map(|k| self.triangles[3 * t + k]))
+            .chain(moving)
+            .filter_map(|p| self.site_of(p))
+            .collect();
+        changed.sort_unstable();
+        changed.dedup();
+        Some(changed)
+    }
+
+    /// Voronoi cell of every site, or `None` where a site dropped out of the
+    /// triangulation (it coincides with another).
+    pub fn cells(&self) -> Vec<Option<Cell>> {
+        (0..self.shifts.len()).map(|i| self.cell(i)).collect()
+    }
+
+    /// Voronoi cell of site `i`, or `None` if it dropped out of the
+    /// triangulation.
+    pub fn cell(&self, i: usize) -> Option<Cell> {
+        // The point of the site that sits exactly at its wrapped position,
+        // so the result does not depend on how the sites moved to get there
+        let p = self.home(i)?;
+        let mut corners = Vec::new();
+        let mut edges = Vec::new();
+        for e in self.star(p) {
+            corners.push(self.centres[e / 3]);
+
+            // The edge towards the third vertex separates this triangle from the next
+            let twin = self.halfedges[prev_halfedge(e)];
+            let other = self.triangles[prev_halfedge(e)];
+            if twin == INVALID_INDEX {
+                return None;
+            }
+            if let Some(j) = self.site_of(other)
+                && j != i
+            {
+                edges.push(Edge {
+                    neighbor: j,
+                    start: self.centres[e / 3],
+                    end: self.centres[twin / 3],
+                    distance: self.positions[p].distance(self.positions[other]),
+                });
+            }
+        }
+        if corners.is_empty() {
+            return None;
+        }
+
+        // Cocircular sites give triangles sharing a circumcentre
+        corners.dedup();
+        if corners.len() > 1 && corners.first() == corners.last() {
+            corners.pop();
+        }
+
+        // Counter-clockwise from a fixed corner, whatever the walk order
+        if self.orientation < 0.0 {
+            corners.reverse();
+        }
+        let first = (0..corners.len())
+            .min_by(|&a, &b| {
+                let (a, b) = (corners[a], corners[b]);
+                a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x))
+            })
+            .unwrap_or(0);
+        corners.rotate_left(first);
+        Some(Cell {
+            polygon: corners,
+            edges,
+            walls: Vec::new(),
+        })
+    }
+
+    // Point of site `i` at its wrapped position, if it is in the triangulation
+    fn home(&self, i: usize) -> Option<usize> {
+        let count = self.shifts.len();
+        let copy = (self.copies[i]..self.copies[i + 1])
+            .find(|&k| self.shifts[i] + self.images[k].1 == IVec2::ZERO);
+        let p = copy.map_or(i, |k| count + k);
+        (self.incident[p] != INVALID_INDEX).then_some(p)
+    }
+
+    // Half-edges leaving point `p`, one per triangle around it, in turn
+    fn star(&self, p: usize) -> impl Iterator<Item = usize> + '_ {
+        let start = self.incident[p];
+        std::iter::successors((start != INVALID_INDEX).then_some(start), move |&e| {
+            let twin = self.halfedges[prev_halfedge(e)];
+            (twin != INVALID_INDEX && twin != start).then_some(twin)
+        })
+        .take(self.triangles.len())
+    }
+
+    // Position of every point for the given sites
+    fn targets(&self, sites: &[Vec2]) -> Vec<Vec2> {
+        let own = sites
+            .iter()
+            .zip(&self.shifts)
+            .map(|(&site, &shift)| site + tile_offset(shift));
+        let images = self
+            .images
+            .iter()
+            .map(|&(site, tile)| sites[site] + tile_offset(self.shifts[site] + tile));
+        let frame = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .map(|(x, y)| Vec2::new(x, y) * FRAME_SIZE * DOMAIN_SIZE as f32);
+        own.chain(images).chain(frame).collect()
+    }
+
+    fn place(&mut self, p: usize, position: Vec2) {
+        self.points[p] = Point {
+            x: position.x as f64,
+            y: position.y as f64,
+        };
+        self.positions[p] = position;
+    }
+
+    // Weight of every point from the weights of the sites (zero for the
+    // frame corners and for sites without one)
+    fn point_weights(&self, weights: &[f32]) -> Vec<f64> {
+        (0..self.positions.len())
+            .map(|p| {
+                self.site_of(p)
+                    .and_then(|site| weights.get(site))
+                    .map_or(0.0, |&w| f64::from(w))
+            })
+            .collect()
+    }
+
+    // Triangles with a corner among the `moving` points, in ascending
+    // order; all of them once most points move, which is quicker than
+    // walking round each
+    fn around(&self, moving: &[usize]) -> Vec<usize> {
+        if 2 * moving.len() > self.points.len() {
+            return (0..self.triangles.len() / 3).collect();
+        }
+        let mut around: Vec<usize> = moving
+            .iter()
+            .flat_map(|&p| self.star(p))
+            .map(|e| e / 3)
+            .collect();
+        around.sort_unstable();
+        around.dedup();
+        around
+    }
+
+    // Whether each of `triangles` at a point inside the domain has its
+    // circumcircle within the ghosted band. Then no missing ghost could
+    // have been inside it, and the cells match a fully ghosted
+    // triangulation. With weights the circle is widened by the largest,
+    // which bounds how far a missing ghost could reach into it.
+    fn covered(&self, mut triangles: impl Iterator<Item = usize>) -> bool {
+        let domain = DOMAIN_SIZE as f32;
+        if !self.wrap || self.margin >= domain {
+            return true;
+        }
+        let bound = f64::from(domain / 2.0 + self.margin - self.slack);
+        triangles.all(|t| {
+            let corners = [0, 1, 2].map(|k| self.triangles[3 * t + k]);
+            if !corners.iter().any(|&p| in_domain(self.positions[p])) {
+                return true;
+            }
+            let centre = self.centres[t];
+            let a = &self.points[corners[0]];
+            let (x, y) = (f64::from(centre.x), f64::from(centre.y));
+            let power = (x - a.x).powi(2) + (y - a.y).powi(2) - self.weights[corners[0]];
+            let radius = (power + self.reach).max(0.0).sqrt();
+            x.abs() + radius < bound && y.abs() + radius < bound
+        })
+    }
+
+    // Site a point belongs to, or `None` for a frame corner
+    fn site_of(&self, point: usize) -> Option<usize> {
+        let count = self.shifts.len();
+        if point < count {
+            return Some(point);
+        }
+        self.images.get(point - count).map(|&(site, _)| site)
+    }
+
+    fn triangle_orientation(&self, t: usize) -> f64 {
+        let [a, b, c] = [0, 1, 2].map(|k| &self.points[self.triangles[3 * t + k]]);
+        orient(a, b, c)
+    }
+
+    // Lawson flips, starting from the half-edges on `stack`. The triangles
+    // flipped are added to `touched`. Returns `false` if they did not
+    // settle within the budget.
+    fn legalize(&mut self, mut stack: Vec<usize>, touched: &mut Vec<usize>) -> bool {
+        let mut budget = FLIPS_PER_POINT * self.points.len();
+        while let Some(a) = stack.pop() {
+            let b = self.halfedges[a];
+            if self.flip_if_illegal(a) {
+                if budget == 0 {
+                    return false;
+                }
+                budget -= 1;
+                touched.extend([a / 3, b / 3]);
+                // The four outer edges of the flipped pair may now be illegal
+                stack.extend([a, next_halfedge(a), b, next_halfedge(b)]);
+            }
+        }
+        true
+    }
+
+    // Flips half-edge `a` and its twin if the opposite point of the twin's
+    // triangle lies inside the circumcircle of `a`'s triangle. The labels
+    // follow delaunator's own `legalize`.
+    fn flip_if_illegal(&mut self, a: usize) -> bool {
+        let b = self.halfedges[a];
+        if b == INVALID_INDEX {
+            return false;
+        }
+        let (al, ar) = (next_halfedge(a), prev_halfedge(a));
+        let bl = prev_halfedge(b);
+        let [p0, pr, pl, p1] = [ar, a, al, bl].map(|e| self.triangles[e]);
+        let [q0, qr, ql, q1] = [p0, pr, pl, p1].map(|p| &self.points[p]);
+
+        // Flipping a non-convex pair would overlap the new triangles
+        let convex = orient(q1, ql, q0) * self.orientation > 0.0
+            && orient(q0, qr, q1) * self.orientation > 0.0;
+        if !convex || !self.prefers(p0, p1, pr, pl) {
+            return false;
+        }
+
+        self.triangles[a] = p1;
+        self.triangles[b] = p0;
+        let (hbl, har) = (self.halfedges[bl], self.halfedges[ar]);
+        self.link(a, hbl);
+        self.link(b, har);
+        self.link(ar, bl);
+        // The triangles are now p1, pl, p0 (from `a`) and p0, pr, p1 (from `b`)
+        self.incident[p1] = a;
+        self.incident[pl] = al;
+        self.incident[p0] = ar;
+        self.incident[pr] = next_halfedge(b);
+        true
+    }
+
+    // Whether diagonal p0-p1 beats pr-pl in the quad p0, pr, p1, pl: p1 lies
+    // inside the (orthogonal) circumcircle of p0, pr, pl. The four points are always
+    // tested in the same order (tracking the sign of the permutation), so
+    // rounding and exact ties come out the same whichever diagonal the quad
+    // has now. This keeps the result independent of the flips that led to
+    // it, and restored snapshots rebuild exactly the same cells.
+    fn prefers(&self, p0: usize, p1: usize, pr: usize, pl: usize) -> bool {
+        // Clear cases first: no rounding can change their sign
+        let (inside, bound) = self.in_circle([p0, pr, pl, p1]);
+        if inside.abs() > bound {
+            return inside * self.orientation > 0.0;
+        }
+
+        let before = |a: usize, b: usize| {
+            let (a, b) = (&self.points[a], &self.points[b]);
+            a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)).is_lt()
+        };
+        let mut quad = [p0, pr, pl, p1];
+        let mut odd = false;
+        for k in 1..4 {
+            let mut j = k;
+            while j > 0 && before(quad[j], quad[j - 1]) {
+                quad.swap(j, j - 1);
+                odd = !odd;
+                j -= 1;
+            }
+        }
+        let inside = self.in_circle(quad).0 * self.orientation;
+        let inside = if odd { -inside } else { inside };
+        if inside == 0.0 {
+            // Cocircular: keep the diagonal through the first point
+            return quad[0] == p0 || quad[0] == p1;
+        }
+        inside > 0.0
+    }
+
+    fn link(&mut self, a: usize, b: usize) {
+        self.halfedges[a] = b;
+        if b != INVALID_INDEX {
+            self.halfedges[b] = a;
+        }
+    }
+
+    // Positive when `p` lies inside the circumcircle of the counter-clockwise
+    // triangle abc (the sign flips for a clockwise one), with a bound on the
+    // rounding error of the result. With weights, inside the circle
+    // orthogonal to the corners' circles: the lifted points are
+    // `(x, y, x² + y² - weight)`.
+    fn in_circle(&self, [a, b, c, p]: [usize; 4]) -> (f64, f64) {
+        let lift = |q: usize| {
+            let (dx, dy) = (
+                self.points[q].x - self.points[p].x,
+                self.points[q].y - self.points[p].y,
+            );
+            (
+                dx,
+                dy,
+                dx * dx + dy * dy - (self.weights[q] - self.weights[p]),
+            )
+        };
+        let (dx, dy, ap) = lift(a);
+        let (ex, ey, bp) = lift(b);
+        let (fx, fy, cp) = lift(c);
+        let det = dx * (ey * cp - bp * fy) - dy * (ex * cp - bp * fx) + ap * (ex * fy - ey * fx);
+        let permanent = dx.abs() * ((ey * cp).abs() + (bp * fy).abs())
+            + dy.abs() * ((ex * cp).abs() + (bp * fx).abs())
+            + ap.abs() * ((ex * fy).abs() + (ey * fx).abs());
+        (det, 1e-13 * permanent)
+    }
+
+    // Circumcentre (with weights, the power centre: the point of equal
+    // power to all three corners) with the corners in a fixed order, so the
+    // same triangle always gives bit-identical corners however it is stored
+    fn circumcentre(&self, t: usize) -> Vec2 {
+        let mut corners = [0, 1, 2].map(|k| self.triangles[3 * t + k]);
+        corners.sort_by(|&a, &b| {
+            let (a, b) = (&self.points[a], &self.points[b]);
+            a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y))
+        });
+        let [wa, wb, wc] = corners.map(|p| self.weights[p]);
+        let [a, b, c] = corners.map(|p| &self.points[p]);
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        let (ex, ey) = (c.x - a.x, c.y - a.y);
+        let bl = dx * dx + dy * dy + (wa - wb);
+        let cl = ex * ex + ey * ey + (wa - wc);
+        let det = dx * ey - dy * ex;
+        if det == 0.0 {
+            return Vec2::new(a.x as f32, a.y as f32);
+        }
+        let x = (ey * bl - dy * cl) * (0.5 / det);
+        let y = (dx * cl - ex * bl) * (0.5 / det);
+        Vec2::new((a.x + x) as f32, (a.y + y) as f32)
+    }
+}
+
+// Whether `p` lies in the domain square, where only the sites' own points
+// (or, when wrapping, their copies at the wrapped positions) can be
+fn in_domain(p: Vec2) -> bool {
+    p.abs().cmple(Vec2::splat(DOMAIN_SIZE as f32 / 2.0)).all()
+}
+
+fn tile_offset(tile: IVec2) -> Vec2 {
+    tile.as_vec2() * DOMAIN_SIZE as f32
+}
+
+// Twice the signed area of triangle abc (positive when counter-clockwise)
+fn orient(a: &Point, b: &Point, c: &Point) -> f64 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Domain;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    const COUNT: usize = 300;
+
+    fn sites(rng: &mut impl Rng) -> Vec<Vec2> {
+        (0..COUNT)
+            .map(|_| Domain::Square.random_point(rng))
+            .collect()
+    }
+
+    // Random weights, each well below the squared distance to the nearest
+    // other site
+    fn weights(sites: &[Vec2], rng: &mut impl Rng) -> Vec<f32> {
+        sites
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                let nearest = (0..sites.len())
+                    .filter(|&j| j != i)
+                    .map(|j| a.distance_squared(sites[j]))
+                    .fold(f32::INFINITY, f32::min);
+                rng.gen_range(0.0..0.25) * nearest
+            })
+            .collect()
+    }
+
+    fn nudge(sites: &mut [Vec2], count: usize, wrap: bool, rng: &mut impl Rng) {
+        for _ in 0..count {
+            let i = rng.gen_range(0..sites.len());
+            let step = Vec2::new(rng.gen_range(-0.3..0.3), rng.gen_range(-0.3..0.3));
+            sites[i] = Domain::Square.confine(sites[i] + step, wrap);
+        }
+    }
+
+    fn neighbours(delaunay: &Delaunay) -> Vec<Vec<usize>> {
+        delaunay
+            .cells()
+            .into_iter()
+            .map(|cell| {
+                let mut neighbours: Vec<usize> = cell
+                    .map(|cell| cell.edges.iter().map(|e| e.neighbor).collect())
+                    .unwrap_or_default();
+                neighbours.sort_unstable();
+                neighbours.dedup();
+                neighbours
+            })
+            .collect()
+    }
+
+    // Repairs after random moves (of a few sites, then of all of them)
+    // against fresh triangulations of the same sites
+    fn check_repairs(wrap: bool, weighted: bool) {
+        let mut rng = ChaCha8Rng::seed_from_u64(11);
+        let mut sites = sites(&mut rng);
+        let mut weights = if weighted {
+            self::weights(&sites, &mut rng)
+        } else {
+            Vec::new()
+        };
+        let mut delaunay = Delaunay::build(&sites, &weights, wrap).unwrap();
+        let mut repaired = 0;
+        for round in 0..40 {
+            nudge(
+                &mut sites,
+                if round < 20 { 5 } else { COUNT },
+                wrap,
+                &mut rng,
+            );
+            if weighted {
+                weights = self::weights(&sites, &mut rng);
+            }
+            let fresh = Delaunay::build(&sites, &weights, wrap).unwrap();
+            match delaunay.update(&sites, &weights) {
+                Some(_) => {
+                    assert_eq!(neighbours(&delaunay), neighbours(&fresh), "round {round}");
+                    repaired += 1;
+                }
+                None => delaunay = fresh,
+            }
+        }
+        // Most of them were small enough to repair
+        assert!(repaired >= 20, "only {repaired} repairs");
+    }
+
+    #[test]
+    fn repairs_match_rebuilds() {
+        check_repairs(false, false);
+    }
+
+    #[test]
+    fn repairs_match_rebuilds_when_wrapping() {
+        check_repairs(true, false);
+    }
+
+    #[test]
+    fn weighted_repairs_match_rebuilds() {
+        check_repairs(false, true);
+    }
+
+    #[test]
+    fn weighted_repairs_match_rebuilds_when_wrapping() {
+        check_repairs(true, true);
+    }
+
+    #[test]
+    fn moving_one_site_changes_only_its_neighbourhood() {
+        let mut rng = ChaCha8Rng::seed_from_u64(12);
+        let mut sites = sites(&mut rng);
+        let mut delaunay = Delaunay::build(&sites, &[], true).unwrap();
+        let before = neighbours(&delaunay);
+        sites[0] = Domain::Square.confine(sites[0] + Vec2::new(0.2, -0.1), true);
+        let changed = delaunay.update(&sites, &[]).unwrap();
+        let after = neighbours(&delaunay);
+        assert!(changed.contains(&0));
+        assert!(
+            before[0]
+                .iter()
+                .chain(&after[0])
+                .all(|j| changed.contains(j))
+        );
+        assert!(changed.len() < COUNT / 4, "{} cells changed", changed.len());
+        // And nothing else did
+        for i in (0..COUNT).filter(|i| !changed.contains(i)) {
+            assert_eq!(before[i], after[i]);
+        }
+    }
+}
@@ -0,0 +1,427 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{PI, TAU};
+
+use crate::sphere;
+use crate::voronoi::{DOMAIN_SIZE, wrapped_offset};
+
+// Circles are handled as regular polygons with this many sides
+const CIRCLE_SEGMENTS: usize = 96;
+
+// Fraction of their radii by which points confined to a round shape stay
+// clear of its edges, which rounding could otherwise put them past
+const ROUND_MARGIN: f32 = 1e-6;
+
+// Attempts at a random point inside the shape before giving up on it
+const MAX_SAMPLES: usize = 1000;
+
+// How far outside its edge a site pushed out of an obstacle lands, so it is
+// not left touching it
+const OBSTACLE_GAP: f32 = 1e-3;
+
+/// Shape of the region the sites live in. Every flat shape fits the
+/// `DOMAIN_SIZE` square around the origin, and only the square itself can
+/// wrap into a torus; the others always have walls. The sphere has neither,
+/// and the cube adds a third dimension.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum Domain {
+    #[default]
+    Square,
+    /// Width over height; the longer side spans the square.
+    Rectangle {
+        aspect: f32,
+    },
+    Disk,
+    /// Inner radius as a fraction of the outer one.
+    Annulus {
+        inner: f32,
+    },
+    /// Simple polygon in domain coordinates, in either winding. Fewer than
+    /// three vertices fall back to the square.
+    Polygon(Vec<Vec2>),
+    /// Surface of a globe, with sites stored in an equal-area map of it
+    /// (see [`sphere`]).
+    Sphere,
+    /// The square extruded into a `DOMAIN_SIZE` cube, with walls all round;
+    /// sites also have a height (see [`crate::foam`]).
+    Cube,
+}
+
+impl Domain {
+    /// One of each shape, with default parameters.
+    pub fn all() -> [Self; 7] {
+        let half = (DOMAIN_SIZE / 2.0) as f32;
+        [
+            Self::Square,
+            Self::Rectangle { aspect: 2.0 },
+            Self::Disk,
+            Self::Annulus { inner: 0.4 },
+            // An L, to show off a concave corner
+            Self::Polygon(
+                [
+                    (-1.0, -1.0),
+                    (1.0, -1.0),
+                    (1.0, 0.0),
+                    (0.0, 0.0),
+                    (0.0, 1.0),
+                    (-1.0, 1.0),
+                ]
+                .map(|(x, y)| Vec2::new(x, y) * half)
+                .to_vec(),
+            ),
+            Self::Sphere,
+            Self::Cube,
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Square => "Square",
+            Self::Rectangle { .. } => "Rectangle",
+            Self::Disk => "Disk",
+            Self::Annulus { .. } => "Annulus",
+            Self::Polygon(_) => "Polygon",
+            Self::Sphere => "Sphere",
+            Self::Cube => "Cube (3D)",
+        }
+    }
+
+    pub fn can_wrap(&self) -> bool {
+        matches!(self, Self::Square)
+    }
+
+    pub fn is_sphere(&self) -> bool {
+        matches!(self, Self::Sphere)
+    }
+
+    pub fn is_3d(&self) -> bool {
+        matches!(self, Self::Cube)
+    }
+
+    /// Whether sites live on a plane, where obstacles can be drawn.
+    pub fn is_flat(&self) -> bool {
+        !self.is_sphere() && !self.is_3d()
+    }
+
+    /// Whether `p` lies inside the shape.
+    pub fn contains(&self, p: Vec2) -> bool {
+        let (inner, outer) = self.radii();
+        match self {
+            Self::Disk | Self::Annulus { .. } => (inner..=outer).contains(&p.length()),
+            Self::Polygon(vertices) if vertices.len() >= 3 => polygon_contains(vertices, p),
+            _ => p.abs().cmple(self.half_extents()).all(),
+        }
+    }
+
+    /// Brings a position back into the shape: wrapped around the torus when
+    /// `wrap` is set (and the shape can wrap), moved to the nearest point
+    /// inside otherwise.
+    pub fn confine(&self, pos: Vec2, wrap: bool) -> Vec2 {
+        let bound = (DOMAIN_SIZE / 2.0) as f32;
+        if wrap && self.can_wrap() {
+            // rem_euclid handles negative wrapping correctly
+            let domain_width = DOMAIN_SIZE as f32;
+            return (pos + bound).rem_euclid(Vec2::splat(domain_width)) - bound;
+        }
+        if self.is_sphere() {
+            // Longitude goes round, latitude stops at the poles
+            let x = (pos.x + bound).rem_euclid(DOMAIN_SIZE as f32) - bound;
+            return Vec2::new(x, pos.y.clamp(-bound, bound));
+        }
+        if self.contains(pos) {
+            return pos;
+        }
+
+        let (inner, outer) = self.radii();
+        match self {
+            Self::Disk | Self::Annulus { .. } => {
+                let direction = pos.try_normalize().unwrap_or(Vec2::X);
+                let (inner, outer) = (inner * (1.0 + ROUND_MARGIN), outer * (1.0 - ROUND_MARGIN));
+                direction * pos.length().clamp(inner, outer)
+            }
+            Self::Polygon(vertices) if vertices.len() >= 3 => nearest_on_ring(vertices, pos),
+            _ => {
+                let extents = self.half_extents();
+                pos.clamp(-extents, extents)
+            }
+        }
+    }
+
+    /// A uniformly random point inside the shape.
+    pub fn random_point(&self, rng: &mut impl Rng) -> Vec2 {
+        let half_size = DOMAIN_SIZE / 2.0;
+        let mut sample = || {
+            Vec2::new(
+                rng.gen_range(-half_size..half_size) as f32,
+                rng.gen_range(-half_size..half_size) as f32,
+            )
+        };
+        if matches!(self, Self::Square | Self::Sphere | Self::Cube) {
+            return sample();
+        }
+        // Rejection sampling from the enclosing square
+        let mut point = Vec2::ZERO;
+        for _ in 0..MAX_SAMPLES {
+            point = sample();
+            if self.contains(point) {
+                return point;
+            }
+        }
+        self.confine(point, false)
+    }
+
+    /// Shortest offset from `from` to `to`: across the torus seam when
+    /// `wrap` is set, and along the globe (as a tangent at `from`) on the
+    /// sphere.
+    pub fn offset(&self, from: Vec2, to: Vec2, wrap: bool) -> Vec2 {
+        if self.is_sphere() {
+            sphere::offset(from, to)
+        } else {
+            wrapped_offset(from, to, wrap && self.can_wrap())
+        }
+    }
+
+    /// Moves `pos` by `step` (a tangent on the sphere) and confines it.
+    pub fn displace(&self, pos: Vec2, step: Vec2, wrap: bool) -> Vec2 {
+        if self.is_sphere() {
+            self.confine(sphere::travel(pos, step, Vec2::ZERO).0, wrap)
+        } else {
+            self.confine(pos + step, wrap)
+        }
+    }
+
+    /// Boundary as one counter-clockwise ring. The annulus is cut open
+    /// along the positive X axis, so its hole is part of the same ring.
+    pub fn outline(&self) -> Vec<Vec2> {
+        let (inner, outer) = self.radii();
+        let circle = |radius: f32| {
+            (0..CIRCLE_SEGMENTS)
+                .map(move |k| Vec2::from_angle(k as f32 * TAU / CIRCLE_SEGMENTS as f32) * radius)
+        };
+        // The rim's sides touch the circle of radius `outer`, so it takes in
+        // every point the shape contains (as the hole's corners keep out)
+        let rim = outer / (PI / CIRCLE_SEGMENTS as f32).cos();
+        match self {
+            Self::Disk => circle(rim).collect(),
+            Self::Annulus { .. } => {
+                // Around the outside, across the cut, then back round the hole
+                let mut ring: Vec<Vec2> = circle(rim).collect();
+                ring.push(Vec2::X * rim);
+                ring.push(Vec2::X * inner);
+                ring.extend(circle(inner).rev());
+                ring
+            }
+            Self::Polygon(vertices) if vertices.len() >= 3 => {
+                let mut ring = vertices.clone();
+                let twice_area: f32 = ring
+                    .iter()
+                    .zip(ring.iter().cycle().skip(1))
+                    .map(|(p, q)| p.perp_dot(*q))
+                    .sum();
+                if twice_area < 0.0 {
+                    ring.reverse();
+                }
+                ring
+            }
+            _ => {
+                let Vec2 { x, y } = self.half_extents();
+                vec![
+                    Vec2::new(-x, -y),
+                    Vec2::new(x, -y),
+                    Vec2::new(x, y),
+                    Vec2::new(-x, y),
+                ]
+            }
+        }
+    }
+
+    // Half the size of the rectangle (or square)
+    fn half_extents(&self) -> Vec2 {
+        let half = (DOMAIN_SIZE / 2.0) as f32;
+        match self {
+            Self::Rectangle { aspect } if *aspect >= 1.0 => Vec2::new(half, half / aspect),
+            Self::Rectangle { aspect } if *aspect > 0.0 => Vec2::new(half * aspect, half),
+            _ => Vec2::splat(half),
+        }
+    }
+
+    // Radii of the hole and the rim of round shapes, kept within the
+    // polygons of `outline` so confined sites are always inside it
+    fn radii(&self) -> (f32, f32) {
+        let half = (DOMAIN_SIZE / 2.0) as f32;
+        let outer = half * (PI / CIRCLE_SEGMENTS as f32).cos();
+        match self {
+            Self::Annulus { inner } => (inner.clamp(0.0, 0.95) * half, outer),
+            _ => (0.0, outer),
+        }
+    }
+}
+
+/// A solid polygon inside the domain: sites are kept out of it, and
+/// chemicals do not diffuse between cells on opposite sides of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Obstacle {
+    /// Corners in domain coordinates, in either winding. Two corners make
+    /// a thin wall.
+    pub vertices: Vec<Vec2>,
+}
+
+impl Obstacle {
+    pub fn new(vertices: Vec<Vec2>) -> Self {
+        Self { vertices }
+    }
+
+    pub fn contains(&self, p: Vec2) -> bool {
+        self.vertices.len() >= 3 && polygon_contains(&self.vertices, p)
+    }
+
+    /// Moves `p` just outside the nearest point of the outline if it is inside.
+    pub fn push_out(&self, p: Vec2) -> Vec2 {
+        if self.contains(p) {
+            let edge = nearest_on_ring(&self.vertices, p);
+            edge + (edge - p).normalize_or_zero() * OBSTACLE_GAP
+        } else {
+            p
+        }
+    }
+
+    /// Nearest point of the outline to `p`.
+    pub fn nearest(&self, p: Vec2) -> Vec2 {
+        nearest_on_ring(&self.vertices, p)
+    }
+
+    /// Whether the segment from `a` to `b` touches the obstacle.
+    pub fn blocks(&self, a: Vec2, b: Vec2) -> bool {
+        if self.contains(a) || self.contains(b) {
+            return true;
+        }
+        let crosses = |c: Vec2, d: Vec2| {
+            let side = |p: Vec2, q: Vec2, r: Vec2| (q - p).perp_dot(r - p);
+            a.min(b).cmple(c.max(d)).all()
+                && c.min(d).cmple(a.max(b)).all()
+                && side(a, b, c) * side(a, b, d) <= 0.0
+                && side(c, d, a) * side(c, d, b) <= 0.0
+        };
+        self.vertices
+            .iter()
+            .zip(self.vertices.iter().cycle().skip(1))
+            .any(|(&c, &d)| crosses(c, d))
+    }
+}
+
+// Closest point to `p` on the edges of a closed ring
+fn nearest_on_ring(ring: &[Vec2], p: Vec2) -> Vec2 {
+    let mut nearest = p;
+    let mut best = f32::INFINITY;
+    for (k, &a) in ring.iter().enumerate() {
+        let b = ring[(k + 1) % ring.len()];
+        let t = ((p - a).dot(b - a) / (b - a).length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+        let candidate = a + (b - a) * t;
+        if candidate.distance_squared(p) < best {
+            best = candidate.distance_squared(p);
+            nearest = candidate;
+        }
+    }
+    nearest
+}
+
+/// Even-odd test of `p` against a closed ring.
+pub fn polygon_contains(ring: &[Vec2], p: Vec2) -> bool {
+    let mut inside = false;
+    for (k, &a) in ring.iter().enumerate() {
+        let b = ring[(k + 1) % ring.len()];
+        if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn flat_domains() -> impl Iterator<Item = Domain> {
+        Domain::all().into_iter().filter(Domain::is_flat)
+    }
+
+    // Positive when counter-clockwise
+    fn signed_area(ring: &[Vec2]) -> f32 {
+        ring.iter()
+            .zip(ring.iter().cycle().skip(1))
+            .map(|(p, q)| p.perp_dot(*q))
+            .sum::<f32>()
+            / 2.0
+    }
+
+    #[test]
+    fn outlines_run_counter_clockwise_round_the_shape() {
+        let half = (DOMAIN_SIZE / 2.0) as f32;
+        let inner = 0.4 * half;
+        let areas = [
+            4.0 * half * half,
+            2.0 * half * half,
+            PI * half * half,
+            PI * (half * half - inner * inner),
+            3.0 * half * half,
+        ];
+        for (domain, expected) in flat_domains().zip(areas) {
+            let area = signed_area(&domain.outline());
+            assert!(
+                (area - expected).abs() < 0.01 * expected,
+                "{}: area {area}, expected {expected}",
+                domain.label()
+            );
+        }
+
+        // Whichever way the polygon was given
+        let Domain::Polygon(mut vertices) = Domain::all()[4].clone() else {
+            unreachable!()
+        };
+        vertices.reverse();
+        assert!(signed_area(&Domain::Polygon(vertices).outline()) > 0.0);
+    }
+
+    #[test]
+    fn outlines_enclose_what_the_shape_contains() {
+        let mut rng = ChaCha8Rng::seed_from_u64(31);
+        for domain in flat_domains() {
+            let outline = domain.outline();
+            for _ in 0..2000 {
+                let p = Vec2::new(rng.gen_range(-11.0..11.0), rng.gen_range(-11.0..11.0));
+                if domain.contains(p) {
+                    assert!(polygon_contains(&outline, p), "{}: {p}", domain.label());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn confine_brings_points_inside() {
+        let mut rng = ChaCha8Rng::seed_from_u64(32);
+        for domain in flat_domains() {
+            let outline = domain.outline();
+            for _ in 0..2000 {
+                let p = Vec2::new(rng.gen_range(-15.0..15.0), rng.gen_range(-15.0..15.0));
+                let confined = domain.confine(p, false);
+                if domain.contains(p) {
+                    assert_eq!(confined, p);
+                }
+                // Polygon edges may test either way
+                let on_edge = nearest_on_ring(&outline, confined).distance(confined) < 1e-4;
+                assert!(
+                    domain.contains(confined) || on_edge,
+                    "{}: {p} went to {confined}",
+                    domain.label()
+                );
+            }
+        }
+
+        // Across the seams of the torus instead
+        let wrapped = Domain::Square.confine(Vec2::new(10.5, -12.0), true);
+        assert!(wrapped.abs_diff_eq(Vec2::new(-9.5, 8.0), 1e-5));
+    }
+}
@@ -0,0 +1,680 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::kinetics::{Local, Param, ReactionKernel};
+use crate::species::Species;
+
+/// Deepest operand stack a compiled expression may need.
+const MAX_STACK: usize = 32;
+
+// --- Errors ---
+
+/// A problem in one line of the reaction rules.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprError {
+    /// 1-based line number in the source.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+// --- Compiled Form ---
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Func {
+    Abs,
+    Sqrt,
+    Exp,
+    Ln,
+    Sin,
+    Cos,
+    Min,
+    Max,
+}
+
+impl Func {
+    fn parse(name: &str) -> Option<(Self, usize)> {
+        Some(match name {
+            "abs" => (Self::Abs, 1),
+            "sqrt" => (Self::Sqrt, 1),
+            "exp" => (Self::Exp, 1),
+            "ln" => (Self::Ln, 1),
+            "sin" => (Self::Sin, 1),
+            "cos" => (Self::Cos, 1),
+            "min" => (Self::Min, 2),
+            "max" => (Self::Max, 2),
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Const(f32),
+    Species(usize),
+    NeighborAvg(usize),
+    Area,
+    Param(usize),
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Call(Func),
+}
+
+/// One rate equation compiled to a postfix stack program.
+#[derive(Debug, Clone, PartialEq)]
+struct Program {
+    ops: Vec<Op>,
+}
+
+impl Program {
+    fn eval(&self, local: &Local<'_>, params: &[ExprParam]) -> f32 {
+        let mut stack = [0.0f32; MAX_STACK];
+        let mut top = 0;
+        for op in &self.ops {
+            let value = match *op {
+                Op::Const(v) => v,
+                Op::Species(s) => local.cell.get(s).copied().unwrap_or(0.0),
+                Op::NeighborAvg(s) => local.neighbor_mean.get(s).copied().unwrap_or(0.0),
+                Op::Area => local.area,
+                Op::Param(p) => params.get(p).map_or(0.0, |p| p.value),
+                Op::Neg => {
+                    stack[top - 1] = -stack[top - 1];
+                    continue;
+                }
+                Op::Call(func) if matches!(func, Func::Min | Func::Max) => {
+                    top -= 1;
+                    let (a, b) = (stack[top - 1], stack[top]);
+                    stack[top - 1] = if func == Func::Min {
+                        a.min(b)
+                    } else {
+                        a.max(b)
+                    };
+                    continue;
+                }
+                Op::Call(func) => {
+                    let x = stack[top - 1];
+                    stack[top - 1] = match func {
+                        Func::Abs => x.abs(),
+                        Func::Sqrt => x.sqrt(),
+                        Func::Exp => x.exp(),
+                        Func::Ln => x.ln(),
+                        Func::Sin => x.sin(),
+                        Func::Cos => x.cos(),
+                        Func::Min | Func::Max => unreachable!(),
+                    };
+                    continue;
+                }
+                Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow => {
+                    top -= 1;
+                    let (a, b) = (stack[top - 1], stack[top]);
+                    stack[top - 1] = match *op {
+                        Op::Add => a + b,
+                        Op::Sub => a - b,
+                        Op::Mul => a * b,
+                        Op::Div => a / b,
+                        _ => a.powf(b),
+                    };
+                    continue;
+                }
+            };
+            stack[top] = value;
+            top += 1;
+        }
+        if top == 1 { stack[0] } else { 0.0 }
+    }
+}
+
+// --- Parsing ---
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f32),
+    Ident(String),
+    Symbol(char),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                // Allow exponents such as 1e-3
+                let exponent_sign = (c == '-' || c == '+') && text[start..i].ends_with(['e', 'E']);
+                if c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || exponent_sign {
+                    end = i + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            let literal = &text[start..end];
+            let value = literal
+                .parse()
+                .map_err(|_| format!("invalid number '{literal}'"))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_alphanumeric() || c == '_' {
+                    end = i + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Ident(text[start..end].to_string()));
+        } else if "+-*/^(),=".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            return Err(format!("unexpected character '{c}'"));
+        }
+    }
+    Ok(tokens)
+}
+
+// Recursive-descent parser emitting postfix ops directly
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    species: &'a [Species],
+    params: &'a mut Vec<String>,
+    ops: Vec<Op>,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(format!("expected '{symbol}'"))
+        }
+    }
+
+    // Tracks stack depth as operands are pushed (+1) and operators pop (-1)
+    fn emit(&mut self, op: Op, push: isize) {
+        self.ops.push(op);
+        self.depth = self.depth.saturating_add_signed(push);
+        self.max_depth = self.max_depth.max(self.depth);
+    }
+
+    fn species_index(&self, name: &str) -> Option<usize> {
+        self.species.iter().position(|s| s.name == name)
+    }
+
+    fn expr(&mut self) -> Result<(), String> {
+        self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                Op::Add
+            } else if self.eat('-') {
+                Op::Sub
+            } else {
+                return Ok(());
+            };
+            self.term()?;
+            self.emit(op, -1);
+        }
+    }
+
+    fn term(&mut self) -> Result<(), String> {
+        self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                Op::Mul
+            } else if self.eat('/') {
+                Op::Div
+            } else {
+                return Ok(());
+            };
+            self.unary()?;
+            self.emit(op, -1);
+        }
+    }
+
+    fn unary(&mut self) -> Result<(), String> {
+        if self.eat('-') {
+            self.unary()?;
+            self.emit(Op::Neg, 0);
+            Ok(())
+        } else {
+            self.power()
+        }
+    }
+
+    // Right-associative, binding tighter than unary minus on its left
+    fn power(&mut self) -> Result<(), String> {
+        self.atom()?;
+        if self.eat('^') {
+            self.unary()?;
+            self.emit(Op::Pow, -1);
+        }
+        Ok(())
+    }
+
+    fn atom(&mut self) -> Result<(), String> {
+        let token = self.peek().cloned().ok_or("unexpected end of expression")?;
+        self.pos += 1;
+        match token {
+            Token::Number(v) => self.emit(Op::Const(v), 1),
+            Token::Symbol('(') => {
+                self.expr()?;
+                self.expect(')')?;
+            }
+            Token::Symbol(c) => return Err(format!("unexpected '{c}'")),
+            Token::Ident(name) if self.eat('(') => self.call(&name)?,
+            Token::Ident(name) => {
+                if let Some(s) = self.species_index(&name) {
+                    self.emit(Op::Species(s), 1);
+                } else if name == "area" {
+                    self.emit(Op::Area, 1);
+                } else {
+                    // Anything else is a user parameter
+                    let index = match self.params.iter().position(|p| *p == name) {
+                        Some(i) => i,
+                        None => {
+                            self.params.push(name);
+                            self.params.len() - 1
+                        }
+                    };
+                    self.emit(Op::Param(index), 1);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Parses the arguments of `name(...)`; the '(' is already consumed
+    fn call(&mut self, name: &str) -> Result<(), String> {
+        if name == "avg" {
+            let species = match self.peek() {
+                Some(Token::Ident(s)) => self.species_index(s),
+                _ => None,
+            }
+            .ok_or("avg() takes a species name")?;
+            self.pos += 1;
+            self.expect(')')?;
+            self.emit(Op::NeighborAvg(species), 1);
+            return Ok(());
+        }
+
+        let (func, arity) =
+            Func::parse(name).ok_or_else(|| format!("unknown function '{name}'"))?;
+        for i in 0..arity {
+            if i > 0 {
+                self.expect(',')?;
+            }
+            self.expr()?;
+        }
+        self.expect(')')?;
+        self.emit(Op::Call(func), 1 - arity as isize);
+        Ok(())
+    }
+}
+
+// --- Kernel ---
+
+/// A named constant referenced by the rules, tuned from the UI.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExprParam {
+    pub name: String,
+    pub value: f32,
+}
+
+/// Reaction rules typed as text, one `dX = ...` line per species `X`.
+///
+/// Expressions may use species names, `avg(X)` (mean of `X` over the
+/// neighbours), `area` (cell area), numbers, `+ - * / ^`, the functions
+/// `abs sqrt exp ln sin cos min max`, and any other name as a parameter.
+/// Lines that are empty or start with `#` are ignored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExpressionKernel {
+    pub source: String,
+    pub params: Vec<ExprParam>,
+    // Rebuilt by `compile`, indexed by species
+    #[serde(skip)]
+    programs: Vec<Option<Program>>,
+    #[serde(skip)]
+    errors: Vec<ExprError>,
+}
+
+impl PartialEq for ExpressionKernel {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source && self.params == other.params
+    }
+}
+
+impl Default for ExpressionKernel {
+    fn default() -> Self {
+        Self::new(
+            "dR = 0.4*G - R*B^2 + feed*(1 - R)\n\
+             dG = 0.4*B - G*R^2 + feed*(1 - G)\n\
+             dB = 0.4*R - B*G^2 + feed*(1 - B)\n\
+             dE = 0.5*(avg(E) - E)",
+            &[("feed", 0.05)],
+        )
+    }
+}
+
+impl ExpressionKernel {
+    /// Rules from `source`, with starting values for its parameters.
+    /// Call [`compile`](Self::compile) before use.
+    pub fn new(source: impl Into<String>, params: &[(&str, f32)]) -> Self {
+        Self {
+            source: source.into(),
+            params: params
+                .iter()
+                .map(|&(name, value)| ExprParam {
+                    name: name.into(),
+                    value,
+                })
+                .collect(),
+            programs: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Errors from the last [`compile`](Self::compile); lines with errors contribute nothing.
+    pub fn errors(&self) -> &[ExprError] {
+        &self.errors
+    }
+
+    /// Parses `source` against the current species names. Parameters keep
+    /// their values by name; new ones start at 0 and unused ones are dropped.
+    pub fn compile(&mut self, species: &[Species]) {
+        self.programs = vec![None; species.len()];
+        self.errors.clear();
+        let mut names = Vec::new();
+
+        for (i, line) in self.source.lines().enumerate() {
+            let line_number = i + 1;
+            let text = line.trim();
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+            match compile_line(text, species, &mut names) {
+                Ok((target, program)) => {
+                    if self.programs[target].is_some() {
+                        self.errors.push(ExprError {
+                            line: line_number,
+                            message: format!("d{} is defined twice", species[target].name),
+                        });
+                    }
+                    self.programs[target] = Some(program);
+                }
+                Err(message) => self.errors.push(ExprError {
+                    line: line_number,
+                    message,
+                }),
+            }
+        }
+
+        let old = std::mem::take(&mut self.params);
+        self.params = names
+            .into_iter()
+            .map(|name| {
+                let value = old.iter().find(|p| p.name == name).map_or(0.0, |p| p.value);
+                ExprParam { name, value }
+            })
+            .collect();
+    }
+}
+
+// Compiles `dX = expr` into the index of X and its program
+fn compile_line(
+    text: &str,
+    species: &[Species],
+    params: &mut Vec<String>,
+) -> Result<(usize, Program), String> {
+    let tokens = tokenize(text)?;
+    let target = match tokens.first() {
+        Some(Token::Ident(lhs)) => lhs
+            .strip_prefix('d')
+            .and_then(|name| species.iter().position(|s| s.name == name))
+            .ok_or_else(|| format!("'{lhs}' is not d<species>"))?,
+        _ => return Err("expected 'd<species> = ...'".into()),
+    };
+    if tokens.get(1) != Some(&Token::Symbol('=')) {
+        return Err("expected '=' after the species".into());
+    }
+
+    let mut parser = Parser {
+        tokens: &tokens[2..],
+        pos: 0,
+        species,
+        params,
+        ops: Vec::new(),
+        depth: 0,
+        max_depth: 0,
+    };
+    parser.expr()?;
+    if parser.pos < parser.tokens.len() {
+        return Err("unexpected text after the expression".into());
+    }
+    if parser.max_depth > MAX_STACK {
+        return Err("expression is nested too deeply".into());
+    }
+    Ok((target, Program { ops: parser.ops }))
+}
+
+impl ReactionKernel for ExpressionKernel {
+    fn equation(&self) -> &'static str {
+        "dX = f(species, avg(X), area, params)"
+    }
+
+    fn react(&self, cell: &[f32], rate: &mut [f32]) {
+        let local = Local {
+            cell,
+            neighbor_mean: cell,
+            area: 1.0,
+        };
+        self.react_local(&local, rate);
+    }
+
+    fn react_local(&self, local: &Local<'_>, rate: &mut [f32]) {
+        for (s, r) in rate.iter_mut().enumerate() {
+            *r = match self.programs.get(s) {
+                Some(Some(program)) => program.eval(local, &self.params),
+                _ => 0.0,
+            };
+        }
+    }
+
+    fn needs_neighbors(&self) -> bool {
+        true
+    }
+
+    fn stiffness(&self) -> f32 {
+        // Largest finite-difference Jacobian row sum at a few uniform probes
+        let n = self.programs.len();
+        let h = 1e-3;
+        let mut rate = vec![0.0; n];
+        let mut shifted = vec![0.0; n];
+        let mut worst: f32 = 0.0;
+        for level in [0.0, 0.5, 1.0] {
+            let cell = vec![level; n];
+            self.react(&cell, &mut rate);
+            let mut row_sums = vec![0.0f32; n];
+            for j in 0..n {
+                let mut probe = cell.clone();
+                probe[j] += h;
+                self.react(&probe, &mut shifted);
+                for (sum, (a, b)) in row_sums.iter_mut().zip(shifted.iter().zip(&rate)) {
+                    *sum += ((a - b) / h).abs();
+                }
+            }
+            worst = row_sums
+                .into_iter()
+                .filter(|s| s.is_finite())
+                .fold(worst, f32::max);
+        }
+        worst
+    }
+
+    fn params(&mut self) -> Vec<Param<'_>> {
+        self.params
+            .iter_mut()
+            .map(|p| Param {
+                name: p.name.as_str(),
+                value: &mut p.value,
+                range: -1.0..=1.0,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn species() -> Vec<Species> {
+        vec![
+            Species::new("U", [1.0, 0.0, 0.0], 0.1, 0.0),
+            Species::new("V", [0.0, 1.0, 0.0], 0.1, 0.0),
+        ]
+    }
+
+    // Compiles `dU = <expr>` and evaluates it at U = 2, V = 3, mean U = 5,
+    // mean V = 7, area 0.5
+    fn eval(expr: &str, params: &[(&str, f32)]) -> Result<f32, String> {
+        let mut kernel = ExpressionKernel::new(format!("dU = {expr}"), params);
+        kernel.compile(&species());
+        if let Some(error) = kernel.errors().first() {
+            return Err(error.message.clone());
+        }
+        let local = Local {
+            cell: &[2.0, 3.0],
+            neighbor_mean: &[5.0, 7.0],
+            area: 0.5,
+        };
+        let mut rate = [0.0; 2];
+        kernel.react_local(&local, &mut rate);
+        Ok(rate[0])
+    }
+
+    fn error(source: &str) -> String {
+        let mut kernel = ExpressionKernel::new(source, &[]);
+        kernel.compile(&species());
+        kernel.errors().first().expect("an error").to_string()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3", &[]), Ok(7.0));
+        assert_eq!(eval("(1 + 2) * 3", &[]), Ok(9.0));
+        assert_eq!(eval("8 / 4 / 2", &[]), Ok(1.0));
+        assert_eq!(eval("10 - 4 - 3", &[]), Ok(3.0));
+        assert_eq!(eval("2 * 3 ^ 2", &[]), Ok(18.0));
+        assert_eq!(eval("2 ^ 3 ^ 2", &[]), Ok(512.0));
+        assert_eq!(eval("U * V + 1e-1", &[]), Ok(6.1));
+    }
+
+    #[test]
+    fn unary_minus_binds_looser_than_power() {
+        assert_eq!(eval("-2 ^ 2", &[]), Ok(-4.0));
+        assert_eq!(eval("(-2) ^ 2", &[]), Ok(4.0));
+        assert_eq!(eval("2 ^ -1", &[]), Ok(0.5));
+        assert_eq!(eval("--U", &[]), Ok(2.0));
+        assert_eq!(eval("3 - -U", &[]), Ok(5.0));
+    }
+
+    #[test]
+    fn functions_and_locals() {
+        assert_eq!(eval("avg(U)", &[]), Ok(5.0));
+        assert_eq!(eval("avg(V) - V", &[]), Ok(4.0));
+        assert_eq!(eval("area", &[]), Ok(0.5));
+        assert_eq!(eval("min(U, V) + max(U, V)", &[]), Ok(5.0));
+        assert_eq!(eval("sqrt(abs(-16))", &[]), Ok(4.0));
+        assert_eq!(eval("exp(0) + ln(1) + cos(0) + sin(0)", &[]), Ok(2.0));
+        assert_eq!(eval("max(min(U, 1), -V)", &[]), Ok(1.0));
+    }
+
+    #[test]
+    fn parameters_are_discovered_in_order() {
+        let mut kernel = ExpressionKernel::new(
+            "dU = k * U - feed\ndV = feed + k + rate",
+            &[("rate", 0.5), ("unused", 1.0)],
+        );
+        kernel.compile(&species());
+        assert!(kernel.errors().is_empty());
+        let names: Vec<_> = kernel.params.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["k", "feed", "rate"]);
+        // Known ones keep their values, new ones start at 0
+        let values: Vec<_> = kernel.params.iter().map(|p| p.value).collect();
+        assert_eq!(values, [0.0, 0.0, 0.5]);
+
+        assert_eq!(eval("k * U + feed", &[("k", 3.0), ("feed", 1.0)]), Ok(7.0));
+    }
+
+    #[test]
+    fn stack_depth_is_limited() {
+        let nested = |depth: usize| format!("{}1{}", "(1 + ".repeat(depth), ")".repeat(depth));
+        assert_eq!(eval(&nested(MAX_STACK - 1), &[]), Ok(MAX_STACK as f32));
+        assert_eq!(
+            eval(&nested(MAX_STACK), &[]),
+            Err("expression is nested too deeply".into())
+        );
+    }
+
+    #[test]
+    fn malformed_input_is_reported() {
+        assert_eq!(error("dU = 1 +"), "line 1: unexpected end of expression");
+        assert_eq!(error("dU = (U"), "line 1: expected ')'");
+        assert_eq!(error("dU = U $ V"), "line 1: unexpected character '$'");
+        assert_eq!(
+            error("dU = U V"),
+            "line 1: unexpected text after the expression"
+        );
+        assert_eq!(error("dU = 1..2"), "line 1: invalid number '1..2'");
+        assert_eq!(error("dU = foo(U)"), "line 1: unknown function 'foo'");
+        assert_eq!(error("dU = avg(2)"), "line 1: avg() takes a species name");
+        assert_eq!(error("dU = min(U)"), "line 1: expected ','");
+        assert_eq!(error("dW = 1"), "line 1: 'dW' is not d<species>");
+        assert_eq!(error("= 1"), "line 1: expected 'd<species> = ...'");
+        assert_eq!(error("dU 1"), "line 1: expected '=' after the species");
+        assert_eq!(
+            error("# rules\n\ndU = 1\ndU = 2"),
+            "line 4: dU is defined twice"
+        );
+    }
+
+    #[test]
+    fn lines_with_errors_contribute_nothing() {
+        let mut kernel = ExpressionKernel::new("dU = 1 +\ndV = U", &[]);
+        kernel.compile(&species());
+        assert_eq!(kernel.errors().len(), 1);
+        let mut rate = [9.0; 2];
+        kernel.react(&[2.0, 3.0], &mut rate);
+        assert_eq!(rate, [0.0, 2.0]);
+    }
+}
@@ -0,0 +1,437 @@
+//! Geometry of the 3D mode.
+//!
+//! Sites in the cube keep their `Vec2` in `SimState::sites` plus a height
+//! in `SimState::heights`; here they are world positions `(x, height, y)`,
+//! so the flat domain is the `y = 0` slice. Cells are convex polyhedra: the
+//! cube cut down by the bisector planes to every Delaunay neighbour (power
+//! planes and regular neighbours when the sites are weighted).
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+// Marks a missing neighbour of a tetrahedron (a face on the hull)
+const NONE: usize = usize::MAX;
+
+// The enclosing tetrahedron is this many times the size of the points' bounds
+const SUPER_SIZE: f64 = 100.0;
+
+// Corners this close (in domain units) on a cut are taken as one
+const WELD_TOLERANCE: f32 = 1e-5;
+
+#[derive(Debug, Clone)]
+struct Tetrahedron {
+    vertices: [usize; 4],
+    /// Tetrahedron across the face opposite each vertex.
+    neighbors: [usize; 4],
+    /// Centre and squared radius of the circumsphere (with weights, the
+    /// sphere orthogonal to the corners' spheres of radius √weight).
+    centre: DVec3,
+    radius_squared: f64,
+    alive: bool,
+}
+
+impl Tetrahedron {
+    fn new(vertices: [usize; 4], points: &[DVec3], weights: &[f64]) -> Self {
+        let [a, b, c, d] = vertices.map(|v| points[v]);
+        let [wa, wb, wc, wd] = vertices.map(|v| weights[v]);
+        let (b, c, d) = (b - a, c - a, d - a);
+        let denominator = 2.0 * b.dot(c.cross(d));
+        let offset = (c.cross(d) * (b.length_squared() + wa - wb)
+            + d.cross(b) * (c.length_squared() + wa - wc)
+            + b.cross(c) * (d.length_squared() + wa - wd))
+            / denominator;
+        Self {
+            vertices,
+            neighbors: [NONE; 4],
+            centre: a + offset,
+            // A flat tetrahedron has an infinite sphere, which holds everything
+            radius_squared: if offset.is_finite() {
+                offset.length_squared() - wa
+            } else {
+                f64::INFINITY
+            },
+            alive: true,
+        }
+    }
+
+    // Whether the point `p` of weight `w` has less power than the sphere
+    fn encloses(&self, p: DVec3, w: f64) -> bool {
+        !self.centre.is_finite() || p.distance_squared(self.centre) - w < self.radius_squared
+    }
+}
+
+// Six times the signed volume of the tetrahedron, positive when `d` is on
+// the side of `abc` that makes it counter-clockwise
+fn orient(a: DVec3, b: DVec3, c: DVec3, d: DVec3) -> f64 {
+    (b - a).cross(c - a).dot(d - a)
+}
+
+/// Delaunay tetrahedralisation of `points` (Bowyer–Watson, inserting in
+/// spatial order and walking to each new point), with every tetrahedron
+/// positively oriented. Empty if the points are too few or all flat.
+///
+/// Nonzero `weights` (parallel to `points`, or empty) give the regular
+/// tetrahedralisation instead, the dual of the power diagram. Every weight
+/// must stay below the squared distance to the nearest other point, so
+/// that no point is left without a cell.
+pub fn tetrahedralize(points: &[Vec3], weights: &[f32]) -> Vec<[usize; 4]> {
+    let count = points.len();
+    if count < 4 {
+        return Vec::new();
+    }
+
+    // 1. Points, plus an enclosing tetrahedron far around them
+    let mut all: Vec<DVec3> = points.iter().map(|p| p.as_dvec3()).collect();
+    let (low, high) = all
+        .iter()
+        .fold((DVec3::INFINITY, DVec3::NEG_INFINITY), |(low, high), &p| {
+            (low.min(p), high.max(p))
+        });
+    let centre = (low + high) / 2.0;
+    let size = (high - low).max_element().max(1.0) * SUPER_SIZE;
+    all.extend(
+        [
+            DVec3::new(1.0, 1.0, 1.0),
+            DVec3::new(1.0, -1.0, -1.0),
+            DVec3::new(-1.0, 1.0, -1.0),
+            DVec3::new(-1.0, -1.0, 1.0),
+        ]
+        .map(|corner| centre + corner * size),
+    );
+    let weights: Vec<f64> = (0..all.len())
+        .map(|i| weights.get(i).map_or(0.0, |&w| f64::from(w)))
+        .collect();
+    let mut first = [count, count + 1, count + 2, count + 3];
+    if orient(all[first[0]], all[first[1]], all[first[2]], all[first[3]]) < 0.0 {
+        first.swap(0, 1);
+    }
+    let mut tetrahedra = vec![Tetrahedron::new(first, &all, &weights)];
+
+    // 2. Insert in Morton order, so each walk starts near its goal
+    let morton = |p: DVec3| {
+        let cell = ((p - low) / (high - low).max(DVec3::splat(f64::EPSILON)) * 1023.0)
+            .as_uvec3()
+            .to_array();
+        (0..10).fold(0u32, |code, bit| {
+            cell.iter()
+                .fold(code, |code, &c| (code << 1) | ((c >> (9 - bit)) & 1))
+        })
+    };
+    let mut order: Vec<usize> = (0..count).collect();
+    order.sort_by_key(|&i| morton(all[i]));
+
+    let mut last = 0;
+    for i in order {
+        let (p, w) = (all[i], weights[i]);
+        let Some(start) = locate(&tetrahedra, &all, last, p, w) else {
+            continue;
+        };
+
+        // 3. Cavity: every tetrahedron around the point whose sphere holds
+        // it, killed as it is found
+        let mut cavity = vec![start];
+        tetrahedra[start].alive = false;
+        let mut k = 0;
+        while k < cavity.len() {
+            for neighbor in tetrahedra[cavity[k]].neighbors {
+                if neighbor != NONE
+                    && tetrahedra[neighbor].alive
+                    && tetrahedra[neighbor].encloses(p, w)
+                {
+                    tetrahedra[neighbor].alive = false;
+                    cavity.push(neighbor);
+                }
+            }
+            k += 1;
+        }
+
+        // 4. Join the point to every face of the cavity's boundary, then
+        // link the new tetrahedra to each other across their shared edges
+        let mut open: Vec<((usize, usize), (usize, usize))> = Vec::new();
+        for &t in &cavity {
+            for face in 0..4 {
+                let outside = tetrahedra[t].neighbors[face];
+                if outside != NONE && !tetrahedra[outside].alive {
+                    continue;
+                }
+                let mut vertices = tetrahedra[t].vertices;
+                vertices[face] = i;
+                let new = tetrahedra.len();
+                let mut tetrahedron = Tetrahedron::new(vertices, &all, &weights);
+                tetrahedron.neighbors[face] = outside;
+                tetrahedra.push(tetrahedron);
+                if outside != NONE {
+                    let back = &mut tetrahedra[outside].neighbors;
+                    if let Some(slot) = back.iter_mut().find(|n| **n == t) {
+                        *slot = new;
+                    }
+                }
+                for side in (0..4).filter(|&side| side != face) {
+                    let mut edge = (0..4)
+                        .filter(|&v| v != face && v != side)
+                        .map(|v| vertices[v]);
+                    let (a, b) = (edge.next().unwrap_or(i), edge.next().unwrap_or(i));
+                    let key = (a.min(b), a.max(b));
+                    if let Some(k) = open.iter().position(|&(edge, _)| edge == key) {
+                        let (_, (other, other_side)) = open.swap_remove(k);
+                        tetrahedra[new].neighbors[side] = other;
+                        tetrahedra[other].neighbors[other_side] = new;
+                    } else {
+                        open.push((key, (new, side)));
+                    }
+                }
+                last = new;
+            }
+        }
+    }
+
+    // 5. Drop everything touching the enclosing tetrahedron, and the flat
+    // ones coplanar points leave
+    tetrahedra
+        .into_iter()
+        .filter(|t| t.alive && t.vertices.iter().all(|&v| v < count))
+        .filter(|t| {
+            let [a, b, c, d] = t.vertices.map(|v| all[v]);
+            orient(a, b, c, d) != 0.0
+        })
+        .map(|t| t.vertices)
+        .collect()
+}
+
+// A live tetrahedron whose sphere holds `p`: the one containing it, found by
+// walking from `start`, or any at all if the walk loses its way
+fn locate(
+    tetrahedra: &[Tetrahedron],
+    points: &[DVec3],
+    start: usize,
+    p: DVec3,
+    w: f64,
+) -> Option<usize> {
+    let mut current = start;
+    for _ in 0..tetrahedra.len() {
+        let tetrahedron = &tetrahedra[current];
+        if !tetrahedron.alive {
+            break;
+        }
+        let corners = tetrahedron.vertices.map(|v| points[v]);
+        let across = (0..4).find(|&face| {
+            let mut probe = corners;
+            probe[face] = p;
+            orient(probe[0], probe[1], probe[2], probe[3]) < 0.0
+        });
+        match across.map(|face| tetrahedron.neighbors[face]) {
+            None => return Some(current),
+            Some(NONE) => break,
+            Some(next) => current = next,
+        }
+    }
+    tetrahedra.iter().position(|t| t.alive && t.encloses(p, w))
+}
+
+/// Face of a [`Solid`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Face {
+    /// Site on the other side, or `None` for a wall of the domain.
+    pub neighbor: Option<usize>,
+    /// Corners, counter-clockwise seen from outside.
+    pub corners: Vec<Vec3>,
+}
+
+impl Face {
+    pub fn area(&self) -> f32 {
+        self.weighted_normal().length() / 2.0
+    }
+
+    /// Outward unit normal.
+    pub fn normal(&self) -> Vec3 {
+        self.weighted_normal().normalize_or_zero()
+    }
+
+    // Twice the area times the outward unit normal
+    fn weighted_normal(&self) -> Vec3 {
+        let Some(&a) = self.corners.first() else {
+            return Vec3::ZERO;
+        };
+        self.corners
+            .windows(2)
+            .skip(1)
+            .map(|pair| (pair[0] - a).cross(pair[1] - a))
+            .sum()
+    }
+
+    /// Distance from `p` to the plane of the face.
+    pub fn distance(&self, p: Vec3) -> f32 {
+        let normal = self.normal();
+        self.corners
+            .first()
+            .map_or(0.0, |&a| (p - a).dot(normal).abs())
+    }
+}
+
+/// Convex polyhedron, such as the Voronoi cell of a site in 3D.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Solid {
+    pub faces: Vec<Face>,
+}
+
+impl Solid {
+    /// Box centred on the origin, every face a wall.
+    pub fn cuboid(half: Vec3) -> Self {
+        let corner = |x: f32, y: f32, z: f32| Vec3::new(x, y, z) * half;
+        let faces = [
+            [(1., -1., -1.), (1., 1., -1.), (1., 1., 1.), (1., -1., 1.)],
+            [
+                (-1., -1., -1.),
+                (-1., -1., 1.),
+                (-1., 1., 1.),
+                (-1., 1., -1.),
+            ],
+            [(-1., 1., -1.), (-1., 1., 1.), (1., 1., 1.), (1., 1., -1.)],
+            [
+                (-1., -1., -1.),
+                (1., -1., -1.),
+                (1., -1., 1.),
+                (-1., -1., 1.),
+            ],
+            [(-1., -1., 1.), (1., -1., 1.), (1., 1., 1.), (-1., 1., 1.)],
+            [
+                (-1., -1., -1.),
+                (-1., 1., -1.),
+                (1., 1., -1.),
+                (1., -1., -1.),
+            ],
+        ];
+        Self {
+            faces: faces
+                .into_iter()
+                .map(|face| Face {
+                    neighbor: None,
+                    corners: face.into_iter().map(|(x, y, z)| corner(x, y, z)).collect(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Cuts away everything in front of the plane through `point` facing
+    /// `normal`, closing the cut with a face towards `neighbor`.
+    pub fn clip(&mut self, point: Vec3, normal: Vec3, neighbor: Option<usize>) {
+        let side = |p: Vec3| (p - point).dot(normal);
+        if self
+            .faces
+            .iter()
+            .flat_map(|f| &f.corners)
+            .all(|&p| side(p) <= 0.0)
+        {
+            return;
+        }
+
+        // 1. Clip every face, keeping the points where its edges cross
+        let mut cut: Vec<Vec3> = Vec::new();
+        for face in &mut self.faces {
+            if face.corners.iter().all(|&p| side(p) <= 0.0) {
+                continue;
+            }
+            let mut kept = Vec::with_capacity(face.corners.len() + 1);
+            for (k, &a) in face.corners.iter().enumerate() {
+                let b = face.corners[(k + 1) % face.corners.len()];
+                let (da, db) = (side(a), side(b));
+                if da <= 0.0 {
+                    kept.push(a);
+                }
+                if (da <= 0.0) != (db <= 0.0) {
+                    let crossing = a + (b - a) * (da / (da - db));
+                    kept.push(crossing);
+                    cut.push(crossing);
+                }
+            }
+            face.corners = kept;
+        }
+        self.faces.retain(|f| f.corners.len() >= 3);
+
+        // 2. Close the hole with the crossings in turn around the normal
+        let mut corners: Vec<Vec3> = Vec::with_capacity(cut.len());
+        for p in cut {
+            if corners.iter().all(|q| q.distance(p) > WELD_TOLERANCE) {
+                corners.push(p);
+            }
+        }
+        if corners.len() < 3 {
+            return;
+        }
+        let middle = corners.iter().sum::<Vec3>() / corners.len() as f32;
+        let normal = normal.normalize();
+        let u = normal.any_orthonormal_vector();
+        let v = normal.cross(u);
+        corners.sort_by(|&a, &b| {
+            let angle = |p: Vec3| (p - middle).dot(v).atan2((p - middle).dot(u));
+            angle(a).total_cmp(&angle(b))
+        });
+        self.faces.push(Face { neighbor, corners });
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.pieces().map(|(volume, _)| volume).sum()
+    }
+
+    /// Centre of mass, or `None` for an empty solid.
+    pub fn centroid(&self) -> Option<Vec3> {
+        let (volume, moment) = self
+            .pieces()
+            .fold((0.0, Vec3::ZERO), |(v, m), (volume, centre)| {
+                (v + volume, m + centre * volume)
+            });
+        (volume > 0.0).then(|| moment / volume)
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        self.faces.iter().map(Face::area).sum()
+    }
+
+    /// Cross-section by the horizontal plane at `height`, as a polygon in
+    /// domain (XY) coordinates, counter-clockwise. Empty if the plane
+    /// misses the solid.
+    pub fn slice(&self, height: f32) -> Vec<Vec2> {
+        let mut points: Vec<Vec2> = Vec::new();
+        for face in &self.faces {
+            for (k, &a) in face.corners.iter().enumerate() {
+                let b = face.corners[(k + 1) % face.corners.len()];
+                let (da, db) = (a.y - height, b.y - height);
+                if (da <= 0.0) != (db <= 0.0) {
+                    let p = a + (b - a) * (da / (da - db));
+                    let p = Vec2::new(p.x, p.z);
+                    if points.iter().all(|q| q.distance(p) > WELD_TOLERANCE) {
+                        points.push(p);
+                    }
+                }
+            }
+        }
+        if points.len() < 3 {
+            return Vec::new();
+        }
+        let middle = points.iter().sum::<Vec2>() / points.len() as f32;
+        points.sort_by(|&a, &b| {
+            let angle = |p: Vec2| (p - middle).to_angle();
+            angle(a).total_cmp(&angle(b))
+        });
+        points
+    }
+
+    // Volume and centroid of each tetrahedron joining a face triangle to
+    // the first corner
+    fn pieces(&self) -> impl Iterator<Item = (f32, Vec3)> + '_ {
+        let apex = self
+            .faces
+            .first()
+            .and_then(|f| f.corners.first())
+            .copied()
+            .unwrap_or_default();
+        self.faces.iter().flat_map(move |face| {
+            let a = face.corners[0];
+            face.corners.windows(2).skip(1).map(move |pair| {
+                let (b, c) = (pair[0], pair[1]);
+                let volume = (a - apex).dot((b - apex).cross(c - apex)) / 6.0;
+                (volume, (apex + a + b + c) / 4.0)
+            })
+        })
+    }
+}
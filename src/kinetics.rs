@@ -0,0 +1,424 @@
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+use crate::expression::ExpressionKernel;
+use crate::species::{Matrix, Species};
+
+/// A tunable kernel parameter, exposed so front-ends can build sliders.
+pub struct Param<'a> {
+    pub name: &'a str,
+    pub value: &'a mut f32,
+    pub range: RangeInclusive<f32>,
+}
+
+/// What a kernel may read about one cell and its surroundings.
+pub struct Local<'a> {
+    pub cell: &'a [f32],
+    /// Mean concentrations over the neighbours; only filled in when the
+    /// kernel [needs them](ReactionKernel::needs_neighbors), else `cell`.
+    pub neighbor_mean: &'a [f32],
+    pub area: f32,
+}
+
+/// The local reaction term `dC/dt = f(C)` of a reaction-diffusion model.
+/// Diffusion and per-species decay are added by the integrator.
+pub trait ReactionKernel {
+    /// Human-readable rate equations.
+    fn equation(&self) -> &'static str;
+
+    /// Writes the reaction rate of one cell into `rate` (same length as `cell`).
+    fn react(&self, cell: &[f32], rate: &mut [f32]);
+
+    /// Like [`react`](Self::react), with access to the cell's surroundings.
+    fn react_local(&self, local: &Local<'_>, rate: &mut [f32]) {
+        self.react(local.cell, rate);
+    }
+
+    /// Whether [`Local::neighbor_mean`] must be computed for this kernel.
+    fn needs_neighbors(&self) -> bool {
+        false
+    }
+
+    /// Bound on the Jacobian row sums over the model's usual range,
+    /// used by the adaptive stability check.
+    fn stiffness(&self) -> f32;
+
+    /// Range concentrations are clamped to after each step.
+    fn clamp_range(&self, saturate: bool) -> (f32, f32) {
+        (0.0, if saturate { 1.0 } else { f32::INFINITY })
+    }
+
+    fn params(&mut self) -> Vec<Param<'_>> {
+        Vec::new()
+    }
+}
+
+/// The linear model `dC/dt = M * C`.
+impl ReactionKernel for Matrix {
+    fn equation(&self) -> &'static str {
+        "dC/dt = M * C"
+    }
+
+    fn react(&self, cell: &[f32], rate: &mut [f32]) {
+        self.mul_vec(cell, rate);
+    }
+
+    fn stiffness(&self) -> f32 {
+        (0..self.size())
+            .map(|row| self.row(row).iter().map(|m| m.abs()).sum::<f32>())
+            .fold(0.0, f32::max)
+    }
+}
+
+/// Which reaction model drives the chemistry.
+/// The built-in nonlinear models act on the first two species; any others
+/// only diffuse and decay. Typed expressions can drive every species.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum Kinetics {
+    /// The linear reaction matrix in [`SimState`](crate::SimState).
+    #[default]
+    Linear,
+    GrayScott(GrayScott),
+    FitzHughNagumo(FitzHughNagumo),
+    Brusselator(Brusselator),
+    LotkaVolterra(LotkaVolterra),
+    Expression(ExpressionKernel),
+}
+
+impl Kinetics {
+    /// One instance of every model with its default parameters.
+    pub fn all() -> [Self; 6] {
+        [
+            Self::Linear,
+            Self::GrayScott(GrayScott::default()),
+            Self::FitzHughNagumo(FitzHughNagumo::default()),
+            Self::Brusselator(Brusselator::default()),
+            Self::LotkaVolterra(LotkaVolterra::default()),
+            Self::Expression(ExpressionKernel::default()),
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Linear => "Linear Matrix",
+            Self::GrayScott(_) => "Gray-Scott",
+            Self::FitzHughNagumo(_) => "FitzHugh-Nagumo",
+            Self::Brusselator(_) => "Brusselator",
+            Self::LotkaVolterra(_) => "Lotka-Volterra",
+            Self::Expression(_) => "Custom Expressions",
+        }
+    }
+
+    /// The kernel to evaluate; the linear model uses `matrix`.
+    pub fn kernel<'a>(&'a self, matrix: &'a Matrix) -> &'a dyn ReactionKernel {
+        match self {
+            Self::Linear => matrix,
+            Self::GrayScott(k) => k,
+            Self::FitzHughNagumo(k) => k,
+            Self::Brusselator(k) => k,
+            Self::LotkaVolterra(k) => k,
+            Self::Expression(k) => k,
+        }
+    }
+
+    /// Mutable access to a nonlinear kernel's parameters.
+    pub fn params(&mut self) -> Vec<Param<'_>> {
+        match self {
+            Self::Linear => Vec::new(),
+            Self::GrayScott(k) => k.params(),
+            Self::FitzHughNagumo(k) => k.params(),
+            Self::Brusselator(k) => k.params(),
+            Self::LotkaVolterra(k) => k.params(),
+            Self::Expression(k) => k.params(),
+        }
+    }
+
+    /// Recompiles typed expressions against `species`; other models need no
+    /// preparation. Call after loading or whenever species are renamed.
+    pub fn compile(&mut self, species: &[Species]) {
+        if let Self::Expression(k) = self {
+            k.compile(species);
+        }
+    }
+}
+
+// Zeroes `rate` and returns the first two concentrations, or `None` if the
+// cell has fewer than two species
+fn two_species(cell: &[f32], rate: &mut [f32]) -> Option<(f32, f32)> {
+    rate.fill(0.0);
+    match cell {
+        [u, v, ..] => Some((*u, *v)),
+        _ => None,
+    }
+}
+
+/// Gray-Scott: `U + 2V -> 3V`, with U fed in and V removed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GrayScott {
+    pub feed: f32,
+    pub kill: f32,
+    /// Model time units per simulated second.
+    pub rate: f32,
+}
+
+impl Default for GrayScott {
+    fn default() -> Self {
+        Self {
+            feed: 0.037,
+            kill: 0.06,
+            rate: 20.0,
+        }
+    }
+}
+
+impl ReactionKernel for GrayScott {
+    fn equation(&self) -> &'static str {
+        "dU = -UV² + F(1-U)\ndV = UV² - (F+k)V"
+    }
+
+    fn react(&self, cell: &[f32], rate: &mut [f32]) {
+        let Some((u, v)) = two_species(cell, rate) else {
+            return;
+        };
+        let uvv = u * v * v;
+        rate[0] = self.rate * (-uvv + self.feed * (1.0 - u));
+        rate[1] = self.rate * (uvv - (self.feed + self.kill) * v);
+    }
+
+    fn stiffness(&self) -> f32 {
+        // With U, V in [0, 1]: |dU/dU| + |dU/dV| <= 1 + F + 2
+        self.rate * (3.0 + self.feed + self.kill)
+    }
+
+    fn params(&mut self) -> Vec<Param<'_>> {
+        vec![
+            Param {
+                name: "Feed (F)",
+                value: &mut self.feed,
+                range: 0.0..=0.1,
+            },
+            Param {
+                name: "Kill (k)",
+                value: &mut self.kill,
+                range: 0.0..=0.1,
+            },
+            Param {
+                name: "Rate",
+                value: &mut self.rate,
+                range: 0.1..=100.0,
+            },
+        ]
+    }
+}
+
+/// FitzHugh-Nagumo excitable medium: fast activator U, slow recovery V.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FitzHughNagumo {
+    pub a: f32,
+    pub b: f32,
+    pub epsilon: f32,
+    /// Constant stimulus current.
+    pub current: f32,
+    /// Model time units per simulated second.
+    pub rate: f32,
+}
+
+impl Default for FitzHughNagumo {
+    fn default() -> Self {
+        Self {
+            a: 0.7,
+            b: 0.8,
+            epsilon: 0.08,
+            current: 0.5,
+            rate: 5.0,
+        }
+    }
+}
+
+impl ReactionKernel for FitzHughNagumo {
+    fn equation(&self) -> &'static str {
+        "dU = U - U³/3 - V + I\ndV = ε(U + a - bV)"
+    }
+
+    fn react(&self, cell: &[f32], rate: &mut [f32]) {
+        let Some((u, v)) = two_species(cell, rate) else {
+            return;
+        };
+        rate[0] = self.rate * (u - u * u * u / 3.0 - v + self.current);
+        rate[1] = self.rate * self.epsilon * (u + self.a - self.b * v);
+    }
+
+    fn stiffness(&self) -> f32 {
+        // With |U| <= 2.5: |1 - U²| + 1 for U, ε(1 + b) for V
+        self.rate * 6.25f32.max(self.epsilon * (1.0 + self.b))
+    }
+
+    // U and V are signed potentials, not concentrations
+    fn clamp_range(&self, _saturate: bool) -> (f32, f32) {
+        (f32::NEG_INFINITY, f32::INFINITY)
+    }
+
+    fn params(&mut self) -> Vec<Param<'_>> {
+        vec![
+            Param {
+                name: "a",
+                value: &mut self.a,
+                range: 0.0..=2.0,
+            },
+            Param {
+                name: "b",
+                value: &mut self.b,
+                range: 0.0..=2.0,
+            },
+            Param {
+                name: "ε",
+                value: &mut self.epsilon,
+                range: 0.0..=1.0,
+            },
+            Param {
+                name: "Current (I)",
+                value: &mut self.current,
+                range: -1.0..=2.0,
+            },
+            Param {
+                name: "Rate",
+                value: &mut self.rate,
+                range: 0.1..=50.0,
+            },
+        ]
+    }
+}
+
+/// Brusselator autocatalytic oscillator; Turing patterns need `B > 1 + A²`
+/// and V diffusing faster than U.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Brusselator {
+    pub a: f32,
+    pub b: f32,
+    /// Model time units per simulated second.
+    pub rate: f32,
+}
+
+impl Default for Brusselator {
+    fn default() -> Self {
+        Self {
+            a: 1.0,
+            b: 3.0,
+            rate: 2.0,
+        }
+    }
+}
+
+impl ReactionKernel for Brusselator {
+    fn equation(&self) -> &'static str {
+        "dU = A - (B+1)U + U²V\ndV = BU - U²V"
+    }
+
+    fn react(&self, cell: &[f32], rate: &mut [f32]) {
+        let Some((u, v)) = two_species(cell, rate) else {
+            return;
+        };
+        let uuv = u * u * v;
+        rate[0] = self.rate * (self.a - (self.b + 1.0) * u + uuv);
+        rate[1] = self.rate * (self.b * u - uuv);
+    }
+
+    fn stiffness(&self) -> f32 {
+        // Around the fixed point (A, B/A), scaled for excursions of ~2x
+        let u = 2.0 * self.a;
+        let v = 2.0 * self.b / self.a.max(0.1);
+        self.rate * ((self.b + 1.0) + 2.0 * u * v + u * u)
+    }
+
+    fn clamp_range(&self, _saturate: bool) -> (f32, f32) {
+        (0.0, f32::INFINITY)
+    }
+
+    fn params(&mut self) -> Vec<Param<'_>> {
+        vec![
+            Param {
+                name: "A",
+                value: &mut self.a,
+                range: 0.1..=5.0,
+            },
+            Param {
+                name: "B",
+                value: &mut self.b,
+                range: 0.1..=10.0,
+            },
+            Param {
+                name: "Rate",
+                value: &mut self.rate,
+                range: 0.1..=20.0,
+            },
+        ]
+    }
+}
+
+/// Lotka-Volterra predator-prey: prey U grows, predator V eats it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LotkaVolterra {
+    pub alpha: f32,
+    pub beta: f32,
+    pub delta: f32,
+    pub gamma: f32,
+}
+
+impl Default for LotkaVolterra {
+    fn default() -> Self {
+        Self {
+            alpha: 1.0,
+            beta: 1.5,
+            delta: 1.0,
+            gamma: 0.6,
+        }
+    }
+}
+
+impl ReactionKernel for LotkaVolterra {
+    fn equation(&self) -> &'static str {
+        "dU = αU - βUV\ndV = δUV - γV"
+    }
+
+    fn react(&self, cell: &[f32], rate: &mut [f32]) {
+        let Some((u, v)) = two_species(cell, rate) else {
+            return;
+        };
+        rate[0] = self.alpha * u - self.beta * u * v;
+        rate[1] = self.delta * u * v - self.gamma * v;
+    }
+
+    fn stiffness(&self) -> f32 {
+        // With U, V up to 2
+        (self.alpha + 2.0 * self.beta * 2.0).max(2.0 * self.delta * 2.0 + self.gamma)
+    }
+
+    fn clamp_range(&self, _saturate: bool) -> (f32, f32) {
+        (0.0, f32::INFINITY)
+    }
+
+    fn params(&mut self) -> Vec<Param<'_>> {
+        vec![
+            Param {
+                name: "α (prey growth)",
+                value: &mut self.alpha,
+                range: 0.0..=3.0,
+            },
+            Param {
+                name: "β (predation)",
+                value: &mut self.beta,
+                range: 0.0..=3.0,
+            },
+            Param {
+                name: "δ (predator growth)",
+                value: &mut self.delta,
+                range: 0.0..=3.0,
+            },
+            Param {
+                name: "γ (predator death)",
+                value: &mut self.gamma,
+                range: 0.0..=3.0,
+            },
+        ]
+    }
+}
@@ -0,0 +1,33 @@
+//! Headless core of the Voronoi Vivarium.
+//!
+//! The [`Simulation`] type owns the sites, per-cell chemistry, adjacency and
+//! parameters, and can be stepped without any window or render backend. The
+//! Bevy binary in `main.rs` is a thin front-end over it.
+
+pub mod chemistry;
+pub mod delaunay;
+pub mod domain;
+pub mod expression;
+pub mod foam;
+pub mod kinetics;
+pub mod lifecycle;
+pub mod mechanics;
+pub mod presets;
+pub mod raster;
+pub mod simulation;
+pub mod snapshot;
+pub mod species;
+pub mod sphere;
+pub mod state;
+pub mod voronoi;
+
+pub use chemistry::Chemicals;
+pub use domain::{Domain, Obstacle};
+pub use expression::ExpressionKernel;
+pub use kinetics::{Kinetics, ReactionKernel};
+pub use presets::Preset;
+pub use simulation::Simulation;
+pub use snapshot::{Snapshot, SnapshotError};
+pub use species::{Boundary, Matrix, Species};
+pub use state::SimState;
+pub use voronoi::Topology;
@@ -0,0 +1,216 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+
+use crate::chemistry::Chemicals;
+use crate::state::SimState;
+use crate::voronoi::Topology;
+
+/// What a [`Trigger`] compares against its threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Measure {
+    /// Concentration of a species.
+    Concentration(usize),
+    /// Cell area over the mean cell area.
+    RelativeArea,
+}
+
+/// Fires for cells whose measure is above (or below) a threshold.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trigger {
+    /// `None` disables the trigger.
+    pub measure: Option<Measure>,
+    pub above: bool,
+    pub threshold: f32,
+    /// Chance per second that a triggered cell acts, so events spread out
+    /// instead of happening all at once.
+    pub rate: f32,
+}
+
+impl Trigger {
+    fn fires(&self, cell: &[f32], relative_area: f32) -> bool {
+        let value = match self.measure {
+            None => return false,
+            Some(Measure::Concentration(s)) => match cell.get(s) {
+                Some(&c) => c,
+                None => return false,
+            },
+            Some(Measure::RelativeArea) => relative_area,
+        };
+        if self.above {
+            value > self.threshold
+        } else {
+            value < self.threshold
+        }
+    }
+
+    // Forgets a removed species, shifting later indices down
+    fn remove_species(&mut self, index: usize) {
+        self.measure = match self.measure {
+            Some(Measure::Concentration(s)) if s == index => None,
+            Some(Measure::Concentration(s)) if s > index => Some(Measure::Concentration(s - 1)),
+            other => other,
+        };
+    }
+}
+
+/// Rules for cells to divide and die.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Lifecycle {
+    pub division: Trigger,
+    pub death: Trigger,
+    /// Daughters share the parent's chemicals: each keeps its
+    /// concentrations over about half the area, so the amount is split
+    /// between them. Otherwise each inherits the parent's whole amount,
+    /// doubling its concentrations.
+    pub partition: bool,
+    /// Divisions stop at this many cells, or at what the tessellation is
+    /// meant for if that is fewer.
+    pub max_cells: usize,
+    /// Deaths stop at this many cells.
+    pub min_cells: usize,
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self {
+            division: Trigger {
+                measure: None,
+                above: true,
+                threshold: 0.8,
+                rate: 1.0,
+            },
+            death: Trigger {
+                measure: None,
+                above: false,
+                threshold: 0.05,
+                rate: 1.0,
+            },
+            partition: true,
+            max_cells: 1500,
+            min_cells: 10,
+        }
+    }
+}
+
+impl Lifecycle {
+    pub fn is_enabled(&self) -> bool {
+        self.division.measure.is_some() || self.death.measure.is_some()
+    }
+
+    pub fn remove_species(&mut self, index: usize) {
+        self.division.remove_species(index);
+        self.death.remove_species(index);
+    }
+}
+
+/// Applies one step of division and death to the sites, velocities, radii
+/// and chemistry. Returns `true` if the cell count changed (and the topology
+/// needs rebuilding).
+pub fn divide_and_die(
+    state: &mut SimState,
+    topology: &Topology,
+    chemicals: &mut Chemicals,
+    rng: &mut impl Rng,
+    dt: f32,
+) -> bool {
+    let rules = &state.lifecycle;
+    let count = state.sites.len();
+    let max_cells = rules.max_cells.min(state.max_cells());
+    if !rules.is_enabled() || count == 0 || chemicals.len() != count {
+        return false;
+    }
+
+    let mean_area = topology.areas.iter().sum::<f32>() / topology.areas.len().max(1) as f32;
+    let relative_area = |i: usize| topology.areas.get(i).map_or(1.0, |a| a / mean_area);
+    let division_chance = 1.0 - (-rules.division.rate * dt).exp();
+    let death_chance = 1.0 - (-rules.death.rate * dt).exp();
+
+    // 1. Decide who dies and who divides (a dying cell cannot divide)
+    let mut dying = vec![false; count];
+    let mut dividing = Vec::new();
+    let mut deaths = 0;
+    for (i, cell) in chemicals.iter().enumerate() {
+        let area = relative_area(i);
+        if count - deaths > rules.min_cells
+            && rules.death.fires(cell, area)
+            && rng.r#gen::<f32>() < death_chance
+        {
+            dying[i] = true;
+            deaths += 1;
+        } else if count + dividing.len() < max_cells
+            && rules.division.fires(cell, area)
+            && rng.r#gen::<f32>() < division_chance
+        {
+            dividing.push(i);
+        }
+    }
+    if deaths == 0 && dividing.is_empty() {
+        return false;
+    }
+
+    // 2. Divisions: split the site along a random axis, daughter appended
+    state.velocities.resize(count, Vec2::ZERO);
+    state.heights.resize(count, 0.0);
+    state.vertical_velocities.resize(count, 0.0);
+    state.radii.resize(count, 0.0);
+    for &parent in &dividing {
+        if state.domain.is_3d() {
+            // In 3D the axis is a random direction and the size a volume
+            let radius = topology.areas.get(parent).map_or(0.1, |v| v.cbrt() * 0.2);
+            let angle = rng.gen_range(0.0..TAU);
+            let rise: f32 = rng.gen_range(-1.0..1.0);
+            let across = Vec2::from_angle(angle) * (1.0 - rise * rise).sqrt();
+            let offset = Vec3::new(across.x, rise, across.y) * radius * 0.5;
+            let position = state.position(parent);
+            state.place(parent, position - offset);
+            state.sites.push(Vec2::ZERO);
+            state.place(state.sites.len() - 1, position + offset);
+        } else {
+            let radius = topology.areas.get(parent).map_or(0.1, |a| a.sqrt() * 0.2);
+            let angle = rng.gen_range(0.0..TAU);
+            let offset = Vec2::from_angle(angle) * radius * 0.5;
+            let site = state.sites[parent];
+            state.sites[parent] = state.displace(site, -offset);
+            let daughter_site = state.displace(site, offset);
+            state.sites.push(daughter_site);
+            state.heights.push(0.0);
+        }
+        let velocity = state.velocities.get(parent).copied().unwrap_or_default();
+        state.velocities.push(velocity);
+        let climb = state
+            .vertical_velocities
+            .get(parent)
+            .copied()
+            .unwrap_or(0.0);
+        state.vertical_velocities.push(climb);
+        state.radii.push(state.radii[parent]);
+        dying.push(false);
+
+        // The daughters split the parent's area, so concentrations carry
+        // over unless each is to hold the whole amount
+        let mut daughter = chemicals
+            .get(parent)
+            .map(<[f32]>::to_vec)
+            .unwrap_or_default();
+        if !state.lifecycle.partition {
+            daughter.iter_mut().for_each(|c| *c *= 2.0);
+            if let Some(cell) = chemicals.get_mut(parent) {
+                cell.copy_from_slice(&daughter);
+            }
+        }
+        chemicals.push_cell(&daughter);
+    }
+
+    // 3. Deaths: drop the cells, keeping everything else in order
+    if deaths > 0 {
+        state.retain_sites(&dying);
+        chemicals.retain_cells(|i| !dying[i]);
+    }
+
+    state.cell_count = state.sites.len();
+    state.rebuild_requested = true;
+    true
+}
@@ -2,10 +2,12 @@ use bevy::prelude::*;
 use bevy_egui::{EguiPlugin, EguiPrimaryContextPass};
 use bevy_panorbit_camera::PanOrbitCameraPlugin;
 
-mod chemistry;
-mod state;
+use voronoi_vivarium::{SimState, Simulation};
+
+mod cells;
+mod clock;
+mod persistence;
 mod ui;
-mod voronoi;
 
 fn main() {
     App::new()
@@ -23,21 +25,38 @@ fn main() {
             PanOrbitCameraPlugin,
             MeshPickingPlugin,
         ))
-        .init_resource::<state::SimState>()
-        .init_resource::<chemistry::CellMap>()
+        // Each launch gets a fresh seed; it is shown (and editable) in the UI
+        .insert_resource(Simulation::new(SimState {
+            seed: rand::random(),
+            ..default()
+        }))
+        .init_resource::<cells::CellMap>()
+        .init_resource::<cells::DebugOverlay>()
+        .init_resource::<cells::ObstacleDraft>()
+        .init_resource::<cells::RasterView>()
+        .init_resource::<cells::VolumeView>()
+        .init_resource::<clock::SimClock>()
+        .init_resource::<persistence::SnapshotIo>()
+        .init_resource::<persistence::PresetLibrary>()
         .add_systems(Startup, ui::setup_scene)
         .add_systems(EguiPrimaryContextPass, ui::ui_system)
+        // Advance the headless model at a fixed rate, independent of frame rate
+        .add_systems(FixedUpdate, clock::fixed_step_system)
         .add_systems(
             Update,
             (
-                // 1. Move cells based on chemistry
-                chemistry::chemical_motility_system,
-                // 2. Rebuild Mesh if moved
-                voronoi::spawn_mesh_system,
-                // 3. Compute new chemistry
-                chemistry::reaction_diffusion_system,
-                // 4. Update Visuals
-                chemistry::state_update_system,
+                // 1. Apply clock settings, manual single steps and loaded snapshots
+                clock::clock_control_system,
+                clock::single_step_system,
+                persistence::snapshot_load_system,
+                // 2. Rebuild Meshes if the topology changed
+                cells::spawn_mesh_system,
+                cells::raster_view_system,
+                // 3. Update Visuals
+                cells::state_update_system,
+                cells::debug_overlay_system,
+                cells::obstacle_gizmo_system,
+                cells::volume_gizmo_system,
             )
                 .chain(),
         )
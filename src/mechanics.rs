@@ -0,0 +1,172 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::chemistry::Chemicals;
+use crate::domain::Domain;
+use crate::voronoi::Topology;
+
+/// Vertex-model style cell mechanics. Each cell stores the energy
+/// `K_A (A/A0 - 1)² + K_P (P - p0·√A0)² / A0`, so it resists being squeezed
+/// or stretched away from its preferred area `A0` and shape index `p0`.
+/// In 3D the volume `V` and surface `S` take their place, as
+/// `K_A (V/V0 - 1)² + K_P (S - s0·V0^⅔)² / V0^⁴⁄³`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Mechanics {
+    /// Area elasticity `K_A`; 0 switches the area term off.
+    pub area_stiffness: f32,
+    /// Perimeter elasticity `K_P`; 0 switches the perimeter term off.
+    pub perimeter_stiffness: f32,
+    /// Preferred perimeter over √area (about 3.72 for a regular hexagon);
+    /// in 3D, surface over volume^⅔ (about 5.3 for a Kelvin cell).
+    pub target_shape: f32,
+    /// Preferred area relative to the mean cell area.
+    pub target_area: f32,
+    /// Species that scales the preferred area: `A0 · (1 + gain · c)`.
+    pub area_species: Option<usize>,
+    pub area_gain: f32,
+}
+
+impl Default for Mechanics {
+    fn default() -> Self {
+        Self {
+            area_stiffness: 0.0,
+            perimeter_stiffness: 0.0,
+            target_shape: 3.8,
+            target_area: 1.0,
+            area_species: None,
+            area_gain: 1.0,
+        }
+    }
+}
+
+impl Mechanics {
+    pub fn is_enabled(&self) -> bool {
+        self.area_stiffness != 0.0 || self.perimeter_stiffness != 0.0
+    }
+
+    // dE/dA of one cell, taking dP/dA ≈ P / 2A (shape-preserving growth);
+    // in 3D dE/dV, with dS/dV ≈ 2S / 3V
+    fn energy_slope(&self, area: f32, perimeter: f32, target: f32, solid: bool) -> f32 {
+        // Perimeter (or surface) of the preferred cell at unit shape index
+        let (unit, growth) = if solid {
+            (target.powf(2.0 / 3.0), perimeter * 2.0 / (3.0 * area))
+        } else {
+            (target.sqrt(), perimeter / (2.0 * area))
+        };
+        let target_perimeter = self.target_shape * unit;
+        let area_term = 2.0 * self.area_stiffness * (area / target - 1.0) / target;
+        let perimeter_term = 2.0 * self.perimeter_stiffness * (perimeter - target_perimeter)
+            / (unit * unit)
+            * growth;
+        area_term + perimeter_term
+    }
+}
+
+/// Force on every site from the tissue energy, or an empty list when the
+/// model is off.
+///
+/// Moving a site towards a neighbour shifts their shared edge by half as
+/// much, growing the one cell and shrinking the other in proportion to the
+/// edge length, so the energy gradient only needs each cell's `dE/dA`.
+pub fn tissue_forces(
+    mechanics: &Mechanics,
+    sites: &[Vec2],
+    domain: &Domain,
+    wrap: bool,
+    topology: &Topology,
+    chemicals: &Chemicals,
+) -> Vec<Vec2> {
+    if !mechanics.is_enabled() || topology.areas.is_empty() {
+        return Vec::new();
+    }
+    let slopes = energy_slopes(mechanics, topology, chemicals, false);
+
+    // 3. Forces: F_i = sum over neighbours of (l / 2) (E'_j - E'_i) towards j
+    (0..sites.len())
+        .map(|i| {
+            let (Some(neighbors), Some(lengths)) =
+                (topology.neighbors.get(i), topology.edge_lengths.get(i))
+            else {
+                return Vec2::ZERO;
+            };
+            neighbors
+                .iter()
+                .zip(lengths)
+                .map(|(&j, &length)| {
+                    let toward = domain.offset(sites[i], sites[j], wrap).normalize_or_zero();
+                    let slope_i = slopes.get(i).copied().unwrap_or(0.0);
+                    let slope_j = slopes.get(j).copied().unwrap_or(0.0);
+                    toward * (0.5 * length * (slope_j - slope_i))
+                })
+                .sum()
+        })
+        .collect()
+}
+
+/// [`tissue_forces`] in 3D, on world positions: shared faces take the
+/// place of edges and volumes of areas.
+pub fn tissue_forces_3d(
+    mechanics: &Mechanics,
+    positions: &[Vec3],
+    topology: &Topology,
+    chemicals: &Chemicals,
+) -> Vec<Vec3> {
+    if !mechanics.is_enabled() || topology.areas.is_empty() {
+        return Vec::new();
+    }
+    let slopes = energy_slopes(mechanics, topology, chemicals, true);
+
+    // 3. Forces: F_i = sum over neighbours of (a / 2) (E'_j - E'_i) towards j
+    (0..positions.len())
+        .map(|i| {
+            let (Some(neighbors), Some(areas)) =
+                (topology.neighbors.get(i), topology.edge_lengths.get(i))
+            else {
+                return Vec3::ZERO;
+            };
+            neighbors
+                .iter()
+                .zip(areas)
+                .map(|(&j, &area)| {
+                    let toward = (positions[j] - positions[i]).normalize_or_zero();
+                    let slope_i = slopes.get(i).copied().unwrap_or(0.0);
+                    let slope_j = slopes.get(j).copied().unwrap_or(0.0);
+                    toward * (0.5 * area * (slope_j - slope_i))
+                })
+                .sum()
+        })
+        .collect()
+}
+
+// dE/dA of every cell (dE/dV when `solid`)
+fn energy_slopes(
+    mechanics: &Mechanics,
+    topology: &Topology,
+    chemicals: &Chemicals,
+    solid: bool,
+) -> Vec<f32> {
+    // 1. Preferred areas
+    let mean_area = topology.areas.iter().sum::<f32>() / topology.areas.len() as f32;
+    let base = (mechanics.target_area * mean_area).max(f32::EPSILON);
+    let targets = (0..topology.areas.len()).map(|i| {
+        let concentration = mechanics
+            .area_species
+            .and_then(|s| chemicals.get(i)?.get(s))
+            .copied()
+            .unwrap_or(0.0);
+        (base * (1.0 + mechanics.area_gain * concentration)).max(0.05 * base)
+    });
+
+    // 2. Energy slope of each cell
+    targets
+        .zip(topology.areas.iter().zip(&topology.perimeters))
+        .map(|(target, (&area, &perimeter))| {
+            if area > 0.0 {
+                mechanics.energy_slope(area, perimeter, target, solid)
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
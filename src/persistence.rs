@@ -0,0 +1,330 @@
+use bevy::prelude::*;
+use std::sync::{Arc, Mutex};
+use voronoi_vivarium::{Preset, SimState, Simulation, Snapshot, presets};
+
+// --- Platform File I/O ---
+// Native builds use blocking OS dialogs; the web build downloads a Blob and
+// reads uploads through a hidden <input type="file">, which completes asynchronously.
+
+// Hands file contents from (possibly asynchronous) dialogs back to Bevy systems
+#[derive(Clone, Default)]
+pub struct Inbox(Arc<Mutex<Option<Result<String, String>>>>);
+
+impl Inbox {
+    fn put(&self, result: Result<String, String>) {
+        if let Ok(mut slot) = self.0.lock() {
+            *slot = Some(result);
+        }
+    }
+
+    pub fn take(&self) -> Option<Result<String, String>> {
+        self.0.lock().ok()?.take()
+    }
+}
+
+// Returns Ok(false) if the user cancelled the dialog
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_text(file_name: &str, contents: &str) -> Result<bool, String> {
+    let Some(path) = rfd::FileDialog::new()
+        .set_file_name(file_name)
+        .add_filter("RON", &["ron"])
+        .save_file()
+    else {
+        return Ok(false);
+    };
+    std::fs::write(path, contents).map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn open_text(inbox: &Inbox) {
+    if let Some(path) = rfd::FileDialog::new()
+        .add_filter("RON", &["ron"])
+        .pick_file()
+    {
+        inbox.put(std::fs::read_to_string(path).map_err(|e| e.to_string()));
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn js_error(value: wasm_bindgen::JsValue) -> String {
+    format!("{value:?}")
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save_text(file_name: &str, contents: &str) -> Result<bool, String> {
+    use wasm_bindgen::JsCast;
+
+    let document = web_sys::window()
+        .and_then(|w| w.document())
+        .ok_or("no document")?;
+
+    // Wrap the text in a Blob and click a temporary download link
+    let parts = js_sys::Array::of1(&wasm_bindgen::JsValue::from_str(contents));
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type("text/plain");
+    let blob =
+        web_sys::Blob::new_with_str_sequence_and_options(&parts, &options).map_err(js_error)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js_error)?;
+
+    let anchor: web_sys::HtmlAnchorElement = document
+        .create_element("a")
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(|_| "could not create download link")?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+
+    web_sys::Url::revoke_object_url(&url).map_err(js_error)?;
+    Ok(true)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn open_text(inbox: &Inbox) {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen::closure::Closure;
+
+    let Some(document) = web_sys::window().and_then(|w| w.document()) else {
+        return;
+    };
+    let Ok(input) = document
+        .create_element("input")
+        .map(|e| e.unchecked_into::<web_sys::HtmlInputElement>())
+    else {
+        return;
+    };
+    input.set_type("file");
+    input.set_accept(".ron");
+
+    let inbox = inbox.clone();
+    let picker = input.clone();
+    let on_change = Closure::once_into_js(move |_: web_sys::Event| {
+        let Some(file) = picker.files().and_then(|files| files.get(0)) else {
+            return;
+        };
+        let Ok(reader) = web_sys::FileReader::new() else {
+            return;
+        };
+
+        let loaded = reader.clone();
+        let loaded_inbox = inbox.clone();
+        let on_load = Closure::once_into_js(move |_: web_sys::Event| {
+            let text = loaded.result().ok().and_then(|v| v.as_string());
+            loaded_inbox.put(text.ok_or_else(|| "file is not valid text".to_string()));
+        });
+        reader.set_onload(Some(on_load.unchecked_ref()));
+        if let Err(e) = reader.read_as_text(&file) {
+            inbox.put(Err(js_error(e)));
+        }
+    });
+    input.set_onchange(Some(on_change.unchecked_ref()));
+    input.click();
+}
+
+// --- Platform Key-Value Storage ---
+// Small documents that persist between sessions: a file in the user's config
+// directory on native, `localStorage` on the web.
+
+#[cfg(not(target_arch = "wasm32"))]
+fn store_path(key: &str) -> Option<std::path::PathBuf> {
+    let base = std::env::var_os("APPDATA")
+        .or_else(|| std::env::var_os("XDG_CONFIG_HOME"))
+        .map(std::path::PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(".config"))
+        })?;
+    Some(base.join("voronoi-vivarium").join(format!("{key}.ron")))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_store(key: &str) -> Option<String> {
+    std::fs::read_to_string(store_path(key)?).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_store(key: &str, contents: &str) -> Result<(), String> {
+    let path = store_path(key).ok_or("no config directory")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(path, contents).map_err(|e| e.to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read_store(key: &str) -> Option<String> {
+    local_storage()?
+        .get_item(&format!("voronoi-vivarium/{key}"))
+        .ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write_store(key: &str, contents: &str) -> Result<(), String> {
+    local_storage()
+        .ok_or("localStorage is unavailable")?
+        .set_item(&format!("voronoi-vivarium/{key}"), contents)
+        .map_err(js_error)
+}
+
+// --- Snapshots ---
+
+#[derive(Resource, Default)]
+pub struct SnapshotIo {
+    inbox: Inbox,
+    pub status: Option<String>,
+}
+
+impl SnapshotIo {
+    pub fn save(&mut self, sim: &Simulation) {
+        let result = sim
+            .snapshot()
+            .to_ron()
+            .map_err(|e| e.to_string())
+            .and_then(|text| save_text("vivarium.ron", &text));
+
+        match result {
+            Ok(true) => self.status = Some("Snapshot saved".into()),
+            Ok(false) => {}
+            Err(e) => {
+                warn!("Snapshot save failed: {e}");
+                self.status = Some(format!("Save failed: {e}"));
+            }
+        }
+    }
+
+    pub fn load(&self) {
+        open_text(&self.inbox);
+    }
+}
+
+// Applies a snapshot once its file has been read
+pub fn snapshot_load_system(mut io: ResMut<SnapshotIo>, mut sim: ResMut<Simulation>) {
+    let Some(result) = io.inbox.take() else {
+        return;
+    };
+
+    match result.and_then(|text| Snapshot::from_ron(&text).map_err(|e| e.to_string())) {
+        Ok(snapshot) => {
+            sim.restore(snapshot);
+            io.status = Some("Snapshot loaded".into());
+        }
+        Err(e) => {
+            warn!("Snapshot load failed: {e}");
+            io.status = Some(format!("Load failed: {e}"));
+        }
+    }
+}
+
+// --- User Presets ---
+
+const PRESETS_KEY: &str = "presets";
+
+// Built-in presets followed by the user's own, which are kept in storage
+#[derive(Resource)]
+pub struct PresetLibrary {
+    pub builtin: Vec<Preset>,
+    pub user: Vec<Preset>,
+    pub selected: usize, // Index into builtin, then user
+    pub new_name: String,
+    pub status: Option<String>,
+}
+
+impl Default for PresetLibrary {
+    fn default() -> Self {
+        let user = match read_store(PRESETS_KEY).map(|text| ron::from_str::<Vec<Preset>>(&text)) {
+            Some(Ok(mut user)) => {
+                user.retain(|preset| {
+                    if !preset.fits() {
+                        warn!(
+                            "Ignoring user preset \"{}\": its matrices do not fit its species",
+                            preset.name
+                        );
+                    }
+                    preset.fits()
+                });
+                user
+            }
+            Some(Err(e)) => {
+                warn!("Ignoring unreadable user presets: {e}");
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+
+        Self {
+            builtin: presets::builtin(),
+            user,
+            selected: 0,
+            new_name: String::new(),
+            status: None,
+        }
+    }
+}
+
+impl PresetLibrary {
+    pub fn count(&self) -> usize {
+        self.builtin.len() + self.user.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Preset> {
+        self.builtin
+            .get(index)
+            .or_else(|| self.user.get(index.checked_sub(self.builtin.len())?))
+    }
+
+    pub fn is_user(&self, index: usize) -> bool {
+        index >= self.builtin.len() && index < self.count()
+    }
+
+    // Stores the current parameters under `new_name`, replacing a user preset of the same name
+    pub fn save_current(&mut self, state: &SimState) {
+        let name = self.new_name.trim().to_string();
+        if name.is_empty() {
+            self.status = Some("Enter a name first".into());
+            return;
+        }
+
+        let preset = Preset::capture(name.clone(), state);
+        let index = match self.user.iter().position(|p| p.name == name) {
+            Some(i) => {
+                self.user[i] = preset;
+                i
+            }
+            None => {
+                self.user.push(preset);
+                self.user.len() - 1
+            }
+        };
+        self.selected = self.builtin.len() + index;
+        self.new_name.clear();
+        self.persist(format!("Saved \"{name}\""));
+    }
+
+    pub fn delete_selected(&mut self) {
+        if !self.is_user(self.selected) {
+            return;
+        }
+        let removed = self.user.remove(self.selected - self.builtin.len());
+        self.selected = 0;
+        self.persist(format!("Deleted \"{}\"", removed.name));
+    }
+
+    fn persist(&mut self, success: String) {
+        let result = ron::ser::to_string_pretty(&self.user, ron::ser::PrettyConfig::default())
+            .map_err(|e| e.to_string())
+            .and_then(|text| write_store(PRESETS_KEY, &text));
+
+        match result {
+            Ok(()) => self.status = Some(success),
+            Err(e) => {
+                warn!("Saving user presets failed: {e}");
+                self.status = Some(format!("Could not store presets: {e}"));
+            }
+        }
+    }
+}
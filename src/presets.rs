@@ -0,0 +1,492 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::chemistry::{MassSource, Motility};
+use crate::domain::Domain;
+use crate::expression::ExpressionKernel;
+use crate::kinetics::{Brusselator, GrayScott, Kinetics};
+use crate::lifecycle::{Lifecycle, Measure, Trigger};
+use crate::mechanics::Mechanics;
+use crate::species::{Matrix, Species};
+use crate::state::SimState;
+use crate::voronoi::Weighting;
+
+/// A named rule set: every tunable parameter of [`SimState`], without the
+/// sites or the seed, so it can be applied on top of any run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    pub cell_count: usize,
+    pub wrap_enabled: bool,
+    // Presets saved before other shapes existed are square
+    #[serde(default)]
+    pub domain: Domain,
+    pub species: Vec<Species>,
+    pub emission_species: Option<usize>,
+    // Presets saved before nonlinear kinetics existed are linear
+    #[serde(default)]
+    pub kinetics: Kinetics,
+    pub reaction_matrix: Matrix,
+    pub force_matrix: Matrix,
+    pub friction: f32,
+    pub emission_jitter: f32,
+    #[serde(default)]
+    pub relaxation: f32,
+    #[serde(default)]
+    pub mechanics: Mechanics,
+    #[serde(default)]
+    pub weighting: Weighting,
+    #[serde(default)]
+    pub lifecycle: Lifecycle,
+    // Presets saved before inertial motility existed are overdamped
+    #[serde(default)]
+    pub motility: Motility,
+    #[serde(default)]
+    pub mass_source: MassSource,
+    #[serde(default = "default_damping")]
+    pub damping: f32,
+    // Presets saved before the cap was a choice run without it
+    #[serde(default)]
+    pub saturate: bool,
+}
+
+fn default_damping() -> f32 {
+    2.0
+}
+
+impl Preset {
+    /// Captures the current parameters of `state` under `name`.
+    pub fn capture(name: impl Into<String>, state: &SimState) -> Self {
+        Self {
+            name: name.into(),
+            cell_count: state.cell_count,
+            wrap_enabled: state.wrap_enabled,
+            domain: state.domain.clone(),
+            species: state.species.clone(),
+            emission_species: state.emission_species,
+            kinetics: state.kinetics.clone(),
+            reaction_matrix: state.reaction_matrix.clone(),
+            force_matrix: state.force_matrix.clone(),
+            friction: state.friction,
+            emission_jitter: state.emission_jitter,
+            relaxation: state.relaxation,
+            mechanics: state.mechanics.clone(),
+            weighting: state.weighting.clone(),
+            lifecycle: state.lifecycle.clone(),
+            motility: state.motility,
+            mass_source: state.mass_source,
+            damping: state.damping,
+            saturate: state.saturate,
+        }
+    }
+
+    /// Whether the matrices match the species, which a hand-edited or
+    /// stale preset may not.
+    pub fn fits(&self) -> bool {
+        let species = self.species.len();
+        self.reaction_matrix.fits(species) && self.force_matrix.fits(species)
+    }
+
+    /// Copies the parameters into `state` and requests a rebuild.
+    /// Switching the wrap mode or the domain discards the sites, as the UI
+    /// wrap toggle does.
+    pub fn apply(&self, state: &mut SimState) {
+        if state.wrap_enabled != self.wrap_enabled || state.domain != self.domain {
+            state.sites.clear();
+        }
+        state.cell_count = self.cell_count;
+        state.wrap_enabled = self.wrap_enabled;
+        state.domain = self.domain.clone();
+        state.species = self.species.clone();
+        state.emission_species = self.emission_species;
+        state.kinetics = self.kinetics.clone();
+        state.reaction_matrix = self.reaction_matrix.clone();
+        state.force_matrix = self.force_matrix.clone();
+        state.friction = self.friction;
+        state.emission_jitter = self.emission_jitter;
+        state.relaxation = self.relaxation;
+        state.mechanics = self.mechanics.clone();
+        state.weighting = self.weighting.clone();
+        state.lifecycle = self.lifecycle.clone();
+        state.motility = self.motility;
+        state.mass_source = self.mass_source;
+        state.damping = self.damping;
+        state.saturate = self.saturate;
+        state.rebuild_requested = true;
+    }
+}
+
+/// The presets shipped with the vivarium, in display order.
+pub fn builtin() -> Vec<Preset> {
+    vec![
+        chromatic_pursuit(),
+        quiet_tide(),
+        segregation(),
+        swarm(),
+        billiards(),
+        colony(),
+        gray_scott_worms(),
+        brusselator_turing(),
+    ]
+}
+
+// Red, green and blue species plus an emission channel that only diffuses
+// and decays; `reaction` and `force` act on the three colours
+fn rgb_emission(diffusion: Vec4, decay: Vec4) -> Vec<Species> {
+    vec![
+        Species::new("R", [1.0, 0.0, 0.0], diffusion.x, decay.x),
+        Species::new("G", [0.0, 1.0, 0.0], diffusion.y, decay.y),
+        Species::new("B", [0.0, 0.0, 1.0], diffusion.z, decay.z),
+        Species::new("E", [0.0, 0.0, 0.0], diffusion.w, decay.w),
+    ]
+}
+
+// Embeds a column-major RGB matrix into the 4-species layout
+fn rgb_matrix(m: Mat3) -> Matrix {
+    Matrix::from_fn(4, |row, col| {
+        if row < 3 && col < 3 {
+            m.col(col)[row]
+        } else {
+            0.0
+        }
+    })
+}
+
+/// The default rule set: cyclic predation chased by cyclic attraction.
+pub fn chromatic_pursuit() -> Preset {
+    // 1. Reaction (Alchemy): Cyclic Predation
+    // Columns = Input (Prey), Rows = Output (Predator)
+    // 0.5 value means "Presence of Col boosts Row"
+    let reaction = Mat3::from_cols(
+        Vec3::new(0.0, 0.0, 1.0), // Col 0 (Red In): Feeds Blue (Row 2)
+        Vec3::new(1.0, 0.0, 0.0), // Col 1 (Green In): Feeds Red (Row 0)
+        Vec3::new(0.0, 1.0, 0.0), // Col 2 (Blue In): Feeds Green (Row 1)
+    ) * 0.4; // Scale intensity
+
+    // 2. Forces (Motility): Cyclic Attraction & Self-Repulsion
+    // Columns = Neighbor (Target), Rows = Self (Agent)
+    // Positive = Chase, Negative = Flee
+    let force = Mat3::from_cols(
+        Vec3::new(-0.2, 0.3, -0.1), // Neighbor is Red: Repels Red, Attracts Blue
+        Vec3::new(-0.1, -0.2, 0.3), // Neighbor is Green: Attracts Red, Repels Green
+        Vec3::new(0.3, -0.1, -0.2), // Neighbor is Blue: Repels Blue, Attracts Green
+    ) * 0.05;
+
+    Preset {
+        name: "Chromatic Pursuit".into(),
+        cell_count: 200, // Increased for better pattern resolution
+        wrap_enabled: true,
+        domain: Domain::Square,
+
+        species: rgb_emission(
+            // Lower diffusion to prevent flickering (Stability)
+            Vec4::new(0.2, 0.3, 0.4, 0.5),
+            // Decay balances the reaction growth
+            Vec4::new(0.3, 0.4, 0.5, 0.6),
+        ),
+        emission_species: Some(3),
+        kinetics: Kinetics::Linear,
+
+        reaction_matrix: rgb_matrix(reaction),
+        force_matrix: rgb_matrix(force),
+
+        friction: 0.8,        // Fluid movement
+        emission_jitter: 0.1, // Slight temperature noise
+        relaxation: 0.0,
+        mechanics: Mechanics::default(),
+        weighting: Weighting::default(),
+        lifecycle: Lifecycle::default(),
+        motility: Motility::Overdamped,
+        mass_source: MassSource::Uniform,
+        damping: default_damping(),
+        saturate: false,
+    }
+}
+
+/// Slow, diffusion-dominated blending with almost no motion.
+pub fn quiet_tide() -> Preset {
+    // Each channel weakly sustains itself
+    let reaction = Mat3::from_diagonal(Vec3::splat(0.25));
+
+    Preset {
+        name: "Quiet Tide".into(),
+        cell_count: 400,
+        wrap_enabled: true,
+        domain: Domain::Square,
+        species: rgb_emission(Vec4::new(1.2, 1.2, 1.2, 2.0), Vec4::new(0.2, 0.2, 0.2, 0.5)),
+        emission_species: Some(3),
+        kinetics: Kinetics::Linear,
+        reaction_matrix: rgb_matrix(reaction),
+        force_matrix: Matrix::zeros(4),
+        friction: 0.95,
+        emission_jitter: 0.02,
+        relaxation: 0.0,
+        mechanics: Mechanics::default(),
+        weighting: Weighting::default(),
+        lifecycle: Lifecycle::default(),
+        motility: Motility::Overdamped,
+        mass_source: MassSource::Uniform,
+        damping: default_damping(),
+        saturate: false,
+    }
+}
+
+/// Like attracts like and repels the rest, so colours sort into patches.
+pub fn segregation() -> Preset {
+    let reaction = Mat3::from_diagonal(Vec3::splat(0.3));
+
+    // Attract own colour, repel the others
+    let force = Mat3::from_cols(
+        Vec3::new(0.3, -0.15, -0.15),
+        Vec3::new(-0.15, 0.3, -0.15),
+        Vec3::new(-0.15, -0.15, 0.3),
+    ) * 0.1;
+
+    Preset {
+        name: "Segregation".into(),
+        cell_count: 300,
+        wrap_enabled: true,
+        domain: Domain::Square,
+        species: rgb_emission(
+            Vec4::new(0.1, 0.1, 0.1, 0.5),
+            Vec4::new(0.25, 0.25, 0.25, 0.6),
+        ),
+        emission_species: Some(3),
+        kinetics: Kinetics::Linear,
+        reaction_matrix: rgb_matrix(reaction),
+        force_matrix: rgb_matrix(force),
+        friction: 0.7,
+        emission_jitter: 0.05,
+        relaxation: 0.0,
+        mechanics: Mechanics::default(),
+        weighting: Weighting::default(),
+        lifecycle: Lifecycle::default(),
+        motility: Motility::Overdamped,
+        mass_source: MassSource::Uniform,
+        damping: default_damping(),
+        saturate: false,
+    }
+}
+
+/// Strong cyclic chasing with hot, restless cells in a walled box.
+pub fn swarm() -> Preset {
+    // Cyclic predation in the opposite direction to Chromatic Pursuit
+    let reaction = Mat3::from_cols(
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec3::new(1.0, 0.0, 0.0),
+    ) * 0.6;
+
+    let force = Mat3::from_cols(
+        Vec3::new(-0.1, 0.4, -0.3),
+        Vec3::new(-0.3, -0.1, 0.4),
+        Vec3::new(0.4, -0.3, -0.1),
+    ) * 0.15;
+
+    Preset {
+        name: "Swarm".into(),
+        cell_count: 250,
+        wrap_enabled: false,
+        domain: Domain::Square,
+        species: rgb_emission(Vec4::new(0.3, 0.3, 0.3, 1.0), Vec4::new(0.5, 0.5, 0.5, 0.4)),
+        emission_species: Some(3),
+        kinetics: Kinetics::Linear,
+        reaction_matrix: rgb_matrix(reaction),
+        force_matrix: rgb_matrix(force),
+        friction: 0.6,
+        emission_jitter: 0.3,
+        relaxation: 0.0,
+        mechanics: Mechanics::default(),
+        weighting: Weighting::default(),
+        lifecycle: Lifecycle::default(),
+        motility: Motility::Overdamped,
+        mass_source: MassSource::Uniform,
+        damping: default_damping(),
+        saturate: true, // Tuned against the cap
+    }
+}
+
+/// Heavy, coloured cells with momentum: cyclic chasing and self-repulsion
+/// in a walled box, where cells overshoot, collide and bounce off the walls.
+pub fn billiards() -> Preset {
+    // Colours never change, so every cell keeps its identity
+    let mut species = rgb_emission(Vec4::ZERO, Vec4::ZERO);
+    species[3].decay = 0.3;
+
+    // Emission sustains itself to keep the heat up
+    let mut reaction = Matrix::zeros(4);
+    *reaction.get_mut(3, 3) = 0.6;
+
+    let force = Mat3::from_cols(
+        Vec3::new(-0.3, 0.3, -0.1),
+        Vec3::new(-0.1, -0.3, 0.3),
+        Vec3::new(0.3, -0.1, -0.3),
+    ) * 0.8;
+
+    Preset {
+        name: "Billiards".into(),
+        cell_count: 200,
+        wrap_enabled: false,
+        domain: Domain::Square,
+        species,
+        emission_species: Some(3),
+        kinetics: Kinetics::Linear,
+        reaction_matrix: reaction,
+        force_matrix: rgb_matrix(force),
+        friction: 0.0,
+        emission_jitter: 0.2,
+        relaxation: 0.0,
+        mechanics: Mechanics::default(),
+        weighting: Weighting::default(),
+        lifecycle: Lifecycle::default(),
+        motility: Motility::Inertial,
+        mass_source: MassSource::Concentration,
+        damping: 0.5,
+        saturate: true, // Tuned against the cap
+    }
+}
+
+/// A small colony that grows on a replenished nutrient: cells divide when
+/// their growth factor is high, age as they go and die of old age. The feed
+/// rate decides between boom-and-bust and a colony filling the dish.
+pub fn colony() -> Preset {
+    let rules = ExpressionKernel::new(
+        "dN = feed*(1 - N) - 2*G*N\n\
+         dG = 1.5*G*N - 0.2*G\n\
+         dA = aging",
+        &[("feed", 0.2), ("aging", 0.03)],
+    );
+
+    Preset {
+        name: "Colony".into(),
+        cell_count: 40,
+        wrap_enabled: true,
+        domain: Domain::Square,
+        species: vec![
+            Species::new("N", [0.1, 0.2, 0.6], 0.8, 0.0),
+            Species::new("G", [0.2, 0.9, 0.3], 0.05, 0.0),
+            Species::new("A", [0.6, 0.1, 0.1], 0.0, 0.0),
+        ],
+        emission_species: None,
+        kinetics: Kinetics::Expression(rules),
+        reaction_matrix: Matrix::zeros(3),
+        force_matrix: Matrix::zeros(3),
+        friction: 0.0,
+        emission_jitter: 0.0,
+        relaxation: 0.0,
+        // Crowded cells push their neighbours apart to make room
+        mechanics: Mechanics {
+            area_stiffness: 5.0,
+            ..Mechanics::default()
+        },
+        weighting: Weighting::default(),
+        lifecycle: Lifecycle {
+            division: Trigger {
+                measure: Some(Measure::Concentration(1)),
+                above: true,
+                threshold: 0.6,
+                rate: 0.5,
+            },
+            death: Trigger {
+                measure: Some(Measure::Concentration(2)),
+                above: true,
+                threshold: 0.9,
+                rate: 1.0,
+            },
+            partition: true,
+            max_cells: 600,
+            min_cells: 5,
+        },
+        motility: Motility::Overdamped,
+        mass_source: MassSource::Uniform,
+        damping: default_damping(),
+        saturate: false,
+    }
+}
+
+/// Gray-Scott worms and spots growing on a static tessellation.
+pub fn gray_scott_worms() -> Preset {
+    Preset {
+        name: "Gray-Scott Worms".into(),
+        cell_count: 1200,
+        wrap_enabled: true,
+        domain: Domain::Square,
+        species: vec![
+            // Substrate stays dark so the autocatalyst stands out
+            Species::new("U", [0.05, 0.05, 0.2], 1.0, 0.0),
+            Species::new("V", [1.0, 0.8, 0.2], 0.5, 0.0),
+        ],
+        emission_species: None,
+        kinetics: Kinetics::GrayScott(GrayScott {
+            feed: 0.04,
+            kill: 0.06,
+            ..GrayScott::default()
+        }),
+        reaction_matrix: Matrix::zeros(2),
+        force_matrix: Matrix::zeros(2),
+        friction: 1.0, // No motion
+        emission_jitter: 0.0,
+        relaxation: 0.0,
+        mechanics: Mechanics::default(),
+        weighting: Weighting::default(),
+        lifecycle: Lifecycle::default(),
+        motility: Motility::Overdamped,
+        mass_source: MassSource::Uniform,
+        damping: default_damping(),
+        saturate: false,
+    }
+}
+
+/// Brusselator just below its Hopf bifurcation, where fast-diffusing V
+/// turns the uniform state into stationary Turing spots.
+pub fn brusselator_turing() -> Preset {
+    Preset {
+        name: "Brusselator Turing".into(),
+        cell_count: 1000,
+        wrap_enabled: true,
+        domain: Domain::Square,
+        species: vec![
+            Species::new("U", [0.2, 0.6, 1.0], 0.2, 0.0),
+            Species::new("V", [0.3, 0.0, 0.1], 1.6, 0.0),
+        ],
+        emission_species: None,
+        kinetics: Kinetics::Brusselator(Brusselator {
+            a: 1.0,
+            b: 1.9,
+            ..Brusselator::default()
+        }),
+        reaction_matrix: Matrix::zeros(2),
+        force_matrix: Matrix::zeros(2),
+        friction: 1.0, // No motion
+        emission_jitter: 0.0,
+        relaxation: 0.0,
+        mechanics: Mechanics::default(),
+        weighting: Weighting::default(),
+        lifecycle: Lifecycle::default(),
+        motility: Motility::Overdamped,
+        mass_source: MassSource::Uniform,
+        damping: default_damping(),
+        saturate: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtins_fit() {
+        assert!(builtin().iter().all(Preset::fits));
+    }
+
+    #[test]
+    fn mismatched_matrices_do_not_fit() {
+        let mut preset = chromatic_pursuit();
+        preset.species.pop();
+        assert!(!preset.fits());
+
+        let mut preset = chromatic_pursuit();
+        preset.reaction_matrix = Matrix::zeros(3);
+        assert!(!preset.fits());
+    }
+}
@@ -0,0 +1,212 @@
+//! Raster tessellation for very large cell counts.
+//!
+//! Instead of exact polygons, the domain square is cut into a grid of
+//! pixels, each labelled with its nearest site (by power distance when the
+//! sites are weighted). The sites are bucketed into a coarse grid about one
+//! cell wide, so every pixel only looks at the sites around it; the labels
+//! are exact for the pixel centres and do not depend on how the sites got
+//! where they are. Areas, shared edge lengths and walls are then counted
+//! off the pixels (see [`crate::Topology`]).
+
+use bevy::prelude::*;
+
+use crate::voronoi::{DOMAIN_SIZE, wrapped_offset};
+
+/// Label of pixels outside the domain or inside an obstacle.
+pub const OUTSIDE: u32 = u32::MAX;
+
+// Pixels per cell on average, so even small cells have some shape
+const PIXELS_PER_CELL: f32 = 16.0;
+
+// Bounds on the pixels per side
+const MIN_RESOLUTION: usize = 128;
+const MAX_RESOLUTION: usize = 1536;
+
+/// Cells as a square grid of pixels over the domain square.
+#[derive(Debug, Clone, Default)]
+pub struct Raster {
+    /// Pixels per side.
+    pub resolution: usize,
+    /// Site of every pixel, or [`OUTSIDE`], row by row from the lowest Y.
+    pub labels: Vec<u32>,
+}
+
+impl Raster {
+    /// Pixels per side that give `count` cells enough pixels each.
+    pub fn resolution_for(count: usize) -> usize {
+        ((count as f32 * PIXELS_PER_CELL).sqrt().ceil() as usize)
+            .clamp(MIN_RESOLUTION, MAX_RESOLUTION)
+    }
+
+    /// Width of one pixel in domain units.
+    pub fn pixel_size(&self) -> f32 {
+        DOMAIN_SIZE as f32 / self.resolution.max(1) as f32
+    }
+
+    /// Centre of pixel `(x, y)` in domain coordinates.
+    pub fn centre(&self, x: usize, y: usize) -> Vec2 {
+        let half = DOMAIN_SIZE as f32 / 2.0;
+        (Vec2::new(x as f32, y as f32) + 0.5) * self.pixel_size() - half
+    }
+
+    /// Site of the pixel under `p`, if it is in the domain.
+    pub fn label_at(&self, p: Vec2) -> Option<usize> {
+        let half = DOMAIN_SIZE as f32 / 2.0;
+        let cell = ((p + half) / self.pixel_size()).floor();
+        let range = 0.0..self.resolution as f32;
+        if !range.contains(&cell.x) || !range.contains(&cell.y) {
+            return None;
+        }
+        let label = self.labels[cell.y as usize * self.resolution + cell.x as usize];
+        (label != OUTSIDE).then_some(label as usize)
+    }
+}
+
+/// Labels every pixel of a `resolution`-wide grid whose centre is
+/// `inside` with its nearest site, by `|p - s|² - weight` for nonzero
+/// `weights` (parallel to `sites`, or empty). Distances cross the torus
+/// seam when `wrap` is set. Ties go to the lower index.
+pub fn rasterize(
+    sites: &[Vec2],
+    weights: &[f32],
+    resolution: usize,
+    inside: impl Fn(Vec2) -> bool,
+    wrap: bool,
+) -> Raster {
+    let mut raster = Raster {
+        resolution,
+        labels: vec![OUTSIDE; resolution * resolution],
+    };
+    if sites.is_empty() {
+        return raster;
+    }
+    let weight = |i: usize| weights.get(i).copied().unwrap_or(0.0);
+    let heaviest = (0..sites.len()).map(weight).fold(0.0, f32::max);
+
+    // 1. Buckets about one cell wide, with the sites copied out in bucket
+    // order so each bucket's are side by side
+    let size = DOMAIN_SIZE as f32;
+    let buckets = ((sites.len() as f32).sqrt() as usize).max(1);
+    let width = size / buckets as f32;
+    let bucket_of = |p: Vec2| {
+        ((p + size / 2.0) / width)
+            .floor()
+            .as_ivec2()
+            .clamp(IVec2::ZERO, IVec2::splat(buckets as i32 - 1))
+    };
+    let index = |b: IVec2| b.y as usize * buckets + b.x as usize;
+    let mut starts = vec![0; buckets * buckets + 1];
+    for &site in sites {
+        starts[index(bucket_of(site)) + 1] += 1;
+    }
+    for k in 1..starts.len() {
+        starts[k] += starts[k - 1];
+    }
+    let mut filled = starts.clone();
+    let mut ordered = vec![(Vec2::ZERO, 0.0, 0); sites.len()];
+    for (i, &site) in sites.iter().enumerate() {
+        let k = index(bucket_of(site));
+        ordered[filled[k]] = (site, weight(i), i);
+        filled[k] += 1;
+    }
+
+    // The pixel columns of each bucket, which are also its rows
+    let mut spans = vec![0; buckets + 1];
+    for x in 0..resolution {
+        spans[bucket_of(raster.centre(x, 0)).x as usize + 1] += 1;
+    }
+    for b in 1..spans.len() {
+        spans[b] += spans[b - 1];
+    }
+
+    // Sites in the buckets on `ring` around `home`, moved across the seam
+    // to their copy nearest `near`
+    let ring_sites =
+        |home: IVec2, ring: i32, near: Vec2, out: &mut Vec<(Vec2, f32, usize)>| {
+            for dy in -ring..=ring {
+                // The middle rows only have their two end buckets
+                let step = if dy.abs() == ring {
+                    1
+                } else {
+                    (2 * ring).max(1)
+                };
+                for dx in (-ring..=ring).step_by(step as usize) {
+                    let mut bucket = home + IVec2::new(dx, dy);
+                    if wrap {
+                        bucket = bucket.rem_euclid(IVec2::splat(buckets as i32));
+                    } else if bucket.min_element() < 0 || bucket.max_element() >= buckets as i32 {
+                        continue;
+                    }
+                    let k = index(bucket);
+                    out.extend(ordered[starts[k]..starts[k + 1]].iter().map(
+                        |&(site, weight, i)| (near + wrapped_offset(near, site, wrap), weight, i),
+                    ));
+                }
+            }
+        };
+
+    // 2. Bucket by bucket, the sites of the 3×3 buckets around it: none
+    // beyond them is nearer to its pixels than a bucket width. Where one
+    // pixel needs more, the next ring is added for the rest of the bucket.
+    // On a small torus the copy of a site nearest the bucket may not be
+    // the one nearest the pixel, so there they are gathered pixel by pixel
+    let per_pixel = wrap && buckets < 5;
+    let mut around = Vec::new();
+    for by in 0..buckets {
+        for bx in 0..buckets {
+            let home = IVec2::new(bx as i32, by as i32);
+            let middle = (home.as_vec2() + 0.5) * width - size / 2.0;
+            around.clear();
+            ring_sites(home, 0, middle, &mut around);
+            ring_sites(home, 1, middle, &mut around);
+            let mut rings = 1;
+            for y in spans[by]..spans[by + 1] {
+                for x in spans[bx]..spans[bx + 1] {
+                    let p = raster.centre(x, y);
+                    if !inside(p) {
+                        continue;
+                    }
+                    if per_pixel {
+                        around.clear();
+                        ring_sites(home, 0, p, &mut around);
+                        ring_sites(home, 1, p, &mut around);
+                        rings = 1;
+                    }
+                    let mut best = nearest(p, around.iter().copied());
+                    loop {
+                        let reach = rings as f32 * width;
+                        if best.0 <= reach * reach - heaviest || rings >= buckets as i32 {
+                            break;
+                        }
+                        if wrap && 2 * (rings + 1) >= buckets as i32 {
+                            // Halfway round the torus: every site, seen from the pixel
+                            let all = ordered.iter().map(|&(site, weight, i)| {
+                                (p + wrapped_offset(p, site, wrap), weight, i)
+                            });
+                            best = nearest(p, all);
+                            break;
+                        }
+                        rings += 1;
+                        ring_sites(home, rings, middle, &mut around);
+                        best = nearest(p, around.iter().copied());
+                    }
+                    raster.labels[y * resolution + x] = best.1 as u32;
+                }
+            }
+        }
+    }
+    raster
+}
+
+// Lowest `|p - s|² - weight` among `candidates` and whose it is, the lower
+// index on a tie
+fn nearest(p: Vec2, candidates: impl IntoIterator<Item = (Vec2, f32, usize)>) -> (f32, usize) {
+    let mut best = (f32::INFINITY, usize::MAX);
+    for (site, weight, i) in candidates {
+        let distance = p.distance_squared(site) - weight;
+        if distance < best.0 || (distance == best.0 && i < best.1) {
+            best = (distance, i);
+        }
+    }
+    best
+}
@@ -0,0 +1,371 @@
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::chemistry::{self, Chemicals, Workspace};
+use crate::domain::{Domain, Obstacle};
+use crate::lifecycle;
+use crate::snapshot::{SNAPSHOT_VERSION, Snapshot};
+use crate::species::Species;
+use crate::state::SimState;
+use crate::voronoi::{self, DOMAIN_SIZE, Tessellation, Topology};
+
+/// A complete, headless vivarium: parameters, sites, per-cell chemistry and
+/// adjacency. Call [`Simulation::step`] to advance it.
+#[derive(Resource)]
+pub struct Simulation {
+    pub state: SimState,
+    /// Current chemistry, indexed like `state.sites`.
+    pub chemicals: Chemicals,
+    pub topology: Topology,
+    /// Bumped whenever `topology` is rebuilt, so front-ends know when to
+    /// refresh their geometry.
+    pub generation: u64,
+    /// The `generation` at which each cell's polygon or neighbours last
+    /// changed, so front-ends can redraw just those cells.
+    pub cell_generations: Vec<u64>,
+    /// Every random draw in the model comes from here, so the same seed and
+    /// parameters reproduce a run exactly.
+    pub rng: ChaCha8Rng,
+    /// Chemistry substeps taken by the last [`Simulation::step`].
+    pub last_substeps: u32,
+    workspace: Workspace,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self::new(SimState::default())
+    }
+}
+
+impl Simulation {
+    pub fn new(state: SimState) -> Self {
+        let mut sim = Self {
+            rng: ChaCha8Rng::seed_from_u64(state.seed),
+            state,
+            chemicals: Chemicals::default(),
+            topology: Topology::default(),
+            generation: 0,
+            cell_generations: Vec::new(),
+            last_substeps: 1,
+            workspace: Workspace::default(),
+        };
+        sim.rebuild();
+        sim
+    }
+
+    /// Restarts the run from `state.seed`: fresh sites, fresh chemistry.
+    pub fn reset(&mut self) {
+        self.rng = ChaCha8Rng::seed_from_u64(self.state.seed);
+        self.state.sites.clear();
+        self.chemicals = Chemicals::default();
+        self.rebuild();
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            state: self.state.clone(),
+            chemicals: self.chemicals.clone(),
+            rng: self.rng.clone(),
+        }
+    }
+
+    /// Replaces the whole run with `snapshot`, resuming exactly where it was taken.
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.state = snapshot.state;
+        self.state.cell_count = self.state.sites.len();
+        self.chemicals = snapshot.chemicals;
+        self.rng = snapshot.rng;
+        self.rebuild();
+    }
+
+    /// Appends a species, zero in every cell, keeping the existing chemistry.
+    pub fn add_species(&mut self, species: Species) {
+        self.state.push_species(species);
+        self.state.compile_kinetics();
+        self.chemicals.push_species();
+    }
+
+    /// Removes species `index` from the rules and from every cell.
+    pub fn remove_species(&mut self, index: usize) {
+        if index >= self.state.species.len() {
+            return;
+        }
+        self.state.remove_species(index);
+        self.state.compile_kinetics();
+        self.chemicals.remove_species(index);
+    }
+
+    /// Runs `iterations` rounds of Lloyd's algorithm: every site jumps to the
+    /// centroid of its cell and the topology is rebuilt. Chemistry stays with
+    /// its cell.
+    pub fn relax(&mut self, iterations: usize) {
+        for _ in 0..iterations {
+            if self.state.domain.is_3d() {
+                for (i, solid) in self.topology.solids.iter().enumerate() {
+                    if let Some(centroid) = solid.centroid() {
+                        self.state.place(i, centroid);
+                    }
+                }
+                self.rebuild();
+                continue;
+            }
+            let sites = self
+                .topology
+                .centroids
+                .iter()
+                .map(|&centroid| self.state.confine(centroid))
+                .collect();
+            self.state.sites = sites;
+            self.rebuild();
+        }
+    }
+
+    /// Switches to another domain shape. Cells left outside it are removed
+    /// and replaced by new ones inside, which take their chemistry from
+    /// their neighbours; the rest stay put. Only flat domains keep their
+    /// obstacles, and sites entering the cube are spread through its depth.
+    pub fn set_domain(&mut self, domain: Domain) {
+        if domain == self.state.domain {
+            return;
+        }
+        let outside: Vec<bool> = self
+            .state
+            .sites
+            .iter()
+            .map(|&site| !domain.contains(site))
+            .collect();
+        if !domain.is_flat() {
+            self.state.obstacles.clear();
+        }
+        if domain.is_3d() && !self.state.domain.is_3d() {
+            let half_size = (DOMAIN_SIZE / 2.0) as f32;
+            self.state.heights = (0..self.state.sites.len())
+                .map(|_| self.rng.gen_range(-half_size..half_size))
+                .collect();
+        }
+        self.state.domain = domain;
+        self.retain_cells(&outside);
+        self.rebuild();
+    }
+
+    /// Adds an obstacle, moving any sites it covers out to its edge.
+    /// Ignored unless the domain is flat.
+    pub fn add_obstacle(&mut self, obstacle: Obstacle) {
+        if !self.state.domain.is_flat() {
+            return;
+        }
+        for site in &mut self.state.sites {
+            *site = obstacle.push_out(*site);
+        }
+        self.state.obstacles.push(obstacle);
+        self.rebuild();
+    }
+
+    /// Gives every cell a random power-diagram radius up to the cap.
+    pub fn randomize_radii(&mut self) {
+        let max_radius = self.state.weighting.max_radius;
+        self.state.radii = (0..self.state.sites.len())
+            .map(|_| self.rng.gen_range(0.0..=max_radius))
+            .collect();
+        self.rebuild();
+    }
+
+    /// Removes every obstacle.
+    pub fn clear_obstacles(&mut self) {
+        self.state.obstacles.clear();
+        self.rebuild();
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.state.sites.len()
+    }
+
+    /// Advances the model by `dt` seconds.
+    pub fn step(&mut self, dt: f32) {
+        // 0. Apply pending parameter changes (e.g. a new species count)
+        if self.state.rebuild_requested {
+            self.rebuild();
+        }
+
+        // 1. Move cells based on chemistry
+        chemistry::chemical_motility(
+            &mut self.state,
+            &self.topology,
+            &self.chemicals,
+            &mut self.rng,
+            dt,
+        );
+
+        // 2. Divide and kill cells
+        lifecycle::divide_and_die(
+            &mut self.state,
+            &self.topology,
+            &mut self.chemicals,
+            &mut self.rng,
+            dt,
+        );
+
+        // 3. Rebuild topology if moved
+        if self.state.rebuild_requested {
+            self.rebuild();
+        }
+
+        // 4. Compute new chemistry, in as many substeps as stability requires
+        let substeps = if self.state.adaptive_step {
+            chemistry::stable_substeps(&self.state, &self.topology, dt)
+        } else {
+            1
+        };
+        let sub_dt = dt / substeps as f32;
+        for _ in 0..substeps {
+            chemistry::reaction_diffusion(
+                &self.state,
+                &self.topology,
+                &mut self.chemicals,
+                &mut self.workspace,
+                sub_dt,
+            );
+        }
+        self.last_substeps = substeps;
+
+        // 5. Radii following the chemistry reshape the cells at the next step
+        if self
+            .state
+            .weighting
+            .follow(&mut self.state.radii, &self.chemicals)
+        {
+            self.state.rebuild_requested = true;
+        }
+    }
+
+    /// Synchronises the sites with `cell_count` and recomputes the topology.
+    /// Surviving cells keep their chemistry: shrinking removes randomly
+    /// chosen cells and new cells start from the mean of their neighbours.
+    /// Chemistry is re-initialised only when there was none to keep (or
+    /// the species count changed).
+    pub fn rebuild(&mut self) {
+        // 1. Match the cell count
+        let old_count = self.state.sites.len();
+        if self.state.cell_count < old_count {
+            self.remove_cells(old_count - self.state.cell_count);
+        }
+        let kept = self.state.sites.len();
+        voronoi::sync_sites(&mut self.state, &mut self.rng);
+        // Typed rules are not serialised in compiled form
+        self.state.compile_kinetics();
+
+        let keep_chemistry = kept > 0
+            && self.chemicals.len() == kept
+            && self.chemicals.species() == self.state.species.len();
+        if keep_chemistry {
+            let blank = vec![0.0; self.chemicals.species()];
+            for _ in kept..self.state.sites.len() {
+                self.chemicals.push_cell(&blank);
+            }
+        } else if self.chemicals.len() != self.state.sites.len()
+            || self.chemicals.species() != self.state.species.len()
+        {
+            // Hard reset if the cell or species count changed
+            self.chemicals = Chemicals::gradient(&self.state, &mut self.rng);
+        }
+
+        // 2. Topology, then chemistry for the new cells from their neighbours
+        let changed = if self.state.domain.is_3d() {
+            self.topology =
+                voronoi::build_foam(&self.state.positions(), &self.state.power_weights());
+            (0..self.state.sites.len()).collect()
+        } else if self.state.tessellation == Tessellation::Raster && self.state.domain.is_flat() {
+            self.topology = voronoi::build_raster(
+                &self.state.sites,
+                &self.state.power_weights(),
+                &self.state.domain,
+                &self.state.obstacles,
+                self.state.wrap_enabled,
+            );
+            (0..self.state.sites.len()).collect()
+        } else {
+            self.topology.update(
+                &self.state.sites,
+                &self.state.power_weights(),
+                &self.state.domain,
+                &self.state.obstacles,
+                self.state.wrap_enabled,
+            )
+        };
+        if keep_chemistry && kept < self.state.sites.len() {
+            self.chemicals.interpolate_from(kept, &self.topology);
+        }
+        self.generation += 1;
+        self.cell_generations
+            .resize(self.state.sites.len(), self.generation);
+        for i in changed {
+            self.cell_generations[i] = self.generation;
+        }
+        self.state.rebuild_requested = false;
+    }
+
+    // Removes `count` randomly chosen cells, keeping the rest in order
+    fn remove_cells(&mut self, count: usize) {
+        let total = self.state.sites.len();
+        let mut removed = vec![false; total];
+        for i in rand::seq::index::sample(&mut self.rng, total, count) {
+            removed[i] = true;
+        }
+        self.retain_cells(&removed);
+    }
+
+    // Drops the cells flagged in `removed`, keeping the rest in order
+    fn retain_cells(&mut self, removed: &[bool]) {
+        let total = self.state.sites.len();
+        self.state.retain_sites(removed);
+        if self.chemicals.len() == total {
+            self.chemicals.retain_cells(|i| !removed[i]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(seed: u64, steps: usize) -> Simulation {
+        let mut sim = Simulation::new(SimState {
+            seed,
+            ..SimState::default()
+        });
+        for _ in 0..steps {
+            sim.step(1.0 / 60.0);
+        }
+        sim
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_run() {
+        let (a, b) = (run(7, 30), run(7, 30));
+        assert_eq!(a.state.sites, b.state.sites);
+        assert_eq!(a.chemicals, b.chemicals);
+        assert_eq!(a.state.sites.len(), a.chemicals.len());
+        // And it did move
+        assert_ne!(a.state.sites, run(7, 0).state.sites);
+    }
+
+    #[test]
+    fn other_seeds_give_other_runs() {
+        assert_ne!(run(7, 0).state.sites, run(8, 0).state.sites);
+        assert_ne!(run(7, 30).chemicals, run(8, 30).chemicals);
+    }
+
+    #[test]
+    fn reset_restarts_from_the_seed() {
+        let mut sim = run(7, 30);
+        sim.reset();
+        for _ in 0..30 {
+            sim.step(1.0 / 60.0);
+        }
+        let fresh = run(7, 30);
+        assert_eq!(sim.state.sites, fresh.state.sites);
+        assert_eq!(sim.chemicals, fresh.chemicals);
+    }
+}
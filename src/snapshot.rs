@@ -0,0 +1,205 @@
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::chemistry::Chemicals;
+use crate::state::SimState;
+
+/// Bumped whenever the snapshot layout changes incompatibly.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// A complete, resumable copy of a [`Simulation`](crate::Simulation):
+/// parameters, sites and seed (in `state`), per-cell chemistry and the
+/// RNG stream position.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub state: SimState,
+    pub chemicals: Chemicals,
+    pub rng: ChaCha8Rng,
+}
+
+// Parsed ahead of the full snapshot so version mismatches get a clear error
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Serialize(ron::Error),
+    Parse(ron::error::SpannedError),
+    UnsupportedVersion(u32),
+    CellCountMismatch {
+        sites: usize,
+        chemicals: usize,
+    },
+    SpeciesMismatch {
+        species: usize,
+        chemicals: usize,
+    },
+    MatrixMismatch {
+        matrix: &'static str,
+        species: usize,
+    },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Serialize(e) => write!(f, "could not serialize snapshot: {e}"),
+            Self::Parse(e) => write!(f, "could not parse snapshot: {e}"),
+            Self::UnsupportedVersion(v) => write!(
+                f,
+                "snapshot version {v} is not supported (expected {SNAPSHOT_VERSION})"
+            ),
+            Self::CellCountMismatch { sites, chemicals } => write!(
+                f,
+                "snapshot has {sites} sites but {chemicals} chemistry entries"
+            ),
+            Self::SpeciesMismatch { species, chemicals } => write!(
+                f,
+                "snapshot has {species} species but {chemicals} chemistry channels"
+            ),
+            Self::MatrixMismatch { matrix, species } => write!(
+                f,
+                "snapshot's {matrix} matrix does not fit its {species} species"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl Snapshot {
+    pub fn to_ron(&self) -> Result<String, SnapshotError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(SnapshotError::Serialize)
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, SnapshotError> {
+        let header: Header = ron::from_str(text).map_err(SnapshotError::Parse)?;
+        if header.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(header.version));
+        }
+
+        let snapshot: Self = ron::from_str(text).map_err(SnapshotError::Parse)?;
+        if snapshot.state.sites.len() != snapshot.chemicals.len() {
+            return Err(SnapshotError::CellCountMismatch {
+                sites: snapshot.state.sites.len(),
+                chemicals: snapshot.chemicals.len(),
+            });
+        }
+        if snapshot.state.species.len() != snapshot.chemicals.species() {
+            return Err(SnapshotError::SpeciesMismatch {
+                species: snapshot.state.species.len(),
+                chemicals: snapshot.chemicals.species(),
+            });
+        }
+        let state = &snapshot.state;
+        for (matrix, name) in [
+            (&state.reaction_matrix, "reaction"),
+            (&state.force_matrix, "force"),
+        ] {
+            if !matrix.fits(state.species.len()) {
+                return Err(SnapshotError::MatrixMismatch {
+                    matrix: name,
+                    species: state.species.len(),
+                });
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Matrix, Simulation};
+
+    fn run(steps: usize) -> Simulation {
+        let mut sim = Simulation::default();
+        for _ in 0..steps {
+            sim.step(1.0 / 60.0);
+        }
+        sim
+    }
+
+    // Steps both runs alike and checks they stay identical
+    fn assert_in_step(mut a: Simulation, mut b: Simulation) {
+        for _ in 0..20 {
+            a.step(1.0 / 60.0);
+            b.step(1.0 / 60.0);
+        }
+        assert_eq!(a.state.sites, b.state.sites);
+        assert_eq!(a.chemicals, b.chemicals);
+    }
+
+    #[test]
+    fn restore_resumes_the_run() {
+        let sim = run(20);
+        let mut resumed = Simulation::new(SimState {
+            seed: sim.state.seed + 1,
+            ..SimState::default()
+        });
+        resumed.restore(sim.snapshot());
+        assert_in_step(sim, resumed);
+    }
+
+    #[test]
+    fn ron_round_trip() {
+        let sim = run(10);
+        let text = sim.snapshot().to_ron().unwrap();
+        let mut resumed = Simulation::default();
+        resumed.restore(Snapshot::from_ron(&text).unwrap());
+        assert_eq!(resumed.state.sites, sim.state.sites);
+        assert_eq!(resumed.chemicals, sim.chemicals);
+        // The RNG position comes along too
+        assert_in_step(sim, resumed);
+    }
+
+    #[test]
+    fn mismatches_are_rejected() {
+        let snapshot = Simulation::default().snapshot();
+
+        let mut future = snapshot.clone();
+        future.version = SNAPSHOT_VERSION + 1;
+        let error = Snapshot::from_ron(&future.to_ron().unwrap()).unwrap_err();
+        assert!(matches!(error, SnapshotError::UnsupportedVersion(v) if v == SNAPSHOT_VERSION + 1));
+
+        let mut short = snapshot.clone();
+        short.state.sites.pop();
+        let error = Snapshot::from_ron(&short.to_ron().unwrap()).unwrap_err();
+        assert!(matches!(error, SnapshotError::CellCountMismatch { .. }));
+
+        let mut extra = snapshot.clone();
+        extra.state.species.pop();
+        let error = Snapshot::from_ron(&extra.to_ron().unwrap()).unwrap_err();
+        assert!(matches!(error, SnapshotError::SpeciesMismatch { .. }));
+
+        let mut small = snapshot.clone();
+        small.state.force_matrix = Matrix::zeros(2);
+        let error = Snapshot::from_ron(&small.to_ron().unwrap()).unwrap_err();
+        assert!(matches!(
+            error,
+            SnapshotError::MatrixMismatch {
+                matrix: "force",
+                ..
+            }
+        ));
+
+        // Right size, too few values
+        let size = snapshot.state.species.len();
+        let mut truncated = snapshot;
+        truncated.state.reaction_matrix =
+            ron::from_str(&format!("(size: {size}, values: [1.0])")).unwrap();
+        let error = Snapshot::from_ron(&truncated.to_ron().unwrap()).unwrap_err();
+        assert!(matches!(
+            error,
+            SnapshotError::MatrixMismatch {
+                matrix: "reaction",
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+
+/// One chemical channel: how it spreads, fades and is drawn.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Species {
+    pub name: String,
+    /// Linear RGB contributed to a cell's colour per unit concentration.
+    pub color: [f32; 3],
+    pub diffusion: f32,
+    pub decay: f32,
+    /// What happens at the walls of a domain that does not wrap.
+    #[serde(default)]
+    pub boundary: Boundary,
+}
+
+impl Species {
+    pub fn new(name: impl Into<String>, color: [f32; 3], diffusion: f32, decay: f32) -> Self {
+        Self {
+            name: name.into(),
+            color,
+            diffusion,
+            decay,
+            boundary: Boundary::default(),
+        }
+    }
+}
+
+/// Boundary condition of one species, applied to the cells touching the
+/// edge of the domain.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Boundary {
+    /// Nothing crosses the boundary.
+    #[default]
+    ZeroFlux,
+    /// Cells touching the boundary are reservoirs held at this value.
+    Fixed(f32),
+    /// The species diffuses out through the boundary into an empty outside.
+    Absorbing,
+}
+
+impl Boundary {
+    /// One of each condition, with a default reservoir value of 1.
+    pub fn all() -> [Self; 3] {
+        [Self::ZeroFlux, Self::Fixed(1.0), Self::Absorbing]
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::ZeroFlux => "Zero Flux",
+            Self::Fixed(_) => "Fixed Value",
+            Self::Absorbing => "Absorbing",
+        }
+    }
+}
+
+/// Square interaction matrix between species, stored row-major.
+/// Rows are the affected species (output), columns the source (input).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Matrix {
+    size: usize,
+    values: Vec<f32>,
+}
+
+impl Matrix {
+    pub fn zeros(size: usize) -> Self {
+        Self {
+            size,
+            values: vec![0.0; size * size],
+        }
+    }
+
+    pub fn from_fn(size: usize, mut f: impl FnMut(usize, usize) -> f32) -> Self {
+        let mut matrix = Self::zeros(size);
+        for row in 0..size {
+            for col in 0..size {
+                matrix.values[row * size + col] = f(row, col);
+            }
+        }
+        matrix
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether this is a well-formed `size`×`size` matrix, which one read
+    /// from a hand-edited or stale file may not be.
+    pub fn fits(&self, size: usize) -> bool {
+        self.size == size && self.values.len() == size * size
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> f32 {
+        self.values[row * self.size + col]
+    }
+
+    pub fn get_mut(&mut self, row: usize, col: usize) -> &mut f32 {
+        &mut self.values[row * self.size + col]
+    }
+
+    pub fn row(&self, row: usize) -> &[f32] {
+        &self.values[row * self.size..(row + 1) * self.size]
+    }
+
+    /// `out = self * v`
+    pub fn mul_vec(&self, v: &[f32], out: &mut [f32]) {
+        for (row, o) in out.iter_mut().enumerate().take(self.size) {
+            *o = self.row(row).iter().zip(v).map(|(m, x)| m * x).sum();
+        }
+    }
+
+    /// `a · (self * b)`, the bilinear form used for pairwise forces.
+    pub fn bilinear(&self, a: &[f32], b: &[f32]) -> f32 {
+        a.iter()
+            .take(self.size)
+            .enumerate()
+            .map(|(row, &x)| x * self.row(row).iter().zip(b).map(|(m, y)| m * y).sum::<f32>())
+            .sum()
+    }
+
+    /// Appends a zero row and column.
+    pub fn push(&mut self) {
+        let old = std::mem::take(self);
+        *self = Self::from_fn(old.size + 1, |row, col| {
+            if row < old.size && col < old.size {
+                old.get(row, col)
+            } else {
+                0.0
+            }
+        });
+    }
+
+    /// Drops row and column `index`.
+    pub fn remove(&mut self, index: usize) {
+        let old = std::mem::take(self);
+        let skip = |i: usize| if i >= index { i + 1 } else { i };
+        *self = Self::from_fn(old.size.saturating_sub(1), |row, col| {
+            old.get(skip(row), skip(col))
+        });
+    }
+
+    pub fn values_mut(&mut self) -> &mut [f32] {
+        &mut self.values
+    }
+}
@@ -0,0 +1,197 @@
+//! Geometry of the spherical domain.
+//!
+//! Sites on the sphere keep their `Vec2` form through Lambert's
+//! cylindrical equal-area projection of the `DOMAIN_SIZE` square: X is the
+//! longitude and Y the sine of the latitude, both scaled to the square.
+//! Uniform points in the square are uniform on the globe, and everything
+//! that only stores or copies sites works unchanged. Tangent vectors at a
+//! site (offsets, forces, velocities) are in world units along its local
+//! east and north.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use std::f64::consts::PI;
+use voronator::delaunator::{self, Point};
+
+use crate::voronoi::DOMAIN_SIZE;
+
+/// Radius of the globe: `DOMAIN_SIZE / (2√π)`, so it has the area of the
+/// square and the same cell count gives the same cell size.
+pub const SPHERE_RADIUS: f32 = 5.641_896;
+
+/// Unit vector of a site, with the poles on ±Y.
+pub fn to_sphere(p: Vec2) -> DVec3 {
+    let half = DOMAIN_SIZE / 2.0;
+    let longitude = f64::from(p.x) / half * PI;
+    let height = (f64::from(p.y) / half).clamp(-1.0, 1.0);
+    let ring = (1.0 - height * height).sqrt();
+    DVec3::new(ring * longitude.cos(), height, ring * longitude.sin())
+}
+
+/// Site of a (not necessarily unit) direction.
+pub fn from_sphere(v: DVec3) -> Vec2 {
+    let half = DOMAIN_SIZE / 2.0;
+    let v = v.normalize_or(DVec3::X);
+    let longitude = v.z.atan2(v.x);
+    Vec2::new((longitude / PI * half) as f32, (v.y * half) as f32)
+}
+
+// Local east and north at a site; east follows the longitude even at the poles
+fn frame(p: Vec2) -> (DVec3, DVec3) {
+    let v = to_sphere(p);
+    let longitude = f64::from(p.x) / (DOMAIN_SIZE / 2.0) * PI;
+    let east = DVec3::new(-longitude.sin(), 0.0, longitude.cos());
+    (east, east.cross(v))
+}
+
+/// Tangent vector at `from` pointing along the great circle to `to`, as
+/// long as the arc between them.
+pub fn offset(from: Vec2, to: Vec2) -> Vec2 {
+    let (a, b) = (to_sphere(from), to_sphere(to));
+    let toward = (b - a * a.dot(b)).normalize_or_zero() * arc(a, b) * f64::from(SPHERE_RADIUS);
+    let (east, north) = frame(from);
+    Vec2::new(toward.dot(east) as f32, toward.dot(north) as f32)
+}
+
+/// Follows the great circle from `from` along the tangent `step`, carrying
+/// the tangent vector `carried` along with it (parallel transport).
+/// Returns the site reached and `carried` in its frame.
+pub fn travel(from: Vec2, step: Vec2, carried: Vec2) -> (Vec2, Vec2) {
+    let (east, north) = frame(from);
+    let step = east * f64::from(step.x) + north * f64::from(step.y);
+    let carried = east * f64::from(carried.x) + north * f64::from(carried.y);
+    let angle = step.length() / f64::from(SPHERE_RADIUS);
+    let Some(heading) = step.try_normalize() else {
+        return (
+            from,
+            Vec2::new(carried.dot(east) as f32, carried.dot(north) as f32),
+        );
+    };
+
+    // Rotate the position and the heading together; the sideways part of
+    // `carried` stays put
+    let v = to_sphere(from);
+    let reached = v * angle.cos() + heading * angle.sin();
+    let along = carried.dot(heading);
+    let carried = carried - heading * along + (heading * angle.cos() - v * angle.sin()) * along;
+
+    let to = from_sphere(reached);
+    let (east, north) = frame(to);
+    (
+        to,
+        Vec2::new(carried.dot(east) as f32, carried.dot(north) as f32),
+    )
+}
+
+/// Angle between two unit vectors, in radians.
+pub fn arc(a: DVec3, b: DVec3) -> f64 {
+    a.cross(b).length().atan2(a.dot(b))
+}
+
+/// Area of the spherical triangle on three unit vectors, in steradians
+/// (Van Oosterom and Strackee).
+pub fn triangle_area(a: DVec3, b: DVec3, c: DVec3) -> f64 {
+    let numerator = a.dot(b.cross(c)).abs();
+    let denominator = 1.0 + a.dot(b) + b.dot(c) + c.dot(a);
+    2.0 * numerator.atan2(denominator)
+}
+
+/// Delaunay triangulation of unit vectors, which is their convex hull, with
+/// every triangle `[a, b, c]` facing out along `(b - a) × (c - a)`. Empty if
+/// there are too few points.
+///
+/// The hull is found by projecting the points stereographically from the
+/// first one, which maps circles to circles, so a planar Delaunay
+/// triangulation gives every face not touching that point; the faces that do
+/// join it to the planar hull.
+pub fn triangulate(points: &[DVec3]) -> Vec<[usize; 3]> {
+    let Some(&pole) = points.first() else {
+        return Vec::new();
+    };
+    let e1 = pole.any_orthonormal_vector();
+    let e2 = pole.cross(e1);
+
+    // 1. Project every other point; a copy of the pole cannot be projected
+    // and is left out
+    let mut indices = Vec::with_capacity(points.len());
+    let mut projected = Vec::with_capacity(points.len());
+    for (i, &v) in points.iter().enumerate().skip(1) {
+        let depth = 1.0 - v.dot(pole);
+        if depth > 1e-12 {
+            indices.push(i);
+            projected.push(Point {
+                x: v.dot(e1) / depth,
+                y: v.dot(e2) / depth,
+            });
+        }
+    }
+    let Some(planar) = delaunator::triangulate(&projected) else {
+        return Vec::new();
+    };
+
+    // 2. Projected orientation is reversed (seen from outside), so planar
+    // counter-clockwise triangles are flipped
+    let turn = |a: usize, b: usize, c: usize| {
+        let (a, b, c) = (&projected[a], &projected[b], &projected[c]);
+        (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+    };
+    let mut triangles: Vec<[usize; 3]> = planar
+        .triangles
+        .chunks_exact(3)
+        .map(|t| {
+            if turn(t[0], t[1], t[2]) > 0.0 {
+                [indices[t[0]], indices[t[2]], indices[t[1]]]
+            } else {
+                [indices[t[0]], indices[t[1]], indices[t[2]]]
+            }
+        })
+        .collect();
+
+    // 3. Close the hull around the pole, which lies outside every planar
+    // hull edge
+    let mut hull = planar.hull.clone();
+    let twice_area: f64 = hull
+        .iter()
+        .zip(hull.iter().cycle().skip(1))
+        .map(|(&p, &q)| projected[p].x * projected[q].y - projected[q].x * projected[p].y)
+        .sum();
+    if twice_area < 0.0 {
+        hull.reverse();
+    }
+    for (&p, &q) in hull.iter().zip(hull.iter().cycle().skip(1)) {
+        triangles.push([indices[q], 0, indices[p]]);
+    }
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn travel_stays_on_the_globe() {
+        let mut rng = ChaCha8Rng::seed_from_u64(41);
+        let half = (DOMAIN_SIZE / 2.0) as f32;
+        for _ in 0..1000 {
+            let from = Vec2::new(rng.gen_range(-half..half), rng.gen_range(-half..half));
+            let step = Vec2::new(rng.gen_range(-3.0..3.0), rng.gen_range(-3.0..3.0));
+            let carried = Vec2::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+            let (to, moved) = travel(from, step, carried);
+
+            // Still a site of the map, as far along the globe as the step is long
+            assert!(
+                to.abs().cmple(Vec2::splat(half)).all(),
+                "{from} went to {to}"
+            );
+            let distance = arc(to_sphere(from), to_sphere(to)) as f32 * SPHERE_RADIUS;
+            assert!(
+                (distance - step.length()).abs() < 1e-2,
+                "{distance} for {step}"
+            );
+            // Carried along without stretching
+            assert!((moved.length() - carried.length()).abs() < 1e-3);
+        }
+    }
+}
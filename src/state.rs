@@ -1,58 +1,367 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-#[derive(Resource)]
+use crate::chemistry::{Integrator, MassSource, Motility};
+use crate::domain::{Domain, Obstacle};
+use crate::kinetics::{Kinetics, ReactionKernel};
+use crate::lifecycle::Lifecycle;
+use crate::mechanics::Mechanics;
+use crate::presets;
+use crate::species::{Matrix, Species};
+use crate::sphere;
+use crate::voronoi::{DOMAIN_SIZE, Laplacian, Tessellation, Weighting};
+
+// Missing fields fall back to the default preset, so older snapshots keep loading
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SimState {
+    pub seed: u64,
     pub cell_count: usize,
+    #[serde(skip)]
     pub rebuild_requested: bool,
+    /// Only takes effect while `domain` can wrap; see [`SimState::wraps`].
     pub wrap_enabled: bool,
+    pub domain: Domain,
+    pub obstacles: Vec<Obstacle>,
     pub sites: Vec<Vec2>,
-    pub diffusion_rates: Vec4,
-    pub decay_rates: Vec4,
-    pub reaction_matrix: Mat3,
-    pub force_matrix: Mat3,
+    /// Per-site velocity, indexed like `sites`; only carried over between
+    /// steps in [`Motility::Inertial`] mode.
+    pub velocities: Vec<Vec2>,
+    /// Height of each site above the plane, indexed like `sites`; only used
+    /// by the 3D domain (see [`SimState::position`]).
+    pub heights: Vec<f32>,
+    /// Vertical part of each site's velocity, indexed like `sites`.
+    pub vertical_velocities: Vec<f32>,
+    /// Power-diagram radius of each cell, indexed like `sites`; see
+    /// [`Weighting`].
+    pub radii: Vec<f32>,
+    pub species: Vec<Species>,
+    /// Species whose concentration drives jitter and glow.
+    pub emission_species: Option<usize>,
+    pub kinetics: Kinetics,
+    /// Rates of the linear reaction model.
+    pub reaction_matrix: Matrix,
+    pub force_matrix: Matrix,
     pub friction: f32,
     pub emission_jitter: f32,
+    /// Strength of the pull toward each cell's centroid (Lloyd relaxation).
+    pub relaxation: f32,
+    pub mechanics: Mechanics,
+    pub weighting: Weighting,
+    pub lifecycle: Lifecycle,
+    pub motility: Motility,
+    pub mass_source: MassSource,
+    /// Rate (per second) at which inertial velocities decay.
+    pub damping: f32,
+    pub laplacian: Laplacian,
+    /// Only takes effect on flat domains.
+    pub tessellation: Tessellation,
+    pub integrator: Integrator,
+    /// Split chemistry steps that would exceed the integrator's stability limit.
+    pub adaptive_step: bool,
+    /// Cap concentrations at 1, for rules that rely on it. Off, the only
+    /// clamp is the kernel's own valid range (non-negative concentrations),
+    /// so a runaway model shows as one.
+    pub saturate: bool,
 }
 
 impl Default for SimState {
     fn default() -> Self {
-        // --- The "Chromatic Pursuit" Preset ---
-
-        // 1. Reaction (Alchemy): Cyclic Predation
-        // Columns = Input (Prey), Rows = Output (Predator)
-        // 0.5 value means "Presence of Col boosts Row"
-        let reaction = Mat3::from_cols(
-            Vec3::new(0.0, 0.0, 1.0), // Col 0 (Red In): Feeds Blue (Row 2)
-            Vec3::new(1.0, 0.0, 0.0), // Col 1 (Green In): Feeds Red (Row 0)
-            Vec3::new(0.0, 1.0, 0.0), // Col 2 (Blue In): Feeds Green (Row 1)
-        ) * 0.4; // Scale intensity
-
-        // 2. Forces (Motility): Cyclic Attraction & Self-Repulsion
-        // Columns = Neighbor (Target), Rows = Self (Agent)
-        // Positive = Chase, Negative = Flee
-        let force = Mat3::from_cols(
-            Vec3::new(-0.2, 0.3, -0.1), // Neighbor is Red: Repels Red, Attracts Blue
-            Vec3::new(-0.1, -0.2, 0.3), // Neighbor is Green: Attracts Red, Repels Green
-            Vec3::new(0.3, -0.1, -0.2), // Neighbor is Blue: Repels Blue, Attracts Green
-        ) * 0.05;
+        let preset = presets::chromatic_pursuit();
 
         Self {
-            cell_count: 200, // Increased for better pattern resolution
+            seed: 0,
+            cell_count: preset.cell_count,
             rebuild_requested: true,
-            wrap_enabled: true,
+            wrap_enabled: preset.wrap_enabled,
+            domain: preset.domain,
+            obstacles: Vec::new(),
             sites: Vec::new(),
+            velocities: Vec::new(),
+            heights: Vec::new(),
+            vertical_velocities: Vec::new(),
+            radii: Vec::new(),
+            species: preset.species,
+            emission_species: preset.emission_species,
+            kinetics: preset.kinetics,
+            reaction_matrix: preset.reaction_matrix,
+            force_matrix: preset.force_matrix,
+            friction: preset.friction,
+            emission_jitter: preset.emission_jitter,
+            relaxation: preset.relaxation,
+            mechanics: preset.mechanics,
+            weighting: preset.weighting,
+            lifecycle: preset.lifecycle,
+            motility: preset.motility,
+            mass_source: preset.mass_source,
+            damping: preset.damping,
+            laplacian: Laplacian::default(),
+            tessellation: Tessellation::default(),
+            integrator: Integrator::default(),
+            adaptive_step: true,
+            saturate: preset.saturate,
+        }
+    }
+}
+
+impl SimState {
+    /// Whether sites live on a torus: wrapping is on and the domain allows it.
+    pub fn wraps(&self) -> bool {
+        self.wrap_enabled && self.domain.can_wrap()
+    }
+
+    /// Most cells the current domain and tessellation are meant for.
+    pub fn max_cells(&self) -> usize {
+        if self.domain.is_flat() {
+            self.tessellation.max_cells()
+        } else {
+            Tessellation::Polygons.max_cells()
+        }
+    }
+
+    /// Brings a position back into the domain and out of any obstacle.
+    pub fn confine(&self, pos: Vec2) -> Vec2 {
+        let pos = self.domain.confine(pos, self.wraps());
+        self.obstacles.iter().fold(pos, |p, o| o.push_out(p))
+    }
+
+    /// Shortest offset between two sites; see [`Domain::offset`].
+    pub fn offset(&self, from: Vec2, to: Vec2) -> Vec2 {
+        self.domain.offset(from, to, self.wraps())
+    }
+
+    /// Moves a site by `step`, keeping it in the domain and out of any obstacle.
+    pub fn displace(&self, pos: Vec2, step: Vec2) -> Vec2 {
+        let pos = self.domain.displace(pos, step, self.wraps());
+        self.obstacles.iter().fold(pos, |p, o| o.push_out(p))
+    }
+
+    /// World position `(x, height, y)` of site `i` in the 3D domain.
+    pub fn position(&self, i: usize) -> Vec3 {
+        let site = self.sites.get(i).copied().unwrap_or_default();
+        let height = self.heights.get(i).copied().unwrap_or(0.0);
+        Vec3::new(site.x, height, site.y)
+    }
+
+    /// World positions of every site; see [`SimState::position`].
+    pub fn positions(&self) -> Vec<Vec3> {
+        (0..self.sites.len()).map(|i| self.position(i)).collect()
+    }
+
+    /// Moves site `i` to the world position `p`, kept inside the cube.
+    pub fn place(&mut self, i: usize, p: Vec3) {
+        let half = (DOMAIN_SIZE / 2.0) as f32;
+        let p = p.clamp(Vec3::splat(-half), Vec3::splat(half));
+        if let Some(site) = self.sites.get_mut(i) {
+            *site = Vec2::new(p.x, p.z);
+        }
+        self.heights.resize(self.sites.len(), 0.0);
+        if let Some(height) = self.heights.get_mut(i) {
+            *height = p.y;
+        }
+    }
+
+    /// Power weight `r²` of every site in domain units, from its radius
+    /// capped by the weighting's `max_radius` and kept short of the nearest
+    /// other site, so no cell is squeezed out; see [`Weighting`]. Empty on
+    /// the sphere.
+    pub fn power_weights(&self) -> Vec<f32> {
+        if self.domain.is_sphere() {
+            return Vec::new();
+        }
+        let count = self.sites.len().max(1) as f32;
+        let spacing = DOMAIN_SIZE as f32
+            / if self.domain.is_3d() {
+                count.cbrt()
+            } else {
+                count.sqrt()
+            };
+        let reach = self.weighting.max_radius.max(0.0) * spacing;
+        let nearest = self.nearest_within(reach);
+        (0..self.sites.len())
+            .map(|i| {
+                let radius = self.radii.get(i).copied().unwrap_or(0.0).max(0.0) * spacing;
+                let radius = radius.min(reach).min(0.9 * nearest[i]);
+                radius * radius
+            })
+            .collect()
+    }
 
-            // Lower diffusion to prevent flickering (Stability)
-            diffusion_rates: Vec4::new(0.2, 0.3, 0.4, 0.5),
+    /// Cell drawn under the world point `point`: the one whose site is
+    /// nearest by power distance, or along the globe on the sphere. `None`
+    /// without sites.
+    pub fn cell_at(&self, point: Vec3) -> Option<usize> {
+        let weights = self.power_weights();
+        let distance = |i: usize| {
+            let weight = weights.get(i).copied().unwrap_or(0.0);
+            if self.domain.is_sphere() {
+                // Nearest along the globe is farthest along the point's direction
+                let direction = point.as_dvec3().normalize_or_zero();
+                -sphere::to_sphere(self.sites[i]).dot(direction) as f32
+            } else if self.domain.is_3d() {
+                self.position(i).distance_squared(point) - weight
+            } else {
+                let p = Vec2::new(point.x, point.z);
+                self.offset(p, self.sites[i]).length_squared() - weight
+            }
+        };
+        (0..self.sites.len()).min_by(|&a, &b| distance(a).total_cmp(&distance(b)))
+    }
 
-            // Decay balances the reaction growth
-            decay_rates: Vec4::new(0.3, 0.4, 0.5, 0.6),
+    // Distance from every site to the nearest other one, or `reach` if none
+    // is closer, looking only through the neighbouring squares (cubes in
+    // 3D) of a grid at least `reach` wide
+    fn nearest_within(&self, reach: f32) -> Vec<f32> {
+        // Heights left over from the cube mean nothing off it
+        let positions: Vec<Vec3> = if self.domain.is_3d() {
+            self.positions()
+        } else {
+            self.sites
+                .iter()
+                .map(|s| Vec3::new(s.x, 0.0, s.y))
+                .collect()
+        };
+        if reach <= 0.0 {
+            return vec![0.0; positions.len()];
+        }
+        let size = DOMAIN_SIZE as f32;
+        let squares = ((size / reach) as i32).max(1);
+        let wraps = self.wraps();
+        let bucket = |p: Vec3| {
+            ((p / size + 0.5) * squares as f32)
+                .floor()
+                .as_ivec3()
+                .clamp(IVec3::ZERO, IVec3::splat(squares - 1))
+        };
+        let mut grid: HashMap<IVec3, Vec<usize>> = HashMap::new();
+        for (i, &p) in positions.iter().enumerate() {
+            grid.entry(bucket(p)).or_default().push(i);
+        }
 
-            reaction_matrix: reaction,
-            force_matrix: force,
+        let depth = if self.domain.is_3d() { -1..=1 } else { 0..=0 };
+        positions
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| {
+                let home = bucket(p);
+                let mut nearest = reach;
+                for rise in depth.clone() {
+                    for dy in -1..=1 {
+                        for dx in -1..=1 {
+                            let mut key = home + IVec3::new(dx, rise, dy);
+                            if wraps {
+                                key = key.rem_euclid(IVec3::splat(squares));
+                            }
+                            for &j in grid.get(&key).into_iter().flatten() {
+                                let distance = if self.domain.is_3d() {
+                                    p.distance(positions[j])
+                                } else {
+                                    self.offset(self.sites[i], self.sites[j]).length()
+                                };
+                                if j != i {
+                                    nearest = nearest.min(distance);
+                                }
+                            }
+                        }
+                    }
+                }
+                nearest
+            })
+            .collect()
+    }
 
-            friction: 0.8,        // Fluid movement
-            emission_jitter: 0.1, // Slight temperature noise
+    /// Drops the sites flagged in `removed` with their velocities, heights
+    /// and radii, keeping the rest in order.
+    pub fn retain_sites(&mut self, removed: &[bool]) {
+        fn retain<T: Clone>(values: &mut Vec<T>, removed: &[bool], fill: T) {
+            values.resize(removed.len(), fill);
+            let mut index = 0;
+            values.retain(|_| {
+                index += 1;
+                !removed[index - 1]
+            });
         }
+        retain(&mut self.sites, removed, Vec2::ZERO);
+        retain(&mut self.velocities, removed, Vec2::ZERO);
+        retain(&mut self.heights, removed, 0.0);
+        retain(&mut self.vertical_velocities, removed, 0.0);
+        retain(&mut self.radii, removed, 0.0);
+    }
+
+    /// The reaction term selected by `kinetics`.
+    pub fn reaction_kernel(&self) -> &dyn ReactionKernel {
+        self.kinetics.kernel(&self.reaction_matrix)
+    }
+
+    /// Recompiles the reaction kernel against the current species names.
+    pub fn compile_kinetics(&mut self) {
+        self.kinetics.compile(&self.species);
+    }
+
+    /// Display colour of a cell: each species' colour weighted by its concentration.
+    pub fn cell_color(&self, cell: &[f32]) -> Vec3 {
+        self.species
+            .iter()
+            .zip(cell)
+            .map(|(species, &c)| Vec3::from(species.color) * c)
+            .sum()
+    }
+
+    /// Concentration of the emission species in `cell`, or 0 if there is none.
+    pub fn cell_emission(&self, cell: &[f32]) -> f32 {
+        self.emission_species
+            .and_then(|s| cell.get(s))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// Appends `species` with no reactions or forces; see [`Simulation::add_species`](crate::Simulation::add_species).
+    pub fn push_species(&mut self, species: Species) {
+        self.species.push(species);
+        self.reaction_matrix.push();
+        self.force_matrix.push();
+    }
+
+    /// Drops species `index` with its matrix rows and columns.
+    pub fn remove_species(&mut self, index: usize) {
+        if index >= self.species.len() {
+            return;
+        }
+        self.species.remove(index);
+        self.reaction_matrix.remove(index);
+        self.force_matrix.remove(index);
+        let shift = |species: Option<usize>| match species {
+            Some(e) if e == index => None,
+            Some(e) if e > index => Some(e - 1),
+            other => other,
+        };
+        self.emission_species = shift(self.emission_species);
+        self.mechanics.area_species = shift(self.mechanics.area_species);
+        self.weighting.species = shift(self.weighting.species);
+        self.lifecycle.remove_species(index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two sites a unit apart on the map, far apart in height
+    fn pair(domain: Domain) -> SimState {
+        SimState {
+            domain,
+            sites: vec![Vec2::ZERO, Vec2::X],
+            heights: vec![-8.0, 8.0],
+            ..SimState::default()
+        }
+    }
+
+    #[test]
+    fn flat_domains_ignore_leftover_heights() {
+        assert_eq!(pair(Domain::Square).nearest_within(2.0), [1.0, 1.0]);
+        assert_eq!(pair(Domain::Disk).nearest_within(2.0), [1.0, 1.0]);
+        // Where heights count, the two are out of reach
+        assert_eq!(pair(Domain::Cube).nearest_within(2.0), [2.0, 2.0]);
     }
 }
@@ -1,8 +1,18 @@
-use crate::state::SimState;
+use bevy::ecs::system::SystemParam;
 use bevy::{post_process::bloom::Bloom, prelude::*};
 use bevy_egui::{EguiContexts, egui};
 use bevy_panorbit_camera::PanOrbitCamera;
 use rand::Rng;
+use voronoi_vivarium::chemistry::{Integrator, MassSource, Motility};
+use voronoi_vivarium::lifecycle::{Measure, Trigger};
+use voronoi_vivarium::voronoi::{DOMAIN_SIZE, Laplacian, Tessellation};
+use voronoi_vivarium::{
+    Boundary, Domain, Kinetics, Matrix, Obstacle, ReactionKernel, Simulation, Species,
+};
+
+use crate::cells::{DebugOverlay, ObstacleDraft, VolumeView};
+use crate::clock::SimClock;
+use crate::persistence::{PresetLibrary, SnapshotIo};
 
 pub fn setup_scene(mut commands: Commands) {
     // Lighting
@@ -25,6 +35,8 @@ pub fn setup_scene(mut commands: Commands) {
             focus: Vec3::ZERO,
             button_orbit: MouseButton::Right,
             button_pan: MouseButton::Middle,
+            // Keep the camera responsive while the simulation is paused
+            use_real_time: true,
             ..default()
         },
         MeshPickingCamera,
@@ -32,64 +44,427 @@ pub fn setup_scene(mut commands: Commands) {
     ));
 }
 
-// Helper for rendering 3x3 Matrix UI
-fn matrix_ui(ui: &mut egui::Ui, matrix: &mut Mat3, id_salt: &str) {
+// Upper bound for the species editor; the model itself has no limit
+const MAX_SPECIES: usize = 8;
+
+// Label colour for a species, lifted so dark species stay readable
+fn species_color32(species: &Species) -> egui::Color32 {
+    let [r, g, b] = species.color.map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8);
+    if u16::from(r) + u16::from(g) + u16::from(b) < 96 {
+        egui::Color32::LIGHT_GRAY
+    } else {
+        egui::Color32::from_rgb(r, g, b)
+    }
+}
+
+// Fills every entry not involving the `skip` species with a value in `-range..range`
+fn randomize_matrix(matrix: &mut Matrix, skip: Option<usize>, range: f32, rng: &mut impl Rng) {
+    let size = matrix.size();
+    for row in 0..size {
+        for col in 0..size {
+            if skip == Some(row) || skip == Some(col) {
+                continue;
+            }
+            *matrix.get_mut(row, col) = rng.gen_range(-range..range);
+        }
+    }
+}
+
+// Helper for rendering an NxN species Matrix UI
+fn matrix_ui(ui: &mut egui::Ui, matrix: &mut Matrix, species: &[Species], id_salt: &str) {
     egui::Grid::new(id_salt).min_col_width(30.0).show(ui, |ui| {
         // Header Row: Input Sources
         ui.label(""); // Corner
-        ui.label(egui::RichText::new("R In").color(egui::Color32::RED));
-        ui.label(egui::RichText::new("G In").color(egui::Color32::GREEN));
-        ui.label(egui::RichText::new("B In").color(egui::Color32::BLUE));
+        for s in species {
+            ui.label(egui::RichText::new(format!("{} In", s.name)).color(species_color32(s)));
+        }
         ui.end_row();
 
         // Rows: Output Targets
-        let labels = [
-            ("R Out", egui::Color32::RED),
-            ("G Out", egui::Color32::GREEN),
-            ("B Out", egui::Color32::BLUE),
-        ];
-
-        for (r, _label) in labels.iter().enumerate() {
-            ui.label(egui::RichText::new(labels[r].0).color(labels[r].1));
-            // Bevy Mat3 is Column-Major [col][row]
-            ui.add(
-                egui::DragValue::new(&mut matrix.x_axis[r])
-                    .speed(0.01)
-                    .fixed_decimals(2),
-            );
-            ui.add(
-                egui::DragValue::new(&mut matrix.y_axis[r])
-                    .speed(0.01)
-                    .fixed_decimals(2),
-            );
-            ui.add(
-                egui::DragValue::new(&mut matrix.z_axis[r])
-                    .speed(0.01)
-                    .fixed_decimals(2),
-            );
+        for (row, s) in species.iter().enumerate().take(matrix.size()) {
+            ui.label(egui::RichText::new(format!("{} Out", s.name)).color(species_color32(s)));
+            for col in 0..matrix.size() {
+                ui.add(
+                    egui::DragValue::new(matrix.get_mut(row, col))
+                        .speed(0.01)
+                        .fixed_decimals(2),
+                );
+            }
             ui.end_row();
         }
     });
 }
 
-pub fn ui_system(mut contexts: EguiContexts, mut state: ResMut<SimState>) {
+// Editor for one division/death trigger
+fn trigger_ui(ui: &mut egui::Ui, label: &str, trigger: &mut Trigger, species: &[Species]) {
+    let measure_label = |measure: Option<Measure>| match measure {
+        None => "Off".to_string(),
+        Some(Measure::RelativeArea) => "Area (× mean)".to_string(),
+        Some(Measure::Concentration(s)) => species.get(s).map_or("?".into(), |s| s.name.clone()),
+    };
+
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_salt(label)
+            .selected_text(measure_label(trigger.measure))
+            .show_ui(ui, |ui| {
+                let options = [None, Some(Measure::RelativeArea)]
+                    .into_iter()
+                    .chain((0..species.len()).map(|s| Some(Measure::Concentration(s))));
+                for measure in options {
+                    ui.selectable_value(&mut trigger.measure, measure, measure_label(measure));
+                }
+            });
+        ui.label(label);
+    });
+    if trigger.measure.is_some() {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut trigger.above, true, ">");
+            ui.selectable_value(&mut trigger.above, false, "<");
+            ui.add(egui::DragValue::new(&mut trigger.threshold).speed(0.01));
+        });
+        ui.add(
+            egui::Slider::new(&mut trigger.rate, 0.01..=10.0)
+                .logarithmic(true)
+                .text("Rate (1/s)"),
+        );
+    }
+}
+
+// Polygon domain vertices as "x, y" lines, for editing as text
+fn polygon_text(vertices: &[Vec2]) -> String {
+    vertices
+        .iter()
+        .map(|v| format!("{:.2}, {:.2}\n", v.x, v.y))
+        .collect()
+}
+
+// Parses "x, y" lines back into vertices, or `None` unless every line is a
+// point and there are at least three of them
+fn parse_polygon(text: &str) -> Option<Vec<Vec2>> {
+    let vertices = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (x, y) = line.split_once(',')?;
+            Some(Vec2::new(x.trim().parse().ok()?, y.trim().parse().ok()?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    (vertices.len() >= 3).then_some(vertices)
+}
+
+// Rounds of Lloyd's algorithm run by the "Relax" button
+pub struct RelaxIterations(usize);
+
+impl Default for RelaxIterations {
+    fn default() -> Self {
+        Self(10)
+    }
+}
+
+// Where the panel saves and loads runs and parameters
+#[derive(SystemParam)]
+pub struct Files<'w> {
+    snapshot_io: ResMut<'w, SnapshotIo>,
+    library: ResMut<'w, PresetLibrary>,
+}
+
+// How the cells are drawn, and the obstacle being drawn over them
+#[derive(SystemParam)]
+pub struct Drawing<'w> {
+    overlay: ResMut<'w, DebugOverlay>,
+    draft: ResMut<'w, ObstacleDraft>,
+    volume_view: ResMut<'w, VolumeView>,
+}
+
+pub fn ui_system(
+    mut contexts: EguiContexts,
+    mut sim: ResMut<Simulation>,
+    mut clock: ResMut<SimClock>,
+    files: Files,
+    drawing: Drawing,
+    mut relax_iterations: Local<RelaxIterations>,
+    mut polygon_buffer: Local<String>,
+) {
+    let Files {
+        mut snapshot_io,
+        mut library,
+    } = files;
+    let Drawing {
+        mut overlay,
+        mut draft,
+        mut volume_view,
+    } = drawing;
+    let mut restart = false;
+    let mut new_domain = None;
+    let mut new_obstacle = None;
+    let mut clear_obstacles = false;
+    let mut randomize_radii = false;
+    let mut relax = None;
+    let mut save_snapshot = false;
+    let mut add_species = false;
+    let mut remove_species = None;
+    let Simulation {
+        state,
+        rng,
+        topology,
+        last_substeps,
+        ..
+    } = &mut *sim;
     if let Ok(ctx) = contexts.ctx_mut() {
         egui::Window::new("Vivarium Controls")
             .default_width(300.0)
             .show(ctx, |ui| {
+                egui::CollapsingHeader::new("Time")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            let label = if clock.paused {
+                                "▶ Resume"
+                            } else {
+                                "⏸ Pause"
+                            };
+                            if ui.button(label).clicked() {
+                                clock.paused = !clock.paused;
+                            }
+                            if ui
+                                .add_enabled(clock.paused, egui::Button::new("⏭ Step"))
+                                .clicked()
+                            {
+                                clock.step_requested = true;
+                            }
+                        });
+
+                        ui.add(
+                            egui::Slider::new(&mut clock.speed, 0.1..=8.0)
+                                .logarithmic(true)
+                                .text("Speed"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut clock.tick_rate, 10.0..=240.0)
+                                .text("Tick Rate (Hz)"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut clock.substeps, 1..=16)
+                                .text("Substeps per Tick"),
+                        );
+                    });
+
+                ui.separator();
+
+                egui::CollapsingHeader::new("Presets")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        let selected_name = library
+                            .get(library.selected)
+                            .map(|p| p.name.clone())
+                            .unwrap_or_default();
+                        let mut selected = library.selected;
+                        egui::ComboBox::from_id_salt("preset_combo")
+                            .selected_text(selected_name)
+                            .show_ui(ui, |ui| {
+                                for (i, preset) in library.builtin.iter().enumerate() {
+                                    ui.selectable_value(&mut selected, i, &preset.name);
+                                }
+                                if !library.user.is_empty() {
+                                    ui.separator();
+                                }
+                                for (i, preset) in library.user.iter().enumerate() {
+                                    let index = library.builtin.len() + i;
+                                    ui.selectable_value(
+                                        &mut selected,
+                                        index,
+                                        format!("★ {}", preset.name),
+                                    );
+                                }
+                            });
+                        library.selected = selected;
+
+                        ui.horizontal(|ui| {
+                            if ui.button("Apply").clicked()
+                                && let Some(preset) = library.get(library.selected)
+                            {
+                                preset.apply(state);
+                            }
+                            let deletable = library.is_user(library.selected);
+                            if ui
+                                .add_enabled(deletable, egui::Button::new("🗑 Delete"))
+                                .clicked()
+                            {
+                                library.delete_selected();
+                            }
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::TextEdit::singleline(&mut library.new_name)
+                                    .hint_text("Preset name")
+                                    .desired_width(140.0),
+                            );
+                            if ui.button("💾 Save Current").clicked() {
+                                library.save_current(state);
+                            }
+                        });
+
+                        if let Some(status) = &library.status {
+                            ui.label(status);
+                        }
+                    });
+
+                ui.separator();
+
+                egui::CollapsingHeader::new("Snapshots")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            if ui.button("💾 Save").clicked() {
+                                save_snapshot = true;
+                            }
+                            if ui.button("📂 Load").clicked() {
+                                snapshot_io.load();
+                            }
+                        });
+                        if let Some(status) = &snapshot_io.status {
+                            ui.label(status);
+                        }
+                    });
+
+                ui.separator();
+
                 egui::CollapsingHeader::new("Topology & Simulation")
                     .default_open(true)
                     .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Seed");
+                            ui.add(egui::DragValue::new(&mut state.seed));
+                            if ui.button("🎲").clicked() {
+                                state.seed = rng.r#gen();
+                                restart = true;
+                            }
+                            if ui.button("Restart").clicked() {
+                                restart = true;
+                            }
+                        });
+
                         let mut count = state.cell_count;
+                        let max_cells = state.max_cells();
                         if ui
-                            .add(egui::Slider::new(&mut count, 10..=1500).text("Cell Count"))
+                            .add(
+                                egui::Slider::new(&mut count, 10..=max_cells)
+                                    .logarithmic(max_cells > Tessellation::Polygons.max_cells())
+                                    .text("Cell Count"),
+                            )
                             .changed()
                         {
                             state.cell_count = count;
                             state.rebuild_requested = true;
                         }
+                        ui.add_enabled_ui(state.domain.is_flat(), |ui| {
+                            egui::ComboBox::from_label("Tessellation")
+                                .selected_text(state.tessellation.label())
+                                .show_ui(ui, |ui| {
+                                    for tessellation in Tessellation::ALL {
+                                        if ui
+                                            .selectable_value(
+                                                &mut state.tessellation,
+                                                tessellation,
+                                                tessellation.label(),
+                                            )
+                                            .changed()
+                                        {
+                                            state.rebuild_requested = true;
+                                        }
+                                    }
+                                });
+                        });
 
-                        if ui.checkbox(&mut state.wrap_enabled, "Torus Wrap").changed() {
+                        egui::ComboBox::from_label("Domain")
+                            .selected_text(state.domain.label())
+                            .show_ui(ui, |ui| {
+                                for domain in Domain::all() {
+                                    let current = std::mem::discriminant(&state.domain)
+                                        == std::mem::discriminant(&domain);
+                                    if ui.selectable_label(current, domain.label()).clicked()
+                                        && !current
+                                    {
+                                        new_domain = Some(domain);
+                                    }
+                                }
+                            });
+                        match &state.domain {
+                            Domain::Rectangle { aspect } => {
+                                let mut aspect = *aspect;
+                                if ui
+                                    .add(
+                                        egui::Slider::new(&mut aspect, 0.25..=4.0)
+                                            .logarithmic(true)
+                                            .text("Aspect"),
+                                    )
+                                    .changed()
+                                {
+                                    new_domain = Some(Domain::Rectangle { aspect });
+                                }
+                            }
+                            Domain::Annulus { inner } => {
+                                let mut inner = *inner;
+                                if ui
+                                    .add(
+                                        egui::Slider::new(&mut inner, 0.1..=0.9)
+                                            .text("Inner Radius"),
+                                    )
+                                    .changed()
+                                {
+                                    new_domain = Some(Domain::Annulus { inner });
+                                }
+                            }
+                            Domain::Polygon(vertices) => {
+                                if polygon_buffer.is_empty() {
+                                    *polygon_buffer = polygon_text(vertices);
+                                }
+                                ui.label("Vertices (x, y per line)");
+                                ui.add(
+                                    egui::TextEdit::multiline(&mut *polygon_buffer)
+                                        .code_editor()
+                                        .desired_rows(4),
+                                );
+                                let parsed = parse_polygon(&polygon_buffer);
+                                ui.horizontal(|ui| {
+                                    if ui
+                                        .add_enabled(parsed.is_some(), egui::Button::new("Apply"))
+                                        .clicked()
+                                    {
+                                        new_domain = parsed.map(Domain::Polygon);
+                                    }
+                                    if ui.button("Reset").clicked() {
+                                        *polygon_buffer = polygon_text(vertices);
+                                    }
+                                });
+                            }
+                            Domain::Cube => {
+                                // Copied so the meshes rebuild only on a real change
+                                let mut view = *volume_view;
+                                ui.horizontal(|ui| {
+                                    ui.radio_value(&mut view.slice, false, "Translucent");
+                                    ui.radio_value(&mut view.slice, true, "Slice");
+                                });
+                                if view.slice {
+                                    let half = (DOMAIN_SIZE / 2.0) as f32;
+                                    ui.add(
+                                        egui::Slider::new(&mut view.height, -half..=half)
+                                            .text("Slice Height"),
+                                    );
+                                }
+                                volume_view.set_if_neq(view);
+                            }
+                            Domain::Square | Domain::Disk | Domain::Sphere => {}
+                        }
+
+                        if ui
+                            .add_enabled(
+                                state.domain.can_wrap(),
+                                egui::Checkbox::new(&mut state.wrap_enabled, "Torus Wrap"),
+                            )
+                            .changed()
+                        {
                             state.sites.clear();
                             state.rebuild_requested = true;
                         }
@@ -98,11 +473,155 @@ pub fn ui_system(mut contexts: EguiContexts, mut state: ResMut<SimState>) {
                             state.rebuild_requested = true;
                         }
 
-                        ui.add(egui::Slider::new(&mut state.friction, 0.0..=1.0).text("Friction"));
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut overlay.ghosts, "Show Ghosts");
+                            ui.label(format!("{} ghosts", topology.ghosts().count()));
+                        });
+
+                        egui::ComboBox::from_label("Motility")
+                            .selected_text(state.motility.label())
+                            .show_ui(ui, |ui| {
+                                for motility in Motility::ALL {
+                                    ui.selectable_value(
+                                        &mut state.motility,
+                                        motility,
+                                        motility.label(),
+                                    );
+                                }
+                            });
+                        match state.motility {
+                            Motility::Overdamped => {
+                                ui.add(
+                                    egui::Slider::new(&mut state.friction, 0.0..=1.0)
+                                        .text("Friction"),
+                                );
+                            }
+                            Motility::Inertial => {
+                                ui.add(
+                                    egui::Slider::new(&mut state.damping, 0.0..=5.0)
+                                        .text("Damping (1/s)"),
+                                );
+                                egui::ComboBox::from_label("Mass")
+                                    .selected_text(state.mass_source.label())
+                                    .show_ui(ui, |ui| {
+                                        for source in MassSource::ALL {
+                                            ui.selectable_value(
+                                                &mut state.mass_source,
+                                                source,
+                                                source.label(),
+                                            );
+                                        }
+                                    });
+                            }
+                        }
                         ui.add(
                             egui::Slider::new(&mut state.emission_jitter, 0.0..=1.0)
                                 .text("Temp (Jitter)"),
                         );
+                        ui.add(
+                            egui::Slider::new(&mut state.relaxation, 0.0..=5.0)
+                                .text("Lloyd Relaxation"),
+                        );
+                        ui.horizontal(|ui| {
+                            if ui.button("Relax").clicked() {
+                                relax = Some(relax_iterations.0);
+                            }
+                            ui.add(egui::DragValue::new(&mut relax_iterations.0).range(1..=200));
+                            ui.label("iterations");
+                        });
+                    });
+
+                ui.separator();
+
+                egui::CollapsingHeader::new("Obstacles")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        ui.label(format!("{} obstacles", state.obstacles.len()));
+                        if !state.domain.is_flat() {
+                            ui.label("Only flat domains have obstacles");
+                        } else if draft.active {
+                            ui.label(format!(
+                                "Click cells to add corners ({} so far); two make a wall",
+                                draft.points.len()
+                            ));
+                            ui.horizontal(|ui| {
+                                if ui
+                                    .add_enabled(
+                                        draft.points.len() >= 2,
+                                        egui::Button::new("✔ Finish"),
+                                    )
+                                    .clicked()
+                                {
+                                    new_obstacle =
+                                        Some(Obstacle::new(std::mem::take(&mut draft.points)));
+                                    draft.active = false;
+                                }
+                                if ui.button("↩ Undo").clicked() {
+                                    draft.points.pop();
+                                }
+                                if ui.button("✖ Cancel").clicked() {
+                                    draft.points.clear();
+                                    draft.active = false;
+                                }
+                            });
+                        } else {
+                            ui.horizontal(|ui| {
+                                if ui.button("✏ Draw").clicked() {
+                                    draft.active = true;
+                                }
+                                if ui
+                                    .add_enabled(
+                                        !state.obstacles.is_empty(),
+                                        egui::Button::new("🗑 Clear All"),
+                                    )
+                                    .clicked()
+                                {
+                                    clear_obstacles = true;
+                                }
+                            });
+                        }
+                    });
+
+                ui.separator();
+
+                egui::CollapsingHeader::new("Species")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        let count = state.species.len();
+                        let mut renamed = false;
+                        egui::Grid::new("species_grid").show(ui, |ui| {
+                            ui.label("Name");
+                            ui.label("Colour");
+                            ui.label("Emission");
+                            ui.end_row();
+
+                            for (s, species) in state.species.iter_mut().enumerate() {
+                                renamed |= ui
+                                    .add(
+                                        egui::TextEdit::singleline(&mut species.name)
+                                            .desired_width(60.0),
+                                    )
+                                    .changed();
+                                ui.color_edit_button_rgb(&mut species.color);
+                                ui.radio_value(&mut state.emission_species, Some(s), "");
+                                if ui.add_enabled(count > 1, egui::Button::new("🗑")).clicked() {
+                                    remove_species = Some(s);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                        // Typed reaction rules refer to species by name
+                        if renamed {
+                            state.compile_kinetics();
+                        }
+
+                        ui.radio_value(&mut state.emission_species, None, "No emission species");
+                        if ui
+                            .add_enabled(count < MAX_SPECIES, egui::Button::new("➕ Add Species"))
+                            .clicked()
+                        {
+                            add_species = true;
+                        }
                     });
 
                 ui.separator();
@@ -110,26 +629,78 @@ pub fn ui_system(mut contexts: EguiContexts, mut state: ResMut<SimState>) {
                 egui::CollapsingHeader::new("Reaction Rules (Alchemy)")
                     .default_open(true)
                     .show(ui, |ui| {
-                        ui.horizontal(|ui| {
-                            ui.label("Equation: dC/dt = M * C");
-                            if ui.button("🎲 Randomize").clicked() {
-                                let mut rng = rand::thread_rng();
-                                for c in 0..3 {
-                                    for r in 0..3 {
-                                        // Random range -1.0 to 1.0
-                                        // Positive = Catalyze, Negative = Inhibit
-                                        let val = rng.gen_range(-1.0..1.0);
-                                        match c {
-                                            0 => state.reaction_matrix.x_axis[r] = val,
-                                            1 => state.reaction_matrix.y_axis[r] = val,
-                                            2 => state.reaction_matrix.z_axis[r] = val,
-                                            _ => {}
-                                        }
+                        egui::ComboBox::from_label("Model")
+                            .selected_text(state.kinetics.label())
+                            .show_ui(ui, |ui| {
+                                for kinetics in Kinetics::all() {
+                                    let selected = state.kinetics.label() == kinetics.label();
+                                    if ui.selectable_label(selected, kinetics.label()).clicked()
+                                        && !selected
+                                    {
+                                        state.kinetics = kinetics;
+                                        state.compile_kinetics();
                                     }
                                 }
+                            });
+
+                        let equation = state.reaction_kernel().equation();
+                        if let Kinetics::Expression(rules) = &mut state.kinetics {
+                            ui.label(
+                                "One line per species, e.g. dR = 0.4*G - R*B^2 + feed*(1 - R)",
+                            );
+                            ui.label(
+                                "Use species names, avg(X) for the neighbour mean, area, \
+                                 and any other name as a parameter",
+                            );
+                            let edit = ui.add(
+                                egui::TextEdit::multiline(&mut rules.source)
+                                    .code_editor()
+                                    .desired_rows(4)
+                                    .desired_width(f32::INFINITY),
+                            );
+                            if edit.changed() {
+                                rules.compile(&state.species);
                             }
-                        });
-                        matrix_ui(ui, &mut state.reaction_matrix, "reaction_matrix");
+                            // Broken lines contribute nothing until fixed
+                            for error in rules.errors() {
+                                ui.colored_label(egui::Color32::LIGHT_RED, error.to_string());
+                            }
+                            for param in rules.params() {
+                                ui.add(
+                                    egui::Slider::new(param.value, param.range)
+                                        .text(param.name)
+                                        .clamping(egui::SliderClamping::Never),
+                                );
+                            }
+                        } else if state.kinetics == Kinetics::Linear {
+                            ui.horizontal(|ui| {
+                                ui.label(format!("Equation: {equation}"));
+                                if ui.button("🎲 Randomize").clicked() {
+                                    // Random range -1.0 to 1.0
+                                    // Positive = Catalyze, Negative = Inhibit
+                                    randomize_matrix(
+                                        &mut state.reaction_matrix,
+                                        state.emission_species,
+                                        1.0,
+                                        rng,
+                                    );
+                                }
+                            });
+                            matrix_ui(
+                                ui,
+                                &mut state.reaction_matrix,
+                                &state.species,
+                                "reaction_matrix",
+                            );
+                        } else {
+                            ui.label(equation);
+                            ui.label("U and V are the first two species");
+                            for param in state.kinetics.params() {
+                                ui.add(
+                                    egui::Slider::new(param.value, param.range).text(param.name),
+                                );
+                            }
+                        }
                     });
 
                 ui.separator();
@@ -140,23 +711,138 @@ pub fn ui_system(mut contexts: EguiContexts, mut state: ResMut<SimState>) {
                         ui.horizontal(|ui| {
                             ui.label("Equation: Force = Self * M * Neighbor");
                             if ui.button("🎲 Randomize").clicked() {
-                                let mut rng = rand::thread_rng();
-                                for c in 0..3 {
-                                    for r in 0..3 {
-                                        // Random range -0.5 to 0.5 (forces need to be gentler)
-                                        let val = rng.gen_range(-0.5..0.5);
-                                        match c {
-                                            0 => state.force_matrix.x_axis[r] = val,
-                                            1 => state.force_matrix.y_axis[r] = val,
-                                            2 => state.force_matrix.z_axis[r] = val,
-                                            _ => {}
-                                        }
-                                    }
-                                }
+                                // Random range -0.5 to 0.5 (forces need to be gentler)
+                                randomize_matrix(
+                                    &mut state.force_matrix,
+                                    state.emission_species,
+                                    0.5,
+                                    rng,
+                                );
                             }
                         });
                         ui.label("Pos = Attract, Neg = Repel");
-                        matrix_ui(ui, &mut state.force_matrix, "force_matrix");
+                        matrix_ui(ui, &mut state.force_matrix, &state.species, "force_matrix");
+                    });
+
+                ui.separator();
+
+                egui::CollapsingHeader::new("Tissue Mechanics")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        let mechanics = &mut state.mechanics;
+                        ui.label("E = K_A (A/A0 - 1)² + K_P (P - p0 √A0)² / A0");
+                        ui.add(
+                            egui::Slider::new(&mut mechanics.area_stiffness, 0.0..=20.0)
+                                .text("Area Stiffness (K_A)"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut mechanics.perimeter_stiffness, 0.0..=5.0)
+                                .text("Perimeter Stiffness (K_P)"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut mechanics.target_shape, 3.0..=5.0)
+                                .text("Target Shape (p0)"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut mechanics.target_area, 0.2..=3.0)
+                                .text("Target Area (× mean)"),
+                        );
+
+                        let selected = mechanics
+                            .area_species
+                            .and_then(|s| state.species.get(s))
+                            .map_or("None", |s| s.name.as_str());
+                        egui::ComboBox::from_label("Area Driven By")
+                            .selected_text(selected)
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut mechanics.area_species, None, "None");
+                                for (s, species) in state.species.iter().enumerate() {
+                                    ui.selectable_value(
+                                        &mut mechanics.area_species,
+                                        Some(s),
+                                        &species.name,
+                                    );
+                                }
+                            });
+                        if mechanics.area_species.is_some() {
+                            ui.add(
+                                egui::Slider::new(&mut mechanics.area_gain, -1.0..=5.0)
+                                    .text("Area Gain"),
+                            );
+                        }
+                    });
+
+                ui.separator();
+
+                egui::CollapsingHeader::new("Cell Radii (Power Diagram)")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        let weighting = &mut state.weighting;
+                        if state.domain.is_sphere() {
+                            ui.label("The sphere ignores cell radii");
+                        }
+                        let selected = weighting
+                            .species
+                            .and_then(|s| state.species.get(s))
+                            .map_or("None", |s| s.name.as_str());
+                        egui::ComboBox::from_label("Radius Driven By")
+                            .selected_text(selected)
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut weighting.species, None, "None");
+                                for (s, species) in state.species.iter().enumerate() {
+                                    ui.selectable_value(
+                                        &mut weighting.species,
+                                        Some(s),
+                                        &species.name,
+                                    );
+                                }
+                            });
+                        if weighting.species.is_some() {
+                            ui.add(
+                                egui::Slider::new(&mut weighting.gain, 0.0..=2.0)
+                                    .text("Radius Gain"),
+                            );
+                        }
+                        if ui
+                            .add(
+                                egui::Slider::new(&mut weighting.max_radius, 0.0..=1.0)
+                                    .text("Max Radius (× spacing)"),
+                            )
+                            .changed()
+                        {
+                            state.rebuild_requested = true;
+                        }
+                        if weighting.species.is_none() {
+                            ui.horizontal(|ui| {
+                                if ui.button("Randomize").clicked() {
+                                    randomize_radii = true;
+                                }
+                                if ui.button("Reset").clicked() {
+                                    state.radii.fill(0.0);
+                                    state.rebuild_requested = true;
+                                }
+                            });
+                        }
+                    });
+
+                ui.separator();
+
+                egui::CollapsingHeader::new("Division & Death")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        let max_cells = state.max_cells();
+                        let lifecycle = &mut state.lifecycle;
+                        trigger_ui(ui, "Divide When", &mut lifecycle.division, &state.species);
+                        trigger_ui(ui, "Die When", &mut lifecycle.death, &state.species);
+                        ui.checkbox(&mut lifecycle.partition, "Daughters Split Chemicals");
+                        ui.add(
+                            egui::Slider::new(&mut lifecycle.max_cells, 10..=max_cells)
+                                .logarithmic(max_cells > Tessellation::Polygons.max_cells())
+                                .text("Max Cells"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut lifecycle.min_cells, 0..=500).text("Min Cells"),
+                        );
                     });
 
                 ui.separator();
@@ -164,22 +850,49 @@ pub fn ui_system(mut contexts: EguiContexts, mut state: ResMut<SimState>) {
                 egui::CollapsingHeader::new("Base Physics")
                     .default_open(true)
                     .show(ui, |ui| {
+                        egui::ComboBox::from_label("Laplacian")
+                            .selected_text(state.laplacian.label())
+                            .show_ui(ui, |ui| {
+                                for laplacian in Laplacian::ALL {
+                                    ui.selectable_value(
+                                        &mut state.laplacian,
+                                        laplacian,
+                                        laplacian.label(),
+                                    );
+                                }
+                            });
+                        egui::ComboBox::from_label("Integrator")
+                            .selected_text(state.integrator.label())
+                            .show_ui(ui, |ui| {
+                                for integrator in Integrator::ALL {
+                                    ui.selectable_value(
+                                        &mut state.integrator,
+                                        integrator,
+                                        integrator.label(),
+                                    );
+                                }
+                            });
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut state.adaptive_step, "Adaptive Substeps");
+                            ui.label(format!("({last_substeps} per step)"));
+                        });
+                        ui.checkbox(&mut state.saturate, "Saturate at 1");
+
                         if ui.button("🎲 Randomize Physics").clicked() {
-                            let mut rng = rand::thread_rng();
-                            // Diffusion: 0.5 to 2.0
-                            state.diffusion_rates = Vec4::new(
-                                rng.gen_range(0.1..2.0),
-                                rng.gen_range(0.1..2.0),
-                                rng.gen_range(0.1..2.0),
-                                rng.gen_range(0.1..4.0), // Emission diffuses faster
-                            );
+                            // Diffusion: 0.1 to 2.0
+                            for (i, species) in state.species.iter_mut().enumerate() {
+                                let emission = state.emission_species == Some(i);
+                                // Emission diffuses faster
+                                let max = if emission { 4.0 } else { 2.0 };
+                                species.diffusion = rng.gen_range(0.1..max);
+                            }
                             // Decay: 0.01 to 0.4 (keep it low to sustain life)
-                            state.decay_rates = Vec4::new(
-                                rng.gen_range(0.01..0.4),
-                                rng.gen_range(0.01..0.4),
-                                rng.gen_range(0.01..0.4),
-                                rng.gen_range(0.1..0.8), // Emission decays faster
-                            );
+                            for (i, species) in state.species.iter_mut().enumerate() {
+                                let emission = state.emission_species == Some(i);
+                                // Emission decays faster
+                                let range = if emission { 0.1..0.8 } else { 0.01..0.4 };
+                                species.decay = rng.gen_range(range);
+                            }
                         }
 
                         egui::Grid::new("diff_decay_grid")
@@ -188,29 +901,99 @@ pub fn ui_system(mut contexts: EguiContexts, mut state: ResMut<SimState>) {
                                 ui.label("Ch");
                                 ui.label("Diff");
                                 ui.label("Decay");
+                                ui.label("Boundary");
                                 ui.end_row();
 
-                                ui.label("R");
-                                ui.add(egui::Slider::new(&mut state.diffusion_rates.x, 0.0..=2.0));
-                                ui.add(egui::Slider::new(&mut state.decay_rates.x, 0.0..=1.0));
-                                ui.end_row();
-
-                                ui.label("G");
-                                ui.add(egui::Slider::new(&mut state.diffusion_rates.y, 0.0..=2.0));
-                                ui.add(egui::Slider::new(&mut state.decay_rates.y, 0.0..=1.0));
-                                ui.end_row();
+                                for (i, species) in state.species.iter_mut().enumerate() {
+                                    let emission = state.emission_species == Some(i);
+                                    let (max_diffusion, max_decay) =
+                                        if emission { (4.0, 2.0) } else { (2.0, 1.0) };
 
-                                ui.label("B");
-                                ui.add(egui::Slider::new(&mut state.diffusion_rates.z, 0.0..=2.0));
-                                ui.add(egui::Slider::new(&mut state.decay_rates.z, 0.0..=1.0));
-                                ui.end_row();
-
-                                ui.label("E");
-                                ui.add(egui::Slider::new(&mut state.diffusion_rates.w, 0.0..=4.0));
-                                ui.add(egui::Slider::new(&mut state.decay_rates.w, 0.0..=2.0));
-                                ui.end_row();
+                                    ui.label(
+                                        egui::RichText::new(&species.name)
+                                            .color(species_color32(species)),
+                                    );
+                                    ui.add(egui::Slider::new(
+                                        &mut species.diffusion,
+                                        0.0..=max_diffusion,
+                                    ));
+                                    ui.add(egui::Slider::new(&mut species.decay, 0.0..=max_decay));
+                                    ui.horizontal(|ui| {
+                                        egui::ComboBox::from_id_salt(("boundary", i))
+                                            .selected_text(species.boundary.label())
+                                            .show_ui(ui, |ui| {
+                                                for boundary in Boundary::all() {
+                                                    let selected = species.boundary.label()
+                                                        == boundary.label();
+                                                    if ui
+                                                        .selectable_label(
+                                                            selected,
+                                                            boundary.label(),
+                                                        )
+                                                        .clicked()
+                                                        && !selected
+                                                    {
+                                                        species.boundary = boundary;
+                                                    }
+                                                }
+                                            });
+                                        // Value held at the wall by a reservoir
+                                        if let Boundary::Fixed(value) = &mut species.boundary {
+                                            ui.add(
+                                                egui::DragValue::new(value)
+                                                    .speed(0.01)
+                                                    .range(0.0..=10.0),
+                                            );
+                                        }
+                                    });
+                                    ui.end_row();
+                                }
                             });
+                        if state.wraps() {
+                            ui.label("Boundaries only apply without Torus Wrap");
+                        }
                     });
             });
     }
+
+    if restart {
+        sim.reset();
+    }
+    if let Some(domain) = new_domain {
+        if let Domain::Polygon(vertices) = &domain {
+            *polygon_buffer = polygon_text(vertices);
+        }
+        if !domain.is_flat() {
+            draft.points.clear();
+            draft.active = false;
+        }
+        sim.set_domain(domain);
+    }
+    // Back within what the tessellation (or a curved domain) can handle
+    if sim.state.cell_count > sim.state.max_cells() {
+        sim.state.cell_count = sim.state.max_cells();
+        sim.state.rebuild_requested = true;
+    }
+    if let Some(obstacle) = new_obstacle {
+        sim.add_obstacle(obstacle);
+    }
+    if clear_obstacles {
+        sim.clear_obstacles();
+    }
+    if randomize_radii {
+        sim.randomize_radii();
+    }
+    if let Some(iterations) = relax {
+        sim.relax(iterations);
+    }
+    if let Some(index) = remove_species {
+        sim.remove_species(index);
+    }
+    if add_species {
+        let name = format!("S{}", sim.state.species.len());
+        sim.add_species(Species::new(name, [0.5, 0.5, 0.5], 0.3, 0.3));
+    }
+    if save_snapshot {
+        snapshot_io.save(&sim);
+    }
 }
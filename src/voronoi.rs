@@ -1,267 +1,1304 @@
-use bevy::camera::primitives::Aabb;
+use bevy::math::DVec3;
 use bevy::prelude::*;
 use rand::Rng;
-use std::collections::HashSet;
-use voronator::VoronoiDiagram;
-use voronator::delaunator::{self, Point};
+use serde::{Deserialize, Serialize};
 
-use crate::chemistry::{CellMap, Chemicals, Neighbors, NextChemicals};
+use crate::chemistry::Chemicals;
+use crate::delaunay::{Cell, Delaunay, Edge};
+use crate::domain::{Domain, Obstacle, polygon_contains};
+use crate::foam::{self, Solid};
+use crate::raster::{self, OUTSIDE, Raster};
+use crate::sphere::{self, SPHERE_RADIUS};
 use crate::state::SimState;
 
 pub const DOMAIN_SIZE: f64 = 20.0;
 
-// 1. Component to track identity across mesh rebuilds
-#[derive(Component)]
-pub struct CellIndex(pub usize);
-
-pub fn spawn_mesh_system(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut state: ResMut<SimState>,
-    mut cell_map: ResMut<CellMap>,
-    // Note: We don't query for cleanup anymore, we manage IDs in cell_map
-) {
-    if !state.rebuild_requested {
-        return;
-    }
-
-    // 1. Synchronize Sites with Cell Count
-    let mut rng = rand::thread_rng();
-    let half_size = DOMAIN_SIZE / 2.0;
+// Cells smaller than this are treated as degenerate
+const MIN_CELL_AREA: f32 = 1e-6;
+
+// Floor on the distance from a centroid to a wall, so slivers along the
+// boundary do not couple to it without limit
+const MIN_WALL_DISTANCE: f32 = 1e-3;
+
+// Mean length of a line over that of the pixel staircase along it, taken
+// over every direction
+const STAIRCASE: f32 = std::f32::consts::FRAC_PI_4;
+
+// Attempts at a new site outside every obstacle before settling for one inside
+const MAX_PLACEMENTS: usize = 100;
+
+// Nearest sites tried as neighbours of a foam cell when the sites cannot be
+// tetrahedralised (all in one plane, say), so the fallback stays linear
+const FALLBACK_CANDIDATES: usize = 32;
+
+// Points this close when clipping (in sides along a cell's boundary, or in
+// domain units) are taken as one
+const SEAM_TOLERANCE: f32 = 1e-4;
+
+/// How diffusion couples a cell to its neighbours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Laplacian {
+    /// Every neighbour counts equally (graph Laplacian).
+    #[default]
+    Uniform,
+    /// Flux weighted by shared edge length over site distance, per unit cell area.
+    FiniteVolume,
+}
+
+impl Laplacian {
+    pub const ALL: [Self; 2] = [Self::Uniform, Self::FiniteVolume];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Uniform => "Uniform (Graph)",
+            Self::FiniteVolume => "Finite Volume",
+        }
+    }
+}
+
+/// How the cells of a flat domain are worked out from the sites.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Tessellation {
+    /// Exact polygons from the Delaunay triangulation.
+    #[default]
+    Polygons,
+    /// Pixels labelled with their nearest site (see [`crate::raster`]):
+    /// coarser, but fast enough for 10k–100k cells.
+    Raster,
+}
+
+impl Tessellation {
+    pub const ALL: [Self; 2] = [Self::Polygons, Self::Raster];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Polygons => "Polygons (Exact)",
+            Self::Raster => "Raster (Large)",
+        }
+    }
+
+    /// Most cells it is meant for.
+    pub fn max_cells(self) -> usize {
+        match self {
+            Self::Polygons => 1500,
+            Self::Raster => 100_000,
+        }
+    }
+}
+
+/// Turns the Voronoi diagram into a power diagram. Each cell has a radius
+/// `r` (in [`SimState::radii`]) and the border between two cells lies where
+/// `|x - s|² - r²` is the same for both, so a larger radius takes room from
+/// the neighbours without the site moving. Radii are in typical cell
+/// spacings; all zero gives the plain diagram. The sphere ignores them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Weighting {
+    /// Species that sets every radius each step, as `gain · c`; `None`
+    /// leaves the radii as they are.
+    pub species: Option<usize>,
+    pub gain: f32,
+    /// Cap on the radii. Large ones can squeeze a crowded neighbour out
+    /// of the diagram altogether.
+    pub max_radius: f32,
+}
+
+impl Default for Weighting {
+    fn default() -> Self {
+        Self {
+            species: None,
+            gain: 0.5,
+            max_radius: 0.5,
+        }
+    }
+}
+
+impl Weighting {
+    /// Sets `radii` from the weighting species, if there is one. Returns
+    /// `true` if any changed (and the topology needs rebuilding).
+    pub fn follow(&self, radii: &mut Vec<f32>, chemicals: &Chemicals) -> bool {
+        let Some(species) = self.species else {
+            return false;
+        };
+        radii.resize(chemicals.len(), 0.0);
+        let mut changed = false;
+        for (radius, cell) in radii.iter_mut().zip(chemicals.iter()) {
+            let target = cell.get(species).map_or(0.0, |c| self.gain * c);
+            changed |= *radius != target;
+            *radius = target;
+        }
+        changed
+    }
+}
+
+/// Adjacency and cell geometry derived from the current sites.
+#[derive(Debug, Clone, Default)]
+pub struct Topology {
+    /// Indices of the Delaunay neighbours of each cell, in ascending order.
+    pub neighbors: Vec<Vec<usize>>,
+    /// Voronoi polygon of each cell in domain (XY) coordinates, counter-clockwise
+    /// and clipped to the domain shape when not wrapping. Clipped cells may
+    /// be concave. Degenerate cells have fewer than three points. On the
+    /// sphere these are the corners mapped like the sites, so cells across
+    /// the date line are torn; see `globe` for their real shape. Empty
+    /// altogether for the raster tessellation; see [`Topology::raster`].
+    pub polygons: Vec<Vec<Vec2>>,
+    /// On the sphere, the corners of each cell on the globe (centred on the
+    /// origin, with radius [`SPHERE_RADIUS`]), in the order of `polygons`.
+    /// Empty on flat domains.
+    pub globe: Vec<Vec<Vec3>>,
+    /// In the 3D domain, the polyhedron of each cell in world coordinates
+    /// (see [`crate::foam`]); `polygons` are then empty. Empty otherwise.
+    pub solids: Vec<Solid>,
+    /// Area of each polygon (on the globe, of the spherical polygon; in 3D,
+    /// the volume of the cell).
+    pub areas: Vec<f32>,
+    /// Perimeter of each polygon (in 3D, surface area).
+    pub perimeters: Vec<f32>,
+    /// Length of the edge shared with each neighbour, parallel to `neighbors`.
+    /// Only the part inside the domain counts, and neighbours whose edge
+    /// lies entirely outside it (across a notch or hole) are left out. In
+    /// 3D, the area of the shared face.
+    pub edge_lengths: Vec<Vec<f32>>,
+    /// Centroid of each polygon, or the site itself for degenerate cells.
+    /// In 3D, the centroid's XY part (its height is in `solids`).
+    pub centroids: Vec<Vec2>,
+    /// Finite-volume coupling to each neighbour, parallel to `neighbors`:
+    /// shared edge length / site distance / own cell area.
+    pub flux_weights: Vec<Vec<f32>>,
+    /// Finite-volume coupling of each cell to the domain boundary: the
+    /// length of its walls / twice their distance from the centroid / cell
+    /// area. Zero for cells clear of the boundary, and for all of them when
+    /// wrapping.
+    pub boundary_weights: Vec<f32>,
+    // Kept so the next update can repair it instead of starting over
+    delaunay: Option<Delaunay>,
+    raster: Option<Raster>,
+    // What the cells were cut to, so a repair only keeps cells cut alike
+    domain: Domain,
+    obstacles: Vec<Obstacle>,
+}
+
+impl Topology {
+    /// Neighbours of `cell` paired with their diffusion weight under `laplacian`.
+    pub fn couplings(
+        &self,
+        cell: usize,
+        laplacian: Laplacian,
+    ) -> impl Iterator<Item = (usize, f32)> + '_ {
+        let neighbors = self.neighbors.get(cell).map_or(&[][..], Vec::as_slice);
+        let weights = self.flux_weights.get(cell).map_or(&[][..], Vec::as_slice);
+        neighbors.iter().enumerate().map(move |(k, &j)| {
+            let weight = match laplacian {
+                Laplacian::Uniform => 1.0,
+                Laplacian::FiniteVolume => weights.get(k).copied().unwrap_or(0.0),
+            };
+            (j, weight)
+        })
+    }
+
+    /// Diffusion weight between `cell` and the outside of the domain under
+    /// `laplacian`, 0 unless the cell touches the boundary. The uniform
+    /// Laplacian counts the outside as one more neighbour.
+    pub fn boundary_coupling(&self, cell: usize, laplacian: Laplacian) -> f32 {
+        let weight = self.boundary_weights.get(cell).copied().unwrap_or(0.0);
+        match laplacian {
+            Laplacian::Uniform if weight > 0.0 => 1.0,
+            Laplacian::Uniform => 0.0,
+            Laplacian::FiniteVolume => weight,
+        }
+    }
+
+    /// Largest total diffusion weight of any cell, boundary included, which
+    /// bounds how stiff diffusion can get (the neighbour count for the
+    /// uniform Laplacian).
+    pub fn max_coupling(&self, laplacian: Laplacian) -> f32 {
+        (0..self.neighbors.len())
+            .map(|i| {
+                let neighbors: f32 = self.couplings(i, laplacian).map(|(_, w)| w).sum();
+                neighbors + self.boundary_coupling(i, laplacian)
+            })
+            .fold(0.0, f32::max)
+    }
+}
+
+/// Grows or truncates `state.sites` to match `state.cell_count`.
+pub fn sync_sites(state: &mut SimState, rng: &mut impl Rng) {
     let current_count = state.sites.len();
     let target_count = state.cell_count;
 
+    state.heights.resize(current_count, 0.0);
     if current_count < target_count {
         // Grow: Add new random sites
+        let half_size = (DOMAIN_SIZE / 2.0) as f32;
         for _ in 0..(target_count - current_count) {
-            state.sites.push(Vec2::new(
-                rng.gen_range(-half_size..half_size) as f32,
-                rng.gen_range(-half_size..half_size) as f32,
-            ));
+            let mut site = state.domain.random_point(rng);
+            for _ in 1..MAX_PLACEMENTS {
+                if !state.obstacles.iter().any(|o| o.contains(site)) {
+                    break;
+                }
+                site = state.domain.random_point(rng);
+            }
+            state.sites.push(state.confine(site));
+            // Spread through the depth of the cube
+            let height = if state.domain.is_3d() {
+                rng.gen_range(-half_size..half_size)
+            } else {
+                0.0
+            };
+            state.heights.push(height);
         }
     } else if current_count > target_count {
         // Shrink: Truncate the list
         state.sites.truncate(target_count);
+        state.heights.truncate(target_count);
     }
 
-    // 2. Prepare Computation Points (Ghost Strategy)
-    // Map Vec2 (f32) to Point (f64) for voronator
-    let sites: Vec<Point> = state
-        .sites
-        .iter()
-        .map(|v| Point {
-            x: v.x as f64,
-            y: v.y as f64,
-        })
-        .collect();
-    let mut computation_points = sites.clone();
-
-    if state.wrap_enabled {
-        let offsets = [
-            (-DOMAIN_SIZE, -DOMAIN_SIZE),
-            (0.0, -DOMAIN_SIZE),
-            (DOMAIN_SIZE, -DOMAIN_SIZE),
-            (-DOMAIN_SIZE, 0.0),
-            (DOMAIN_SIZE, 0.0),
-            (-DOMAIN_SIZE, DOMAIN_SIZE),
-            (0.0, DOMAIN_SIZE),
-            (DOMAIN_SIZE, DOMAIN_SIZE),
-        ];
-        for offset in offsets {
-            for site in &sites {
-                computation_points.push(Point {
-                    x: site.x + offset.0,
-                    y: site.y + offset.1,
+    // Surviving sites keep their velocity and radius, new ones start at
+    // rest and unweighted
+    state.velocities.truncate(current_count);
+    state.velocities.resize(state.sites.len(), Vec2::ZERO);
+    state.vertical_velocities.truncate(current_count);
+    state.vertical_velocities.resize(state.sites.len(), 0.0);
+    state.radii.truncate(current_count);
+    state.radii.resize(state.sites.len(), 0.0);
+}
+
+/// Triangulates `sites` (plus torus ghosts when `wrap` is set and `domain`
+/// can wrap) and extracts the neighbour lists and Voronoi polygons of the
+/// real cells, clipped to `domain` otherwise. Nonzero `weights` (squared
+/// radii in domain units, parallel to `sites` or empty) give the power
+/// diagram instead. Sites on opposite sides of an obstacle are not
+/// neighbours. On the sphere the cells are spherical polygons and
+/// obstacles and weights are ignored.
+pub fn build_topology(
+    sites: &[Vec2],
+    weights: &[f32],
+    domain: &Domain,
+    obstacles: &[Obstacle],
+    wrap: bool,
+) -> Topology {
+    if domain.is_sphere() {
+        return Topology::on_sphere(sites);
+    }
+    let wrap = wrap && domain.can_wrap();
+    let delaunay = Delaunay::build(sites, weights, wrap);
+    Topology::from_delaunay(delaunay, sites, domain, obstacles, wrap)
+}
+
+/// Labels a pixel grid over a flat `domain` with the nearest of `sites`
+/// (see [`crate::raster`]) and counts the cells off it; pixels outside the
+/// domain or inside an obstacle belong to no cell. Weights and wrapping are
+/// as in [`build_topology`].
+pub fn build_raster(
+    sites: &[Vec2],
+    weights: &[f32],
+    domain: &Domain,
+    obstacles: &[Obstacle],
+    wrap: bool,
+) -> Topology {
+    let wrap = wrap && domain.can_wrap();
+    let inside = |p: Vec2| (wrap || domain.contains(p)) && !obstacles.iter().any(|o| o.contains(p));
+    let resolution = Raster::resolution_for(sites.len());
+    let raster = raster::rasterize(sites, weights, resolution, inside, wrap);
+    Topology::from_raster(raster, sites, obstacles, wrap)
+}
+
+/// Tetrahedralises the world `positions` of the sites in the 3D domain
+/// and cuts each one's polyhedron out of the cube, along the power planes
+/// for nonzero `weights` (as in [`build_topology`]).
+pub fn build_foam(positions: &[Vec3], weights: &[f32]) -> Topology {
+    let cell_count = positions.len();
+    let half = (DOMAIN_SIZE / 2.0) as f32;
+
+    // 1. Candidate neighbours: every site sharing a tetrahedron, or the
+    // nearest others when too few or too flat to tetrahedralise
+    let tetrahedra = foam::tetrahedralize(positions, weights);
+    let mut candidates: Vec<Vec<usize>> = vec![Vec::new(); cell_count];
+    if tetrahedra.is_empty() {
+        for (i, others) in candidates.iter_mut().enumerate() {
+            others.extend((0..cell_count).filter(|&j| j != i));
+            if others.len() > FALLBACK_CANDIDATES {
+                let p = positions[i];
+                others.select_nth_unstable_by(FALLBACK_CANDIDATES, |&a, &b| {
+                    p.distance_squared(positions[a])
+                        .total_cmp(&p.distance_squared(positions[b]))
+                        .then(a.cmp(&b))
                 });
+                others.truncate(FALLBACK_CANDIDATES);
             }
         }
     }
+    for tetrahedron in &tetrahedra {
+        for &a in tetrahedron {
+            candidates[a].extend(tetrahedron.iter().filter(|&&b| b != a));
+        }
+    }
 
-    // 3. Calculate Topology (Neighbors)
-    let mut adjacency: Vec<HashSet<usize>> = vec![HashSet::new(); state.cell_count];
-    if let Some(triangulation) = delaunator::triangulate(&computation_points) {
-        for i in (0..triangulation.triangles.len()).step_by(3) {
-            let p = [
-                triangulation.triangles[i],
-                triangulation.triangles[i + 1],
-                triangulation.triangles[i + 2],
-            ];
-            for &u in &p {
-                for &v in &p {
-                    if u == v {
-                        continue;
-                    }
-                    if u < state.cell_count {
-                        let v_real = v % state.cell_count;
-                        if u != v_real {
-                            adjacency[u].insert(v_real);
-                        }
-                    }
-                }
+    let mut topology = Topology::default();
+    let weight = |i: usize| weights.get(i).copied().unwrap_or(0.0);
+    for (i, (others, &p)) in candidates.iter_mut().zip(positions).enumerate() {
+        others.sort_unstable();
+        others.dedup();
+
+        // 2. The cube, cut by the bisector with each neighbour (shifted
+        // toward the lighter site), nearest first so the farther cuts
+        // mostly miss
+        others.sort_by(|&a, &b| {
+            p.distance_squared(positions[a])
+                .total_cmp(&p.distance_squared(positions[b]))
+                .then(a.cmp(&b))
+        });
+        let mut solid = Solid::cuboid(Vec3::splat(half));
+        for &j in others.iter() {
+            let q = positions[j];
+            if q != p {
+                let shift = (weight(i) - weight(j)) / (2.0 * p.distance_squared(q));
+                solid.clip((p + q) / 2.0 + (q - p) * shift, q - p, Some(j));
+            }
+        }
+
+        // 3. Cell Geometry
+        let volume = solid.volume();
+        let centroid = solid.centroid().unwrap_or(p);
+        let mut edges = Vec::new();
+        let mut walls = 0.0;
+        for face in &solid.faces {
+            let area = face.area();
+            match face.neighbor {
+                Some(j) if area > 0.0 => edges.push((j, area, p.distance(positions[j]))),
+                Some(_) => {}
+                // Coupling through the walls, to a mirror image of the centroid
+                None => walls += area / (2.0 * face.distance(centroid).max(MIN_WALL_DISTANCE)),
             }
         }
+        topology.boundary_weights.push(if walls == 0.0 {
+            0.0
+        } else if volume < MIN_CELL_AREA {
+            1.0
+        } else {
+            walls / volume
+        });
+        topology.areas.push(volume);
+        topology.perimeters.push(solid.surface_area());
+        topology.centroids.push(Vec2::new(centroid.x, centroid.z));
+        topology.polygons.push(Vec::new());
+        topology.solids.push(solid);
+
+        // 4. Neighbours and Finite-Volume Weights
+        let (neighbors, lengths, weights) = link(edges, volume);
+        topology.neighbors.push(neighbors);
+        topology.edge_lengths.push(lengths);
+        topology.flux_weights.push(weights);
     }
+    topology
+}
 
-    // 4. Compute Geometry & Spawn/Update
-    let bound = DOMAIN_SIZE * 2.0;
-    let diagram = VoronoiDiagram::new(
-        &Point {
-            x: -bound,
-            y: -bound,
-        },
-        &Point { x: bound, y: bound },
-        &computation_points,
-    );
+impl Topology {
+    /// Recomputes the topology after the sites moved or their weights
+    /// changed, and returns the cells whose geometry or neighbours may have
+    /// changed, in ascending order. Small motions repair the previous
+    /// triangulation with edge flips and only redo the cells around the
+    /// sites that moved; a changed cell count, domain or set of obstacles, a
+    /// switched wrap mode or a large jump falls back to [`build_topology`]
+    /// (and returns every cell).
+    pub fn update(
+        &mut self,
+        sites: &[Vec2],
+        weights: &[f32],
+        domain: &Domain,
+        obstacles: &[Obstacle],
+        wrap: bool,
+    ) -> Vec<usize> {
+        let all = (0..sites.len()).collect();
+        if domain.is_sphere() {
+            *self = Self::on_sphere(sites);
+            return all;
+        }
+        let wrap = wrap && domain.can_wrap();
+        let same_cuts = self.domain == *domain && self.obstacles == obstacles;
+        if let Some(mut delaunay) = self
+            .delaunay
+            .take()
+            .filter(|delaunay| same_cuts && delaunay.fits(sites.len(), wrap))
+            && let Some(changed) = delaunay.update(sites, weights)
+        {
+            self.delaunay = Some(delaunay);
+            self.refresh(&changed, sites, wrap);
+            return changed;
+        }
+        let delaunay = Delaunay::build(sites, weights, wrap);
+        *self = Self::from_delaunay(delaunay, sites, domain, obstacles, wrap);
+        all
+    }
+
+    /// Positions of the ghost points the topology was built with.
+    pub fn ghosts(&self) -> impl Iterator<Item = Vec2> + '_ {
+        self.delaunay.iter().flat_map(Delaunay::ghosts)
+    }
+
+    /// Distance beyond the domain edges within which sites were ghosted,
+    /// or `None` when not wrapping.
+    pub fn ghost_margin(&self) -> Option<f32> {
+        self.delaunay.as_ref().and_then(Delaunay::margin)
+    }
 
-    if let Some(diagram) = diagram {
-        // Entity Recycling Check
-        let reuse_entities = cell_map.entities.len() == state.cell_count;
+    /// The pixels the cells were counted off, for the raster tessellation.
+    pub fn raster(&self) -> Option<&Raster> {
+        self.raster.as_ref()
+    }
+
+    fn from_delaunay(
+        delaunay: Option<Delaunay>,
+        sites: &[Vec2],
+        domain: &Domain,
+        obstacles: &[Obstacle],
+        wrap: bool,
+    ) -> Self {
+        let cell_count = sites.len();
+        let mut topology = Topology {
+            neighbors: vec![Vec::new(); cell_count],
+            polygons: vec![Vec::new(); cell_count],
+            areas: vec![0.0; cell_count],
+            perimeters: vec![0.0; cell_count],
+            edge_lengths: vec![Vec::new(); cell_count],
+            centroids: sites.to_vec(),
+            flux_weights: vec![Vec::new(); cell_count],
+            boundary_weights: vec![0.0; cell_count],
+            delaunay,
+            domain: domain.clone(),
+            obstacles: obstacles.to_vec(),
+            ..default()
+        };
+        topology.refresh(&(0..cell_count).collect::<Vec<_>>(), sites, wrap);
+        topology
+    }
+
+    // Recomputes `cells` from the triangulation, leaving the others as they are
+    fn refresh(&mut self, cells: &[usize], sites: &[Vec2], wrap: bool) {
+        let outline = (!wrap).then(|| self.domain.outline());
+        for &i in cells {
+            // 1. Voronoi cell around the site's own point, cut to the walls
+            let cell = self
+                .delaunay
+                .as_ref()
+                .and_then(|delaunay| delaunay.cell(i))
+                .unwrap_or_default();
+            let cell = match &outline {
+                Some(outline) => clip_cell(cell, outline),
+                None => cell,
+            };
+
+            // 2. Cell Geometry
+            let area = polygon_area(&cell.polygon);
+            let centroid = polygon_centroid(&cell.polygon).unwrap_or(sites[i]);
+            self.areas[i] = area;
+            self.perimeters[i] = polygon_perimeter(&cell.polygon);
+            self.centroids[i] = centroid;
+
+            // 3. Coupling through the walls, to a mirror image of the centroid
+            self.boundary_weights[i] = if cell.walls.is_empty() {
+                0.0
+            } else if area < MIN_CELL_AREA {
+                1.0
+            } else {
+                let conductance: f32 = cell
+                    .walls
+                    .iter()
+                    .map(|&(a, b)| {
+                        let length = a.distance(b);
+                        let distance = (b - a).perp_dot(centroid - a).abs() / length;
+                        length / (2.0 * distance.max(MIN_WALL_DISTANCE))
+                    })
+                    .sum();
+                conductance / area
+            };
 
-        if !reuse_entities {
-            // Hard reset if count changed
-            for e in &cell_map.entities {
-                commands.entity(*e).despawn();
+            // 4. Neighbours and Finite-Volume Weights, merging the edges
+            // shared with several images of one site and dropping those
+            // walled off by an obstacle
+            let edges = cell
+                .edges
+                .iter()
+                .map(|e| (e.neighbor, e.start.distance(e.end), e.distance))
+                .filter(|&(j, length, _)| {
+                    length > 0.0 && !blocked(sites, i, j, &self.obstacles, wrap)
+                })
+                .collect();
+            let (neighbors, lengths, weights) = link(edges, area);
+            self.neighbors[i] = neighbors;
+            self.edge_lengths[i] = lengths;
+            self.flux_weights[i] = weights;
+            self.polygons[i] = cell.polygon;
+        }
+    }
+
+    fn from_raster(raster: Raster, sites: &[Vec2], obstacles: &[Obstacle], wrap: bool) -> Self {
+        let cell_count = sites.len();
+        let n = raster.resolution;
+        let pixel = raster.pixel_size();
+        let label = |x: usize, y: usize| raster.labels[y * n + x];
+
+        // 1. Cell Geometry: area and centroid from the pixels of each cell,
+        // measured from the site so cells across the seam stay whole
+        let mut counts = vec![0_u32; cell_count];
+        let mut sums = vec![Vec2::ZERO; cell_count];
+        for y in 0..n {
+            for x in 0..n {
+                let i = label(x, y);
+                if i != OUTSIDE {
+                    let i = i as usize;
+                    counts[i] += 1;
+                    sums[i] += wrapped_offset(sites[i], raster.centre(x, y), wrap);
+                }
             }
-            cell_map.entities.clear();
         }
+        let areas: Vec<f32> = counts.iter().map(|&c| c as f32 * pixel * pixel).collect();
+        let centroids: Vec<Vec2> = (0..cell_count)
+            .map(|i| match counts[i] {
+                0 => sites[i],
+                count => sites[i] + sums[i] / count as f32,
+            })
+            .collect();
 
-        let mut rng = rand::thread_rng();
-        let range_max = (DOMAIN_SIZE / 2.0) as f32;
+        // 2. Every pair of pixels side by side in different cells adds to
+        // their shared edge, or to a wall where one is outside. Walls keep
+        // their sides and summed distances per axis
+        let mut contacts: Vec<Vec<(usize, u32)>> = vec![Vec::new(); cell_count];
+        let mut sides = vec![0_u32; cell_count];
+        let mut walls = vec![[(0_u32, 0.0_f32); 2]; cell_count];
+        let mut touch = |a: u32, b: u32, side: Vec2, axis: usize| {
+            for (i, j) in [(a, b), (b, a)] {
+                if i == OUTSIDE {
+                    continue;
+                }
+                let i = i as usize;
+                sides[i] += 1;
+                if j == OUTSIDE {
+                    let wall = &mut walls[i][axis];
+                    wall.0 += 1;
+                    wall.1 += wrapped_offset(centroids[i], side, wrap)[axis].abs();
+                } else {
+                    match contacts[i].iter_mut().find(|(k, _)| *k == j as usize) {
+                        Some((_, count)) => *count += 1,
+                        None => contacts[i].push((j as usize, 1)),
+                    }
+                }
+            }
+        };
+        for y in 0..n {
+            for x in 0..n {
+                let here = label(x, y);
+                let centre = raster.centre(x, y);
+                // The square's own edges are walls unless wrapping
+                let right = match x + 1 {
+                    next if next < n => label(next, y),
+                    _ if wrap => label(0, y),
+                    _ => OUTSIDE,
+                };
+                let up = match y + 1 {
+                    next if next < n => label(x, next),
+                    _ if wrap => label(x, 0),
+                    _ => OUTSIDE,
+                };
+                if right != here {
+                    touch(here, right, centre + Vec2::X * pixel / 2.0, 0);
+                }
+                if up != here {
+                    touch(here, up, centre + Vec2::Y * pixel / 2.0, 1);
+                }
+                // Nothing below or left of the first row and column
+                if !wrap && x == 0 && here != OUTSIDE {
+                    touch(OUTSIDE, here, centre - Vec2::X * pixel / 2.0, 0);
+                }
+                if !wrap && y == 0 && here != OUTSIDE {
+                    touch(OUTSIDE, here, centre - Vec2::Y * pixel / 2.0, 1);
+                }
+            }
+        }
+
+        // 3. Coupling through the walls, to a mirror image of the centroid.
+        // Averaging the distance along each axis before dividing by it keeps
+        // staircase walls from coupling through their nearest steps; the
+        // steps' length along an axis over their mean distance is exact for
+        // straight walls at any angle
+        let boundary_weights = walls
+            .iter()
+            .zip(&areas)
+            .map(|(axes, &area)| {
+                if axes.iter().all(|&(count, _)| count == 0) {
+                    0.0
+                } else if area < MIN_CELL_AREA {
+                    1.0
+                } else {
+                    let conductance: f32 = axes
+                        .iter()
+                        .filter(|&&(count, _)| count > 0)
+                        .map(|&(count, total)| {
+                            let distance = total / count as f32;
+                            count as f32 * pixel / (2.0 * distance.max(MIN_WALL_DISTANCE))
+                        })
+                        .sum();
+                    conductance / area
+                }
+            })
+            .collect();
 
-        for (i, cell) in diagram.cells().iter().take(state.cell_count).enumerate() {
-            let points: Vec<Vec3> = cell
-                .points()
+        // 4. Neighbours and Finite-Volume Weights, dropping those walled off
+        // by an obstacle
+        let mut neighbors = Vec::with_capacity(cell_count);
+        let mut edge_lengths = Vec::with_capacity(cell_count);
+        let mut flux_weights = Vec::with_capacity(cell_count);
+        for (i, cell_contacts) in contacts.iter().enumerate() {
+            let edges = cell_contacts
                 .iter()
-                .map(|p| Vec3::new(p.x as f32, 0.0, p.y as f32))
+                .filter(|&&(j, _)| !blocked(sites, i, j, obstacles, wrap))
+                .map(|&(j, count)| {
+                    let distance = wrapped_offset(sites[i], sites[j], wrap).length();
+                    (j, count as f32 * pixel * STAIRCASE, distance)
+                })
                 .collect();
+            let (cell_neighbors, lengths, weights) = link(edges, areas[i]);
+            neighbors.push(cell_neighbors);
+            edge_lengths.push(lengths);
+            flux_weights.push(weights);
+        }
+
+        Topology {
+            neighbors,
+            perimeters: sides
+                .iter()
+                .map(|&s| s as f32 * pixel * STAIRCASE)
+                .collect(),
+            areas,
+            edge_lengths,
+            centroids,
+            flux_weights,
+            boundary_weights,
+            raster: Some(raster),
+            ..default()
+        }
+    }
 
-            if points.len() < 3 {
-                continue;
-            } // Skip degenerate cells
+    // Spherical Voronoi cells, read off the convex hull of the sites on the
+    // globe; there are no walls, ghosts or obstacles
+    fn on_sphere(sites: &[Vec2]) -> Self {
+        let cell_count = sites.len();
+        let radius = f64::from(SPHERE_RADIUS);
+        let points: Vec<DVec3> = sites.iter().map(|&p| sphere::to_sphere(p)).collect();
 
-            // Triangulate the polygon fan
-            let mut indices = Vec::new();
-            for j in 1..points.len() - 1 {
-                indices.push(0);
-                indices.push(j as u32);
-                indices.push((j + 1) as u32);
+        // 1. Circumcentre of every face (its outward normal) and the faces
+        // around every site
+        let triangles = sphere::triangulate(&points);
+        let mut faces: Vec<Vec<usize>> = vec![Vec::new(); cell_count];
+        let mut centres = Vec::with_capacity(triangles.len());
+        for (t, &[a, b, c]) in triangles.iter().enumerate() {
+            let normal = (points[b] - points[a]).cross(points[c] - points[a]);
+            centres.push(normal.normalize_or_zero());
+            for v in [a, b, c] {
+                faces[v].push(t);
             }
+        }
 
-            let mut mesh = Mesh::new(
-                bevy::mesh::PrimitiveTopology::TriangleList,
-                bevy::asset::RenderAssetUsages::RENDER_WORLD
-                    | bevy::asset::RenderAssetUsages::MAIN_WORLD,
-            );
-            mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, points);
-            mesh.insert_indices(bevy::mesh::Indices::U32(indices));
-            mesh.compute_smooth_normals();
-
-            let mesh_handle = meshes.add(mesh);
-
-            if reuse_entities {
-                // RECYCLE PATH
-                let id = cell_map.entities[i];
-                commands
-                    .entity(id)
-                    .insert(Mesh3d(mesh_handle)) // Hot-swap Mesh
-                    .insert(Neighbors {
-                        indices: adjacency[i].iter().cloned().collect(),
-                    }) // Update Neighbors
-                    .remove::<Aabb>(); // CRITICAL: Force AABB regeneration for picking!
+        let mut topology = Topology {
+            boundary_weights: vec![0.0; cell_count],
+            ..default()
+        };
+        for (i, (faces, &v)) in faces.iter_mut().zip(&points).enumerate() {
+            // 2. Corners in turn around the site, matching the winding of
+            // the flat polygons
+            let e1 = v.any_orthonormal_vector();
+            let e2 = e1.cross(v);
+            let mut around: Vec<(f64, usize)> = faces
+                .iter()
+                .map(|&t| {
+                    let d = centres[t] - v;
+                    (d.dot(e2).atan2(d.dot(e1)), t)
+                })
+                .collect();
+            around.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            *faces = around.into_iter().map(|(_, t)| t).collect();
+
+            // 3. Geometry from the fan of spherical triangles on the site,
+            // and the edges between consecutive corners
+            let mut area = 0.0;
+            let mut perimeter = 0.0;
+            let mut centroid = DVec3::ZERO;
+            let mut edges = Vec::new();
+            for (&s, &t) in faces.iter().zip(faces.iter().cycle().skip(1)) {
+                let (a, b) = (centres[s], centres[t]);
+                let wedge = sphere::triangle_area(v, a, b);
+                area += wedge;
+                centroid += (v + a + b).normalize_or_zero() * wedge;
+                let length = sphere::arc(a, b) * radius;
+                perimeter += length;
+                let shared = triangles[s]
+                    .into_iter()
+                    .find(|&j| j != i && triangles[t].contains(&j));
+                if let Some(j) = shared
+                    && length > 0.0
+                {
+                    let distance = sphere::arc(v, points[j]) * radius;
+                    edges.push((j, length as f32, distance as f32));
+                }
+            }
+            let area = (area * radius * radius) as f32;
+            let corners: Vec<DVec3> = faces.iter().map(|&t| centres[t]).collect();
+            topology
+                .polygons
+                .push(corners.iter().map(|&c| sphere::from_sphere(c)).collect());
+            topology
+                .globe
+                .push(corners.iter().map(|&c| (c * radius).as_vec3()).collect());
+            topology.areas.push(area);
+            topology.perimeters.push(perimeter as f32);
+            topology.centroids.push(if area < MIN_CELL_AREA {
+                sites[i]
             } else {
-                // SPAWN PATH: Gradient Initialization
-                let pos = state.sites[i];
-
-                // Normalize -10..10 to 0..1
-                let nx = (pos.x / range_max + 1.0) * 0.5;
-                let ny = (pos.y / range_max + 1.0) * 0.5;
-                let dist_center = 1.0 - (pos.length() / range_max).clamp(0.0, 1.0);
-
-                // Base Colors: Red (X), Green (Y), Blue (Center)
-                let r_base = nx;
-                let g_base = ny;
-                let b_base = dist_center;
-
-                // Add Noise
-                let noise = 0.05;
-
-                let chem = Chemicals {
-                    r: (r_base + rng.gen_range(-noise..noise)).clamp(0.0, 1.0),
-                    g: (g_base + rng.gen_range(-noise..noise)).clamp(0.0, 1.0),
-                    b: (b_base + rng.gen_range(-noise..noise)).clamp(0.0, 1.0),
-                    e: rng.r#gen(), // Emission can be random
-                };
+                sphere::from_sphere(centroid)
+            });
+
+            // 4. Neighbours and Finite-Volume Weights
+            let (neighbors, lengths, weights) = link(edges, area);
+            topology.neighbors.push(neighbors);
+            topology.edge_lengths.push(lengths);
+            topology.flux_weights.push(weights);
+        }
+        topology
+    }
+}
+
+// Whether an obstacle stands between sites `i` and `j`
+fn blocked(sites: &[Vec2], i: usize, j: usize, obstacles: &[Obstacle], wrap: bool) -> bool {
+    let (a, b) = (sites[i], sites[j]);
+    let offset = wrapped_offset(a, b, wrap);
+    obstacles
+        .iter()
+        .any(|o| o.blocks(a, a + offset) || o.blocks(b, b - offset))
+}
+
+// Neighbour list, edge lengths and finite-volume weights of a cell of
+// `area` from its edges as (neighbour, length, site distance), merging the
+// edges shared with one neighbour
+fn link(mut edges: Vec<(usize, f32, f32)>, area: f32) -> (Vec<usize>, Vec<f32>, Vec<f32>) {
+    // Sorted fully, so float sums do not depend on the walk order
+    edges.sort_by(|a, b| {
+        a.0.cmp(&b.0)
+            .then(a.1.total_cmp(&b.1))
+            .then(a.2.total_cmp(&b.2))
+    });
+    let mut neighbors: Vec<usize> = Vec::new();
+    let mut lengths: Vec<f32> = Vec::new();
+    let mut weights: Vec<f32> = Vec::new();
+    for (j, length, dist) in edges {
+        let weight = if area < MIN_CELL_AREA || dist <= 0.0 {
+            // Degenerate cell: fall back to a uniform coupling
+            1.0
+        } else {
+            length / dist / area
+        };
+        if neighbors.last() == Some(&j) {
+            let k = lengths.len() - 1;
+            lengths[k] += length;
+            weights[k] += weight;
+        } else {
+            neighbors.push(j);
+            lengths.push(length);
+            weights.push(weight);
+        }
+    }
+    (neighbors, lengths, weights)
+}
+
+/// Shortest offset from `from` to `to`, across the torus seam when `wrap` is set.
+pub fn wrapped_offset(from: Vec2, to: Vec2, wrap: bool) -> Vec2 {
+    let mut dir = to - from;
+    if wrap {
+        let bound = (DOMAIN_SIZE / 2.0) as f32;
+        let domain_width = DOMAIN_SIZE as f32;
+        if dir.x > bound {
+            dir.x -= domain_width;
+        } else if dir.x < -bound {
+            dir.x += domain_width;
+        }
+
+        if dir.y > bound {
+            dir.y -= domain_width;
+        } else if dir.y < -bound {
+            dir.y += domain_width;
+        }
+    }
+    dir
+}
+
+// Cuts a convex cell down to the part inside `outline`, which may be
+// concave
+fn clip_cell(cell: Cell, outline: &[Vec2]) -> Cell {
+    if cell.polygon.len() < 3 {
+        return cell;
+    }
+
+    // 1. Cells clear of the outline are wholly inside or wholly outside
+    let (min, max) = bounds(&cell.polygon);
+    let crosses = outline.iter().enumerate().any(|(k, &a)| {
+        let b = outline[(k + 1) % outline.len()];
+        a.min(b).cmple(max).all() && a.max(b).cmpge(min).all()
+    });
+    if !crosses && polygon_contains(outline, cell.polygon[0]) {
+        return cell;
+    }
 
-                let id = commands
-                    .spawn((
-                        Mesh3d(mesh_handle),
-                        MeshMaterial3d(materials.add(StandardMaterial {
-                            base_color: Color::BLACK,
-                            metallic: 0.1,
-                            perceptual_roughness: 0.8,
-                            cull_mode: None,
-                            ..default()
-                        })),
-                        Transform::default(),
-                        GlobalTransform::default(),
-                        Visibility::default(),
-                        InheritedVisibility::default(),
-                        ViewVisibility::default(),
-                        // Components
-                        chem,
-                        NextChemicals::default(),
-                        Neighbors {
-                            indices: adjacency[i].iter().cloned().collect(),
-                        },
-                        CellIndex(i), // Track Identity
-                    ))
-                    .observe(crate::chemistry::on_click_splash) // Left Click
-                    .observe(on_cell_drag) // Drag
-                    .id();
-
-                cell_map.entities.push(id);
+    // 2. The part of the domain inside the (convex) cell
+    let (polygon, walls) = intersect_convex(&cell.polygon, outline);
+
+    // 3. Shorten the shared edges to their stretches inside the outline
+    let edges = cell
+        .edges
+        .into_iter()
+        .filter_map(|edge| {
+            let inside = inside_length(edge.start, edge.end, outline);
+            // Only the length matters from here on
+            (inside > 0.0).then(|| Edge {
+                end: edge.start + (edge.end - edge.start).normalize_or_zero() * inside,
+                ..edge
+            })
+        })
+        .collect();
+
+    Cell {
+        polygon,
+        edges,
+        walls,
+    }
+}
+
+// Intersection of a counter-clockwise convex polygon with a
+// counter-clockwise simple ring, and the segments of the ring bounding it.
+// Where the ring cuts the convex polygon into several pieces they are
+// joined by zero-width seams
+fn intersect_convex(convex: &[Vec2], ring: &[Vec2]) -> (Vec<Vec2>, Vec<(Vec2, Vec2)>) {
+    let n = convex.len();
+    let side = |s: usize, p: Vec2| {
+        let (a, b) = (convex[s], convex[(s + 1) % n]);
+        (b - a).perp_dot(p - a)
+    };
+    // Position along the convex boundary: side index plus the fraction along it
+    let boundary_position = |p: Vec2| {
+        let s = (0..n)
+            .min_by(|&i, &j| {
+                let distance =
+                    |s: usize| side(s, p).abs() / convex[s].distance(convex[(s + 1) % n]);
+                distance(i).total_cmp(&distance(j))
+            })
+            .unwrap_or(0);
+        let (a, b) = (convex[s], convex[(s + 1) % n]);
+        s as f32 + ((p - a).dot(b - a) / (b - a).length_squared().max(f32::EPSILON)).clamp(0.0, 1.0)
+    };
+
+    // 1. Start from a ring vertex outside, so no stretch inside is cut in two
+    let Some(start) = ring.iter().position(|&p| (0..n).any(|s| side(s, p) < 0.0)) else {
+        let segments = ring.iter().zip(ring.iter().cycle().skip(1));
+        return (
+            ring.to_vec(),
+            drop_seams(segments.map(|(&a, &b)| (a, b)).collect()),
+        );
+    };
+
+    // 2. Stretches of the ring inside, as (entry, exit, points), clipping
+    // each edge against every side in turn (Cyrus-Beck)
+    let mut stretches: Vec<(f32, f32, Vec<Vec2>)> = Vec::new();
+    let mut current: Option<(f32, Vec<Vec2>)> = None;
+    for k in 0..ring.len() {
+        let p = ring[(start + k) % ring.len()];
+        let q = ring[(start + k + 1) % ring.len()];
+        let (mut enter, mut leave) = (0.0, 1.0);
+        for s in 0..n {
+            let (dp, dq) = (side(s, p), side(s, q));
+            if dp < 0.0 && dq < 0.0 {
+                (enter, leave) = (1.0, 0.0);
+                break;
+            } else if dp < 0.0 {
+                enter = f32::max(enter, dp / (dp - dq));
+            } else if dq < 0.0 {
+                leave = f32::min(leave, dp / (dp - dq));
+            }
+        }
+        if enter >= leave {
+            continue;
+        }
+        let entry = p + (q - p) * enter;
+        let (_, points) = current.get_or_insert_with(|| (boundary_position(entry), Vec::new()));
+        points.push(entry);
+        if leave < 1.0 {
+            let exit = p + (q - p) * leave;
+            if let Some((entry, mut points)) = current.take() {
+                points.push(exit);
+                stretches.push((entry, boundary_position(exit), points));
+            }
+        }
+    }
+    if stretches.is_empty() {
+        // The ring never comes inside: the polygon is wholly in or out
+        let middle = convex.iter().sum::<Vec2>() / n as f32;
+        let polygon = if polygon_contains(ring, middle) {
+            convex.to_vec()
+        } else {
+            Vec::new()
+        };
+        return (polygon, Vec::new());
+    }
+    let walls = drop_seams(
+        stretches
+            .iter()
+            .flat_map(|(_, _, points)| points.windows(2).map(|w| (w[0], w[1])))
+            .collect(),
+    );
+
+    // 3. Link each exit to the next entry counter-clockwise along the
+    // boundary, taking in the corners passed on the way
+    let mut pieces: Vec<Vec<Vec2>> = Vec::new();
+    let mut used = vec![false; stretches.len()];
+    while let Some(first) = used.iter().position(|&u| !u) {
+        let mut piece = Vec::new();
+        let mut k = first;
+        loop {
+            used[k] = true;
+            let (_, exit, points) = &stretches[k];
+            piece.extend_from_slice(points);
+            // An entry right at the exit is the way back along a seam of
+            // the ring (the cut of an annulus), so it counts as a full lap
+            let ahead = |position: f32| match (position - exit).rem_euclid(n as f32) {
+                gap if gap < SEAM_TOLERANCE => n as f32,
+                gap => gap,
+            };
+            let next = (0..stretches.len())
+                .filter(|&j| !used[j] || j == first)
+                .min_by(|&i, &j| ahead(stretches[i].0).total_cmp(&ahead(stretches[j].0)))
+                .unwrap_or(first);
+            let gap = ahead(stretches[next].0);
+            for step in 1..=n {
+                let corner = (exit.floor() as usize + step) % n;
+                if (corner as f32 - exit).rem_euclid(n as f32) >= gap {
+                    break;
+                }
+                piece.push(convex[corner]);
+            }
+            if next == first {
+                break;
             }
+            k = next;
         }
+        pieces.push(piece);
+    }
+
+    // 4. One ring, returning to the first point after every piece
+    let anchor = pieces[0][0];
+    let mut polygon = Vec::new();
+    for piece in pieces {
+        let start = piece[0];
+        polygon.extend(piece);
+        polygon.extend([start, anchor]);
+    }
+    polygon.dedup();
+    if polygon.len() > 1 && polygon.first() == polygon.last() {
+        polygon.pop();
     }
+    (polygon, walls)
+}
 
-    state.rebuild_requested = false;
+// Leaves out the segments also walked the other way: the two sides of a
+// seam (the cut of an annulus) are not part of the boundary
+fn drop_seams(segments: Vec<(Vec2, Vec2)>) -> Vec<(Vec2, Vec2)> {
+    let same = |p: Vec2, q: Vec2| p.distance_squared(q) < SEAM_TOLERANCE * SEAM_TOLERANCE;
+    segments
+        .iter()
+        .filter(|&&(a, b)| a != b && !segments.iter().any(|&(c, d)| same(a, d) && same(b, c)))
+        .copied()
+        .collect()
 }
 
-// 5. The Drag Handler
-pub fn on_cell_drag(
-    trigger: On<Pointer<Drag>>,
-    mut state: ResMut<SimState>,
-    query: Query<&CellIndex>,
-) {
-    // Identify which site we are dragging
-    if let Ok(cell_idx) = query.get(trigger.original_event_target()) {
-        let idx = cell_idx.0;
-
-        // Get drag delta (screen space)
-        let drag_event = trigger.event();
-        let delta = drag_event.delta;
-
-        // Map Screen Delta to World Plane (X/Z)
-        // Camera is looking DOWN Y axis.
-        // Screen X+ (Right) -> World X+ (Right)
-        // Screen Y+ (Down)  -> World Z+ (Toward/Back depending on camera up)
-        // With standard look_at(ZERO, Y), screen Up (Y-) moves into the screen (Z-)
-
-        // Sensitivity factor (approximate for height=20.0)
-        let sensitivity = 0.05;
-
-        if let Some(site) = state.sites.get_mut(idx) {
-            site.x += delta.x * sensitivity;
-            site.y -= delta.y * sensitivity;
-
-            // Clamp to domain
-            let bound = (DOMAIN_SIZE / 2.0) as f32;
-            site.x = site.x.clamp(-bound, bound);
-            site.y = site.y.clamp(-bound, bound);
-        }
-
-        // Request immediate rebuild
-        state.rebuild_requested = true;
+// Total length of the parts of segment `a`-`b` inside `outline`
+fn inside_length(a: Vec2, b: Vec2, outline: &[Vec2]) -> f32 {
+    // Cut the segment wherever it crosses the outline, then test the middle
+    // of every piece
+    let direction = b - a;
+    let mut cuts = vec![0.0, 1.0];
+    for (k, &p) in outline.iter().enumerate() {
+        let q = outline[(k + 1) % outline.len()];
+        let denominator = direction.perp_dot(q - p);
+        if denominator == 0.0 {
+            continue;
+        }
+        let t = (p - a).perp_dot(q - p) / denominator;
+        let u = (p - a).perp_dot(direction) / denominator;
+        if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+            cuts.push(t);
+        }
+    }
+    cuts.sort_by(f32::total_cmp);
+    cuts.windows(2)
+        .filter(|w| polygon_contains(outline, a + direction * (w[0] + w[1]) * 0.5))
+        .map(|w| w[1] - w[0])
+        .sum::<f32>()
+        * direction.length()
+}
+
+fn bounds(polygon: &[Vec2]) -> (Vec2, Vec2) {
+    polygon.iter().fold(
+        (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+        |(min, max), &p| (min.min(p), max.max(p)),
+    )
+}
+
+// Shoelace formula
+fn polygon_area(polygon: &[Vec2]) -> f32 {
+    if polygon.len() < 3 {
+        return 0.0;
+    }
+    let mut twice_area = 0.0;
+    for (k, &p) in polygon.iter().enumerate() {
+        let q = polygon[(k + 1) % polygon.len()];
+        twice_area += p.perp_dot(q);
+    }
+    (twice_area * 0.5).abs()
+}
+
+// Length of the boundary, leaving out the zero-width seams joining the
+// pieces of a cut cell
+fn polygon_perimeter(polygon: &[Vec2]) -> f32 {
+    if polygon.len() < 2 {
+        return 0.0;
+    }
+    let sides = polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(&p, &q)| (p, q))
+        .collect();
+    drop_seams(sides).iter().map(|(p, q)| p.distance(*q)).sum()
+}
+
+// Area-weighted centroid, or `None` for a degenerate polygon
+fn polygon_centroid(polygon: &[Vec2]) -> Option<Vec2> {
+    let mut twice_area = 0.0;
+    let mut weighted = Vec2::ZERO;
+    for (k, &p) in polygon.iter().enumerate() {
+        let q = polygon[(k + 1) % polygon.len()];
+        let cross = p.perp_dot(q);
+        twice_area += cross;
+        weighted += (p + q) * cross;
+    }
+    (twice_area.abs() * 0.5 >= MIN_CELL_AREA).then(|| weighted / (3.0 * twice_area))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    // Every flat domain, clipped, and the square wrapped too
+    fn flat_domains() -> Vec<(Domain, bool)> {
+        let mut domains = vec![(Domain::Square, true)];
+        domains.extend(
+            Domain::all()
+                .into_iter()
+                .filter(Domain::is_flat)
+                .map(|domain| (domain, false)),
+        );
+        domains
+    }
+
+    fn random_sites(domain: &Domain, count: usize, rng: &mut impl Rng) -> Vec<Vec2> {
+        (0..count).map(|_| domain.random_point(rng)).collect()
+    }
+
+    #[test]
+    fn clipped_cells_fill_the_domain() {
+        let mut rng = ChaCha8Rng::seed_from_u64(22);
+        for domain in Domain::all().into_iter().filter(Domain::is_flat) {
+            let sites = random_sites(&domain, 200, &mut rng);
+            let topology = build_topology(&sites, &[], &domain, &[], false);
+            let outline = domain.outline();
+            let label = domain.label();
+
+            let area: f32 = topology.areas.iter().sum();
+            let expected = polygon_area(&outline);
+            assert!(
+                (area - expected).abs() < 1e-3 * expected,
+                "{label}: cells cover {area} of {expected}"
+            );
+
+            // Every shared edge twice, once from each side, and the
+            // outline once (without the annulus's cut)
+            let perimeters: f32 = topology.perimeters.iter().sum();
+            let shared: f32 = topology.edge_lengths.iter().flatten().sum();
+            let expected = shared + polygon_perimeter(&outline);
+            assert!(
+                (perimeters - expected).abs() < 1e-3 * expected,
+                "{label}: perimeters add up to {perimeters}, not {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn sphere_cells_cover_the_globe() {
+        let mut rng = ChaCha8Rng::seed_from_u64(23);
+        let sites = random_sites(&Domain::Sphere, 500, &mut rng);
+        let topology = build_topology(&sites, &[], &Domain::Sphere, &[], false);
+        let area: f32 = topology.areas.iter().sum();
+        let expected = 4.0 * std::f32::consts::PI * SPHERE_RADIUS * SPHERE_RADIUS;
+        assert!(
+            (area - expected).abs() < 1e-3 * expected,
+            "{area} of {expected}"
+        );
+        assert!(topology.areas.iter().all(|&a| a > 0.0));
+    }
+
+    #[test]
+    fn foam_cells_fill_the_cube() {
+        let mut rng = ChaCha8Rng::seed_from_u64(24);
+        let half = (DOMAIN_SIZE / 2.0) as f32;
+        let mut point = || Vec3::from_array([(); 3].map(|_| rng.gen_range(-half..half)));
+        let positions: Vec<Vec3> = (0..300).map(|_| point()).collect();
+        let cube = (DOMAIN_SIZE * DOMAIN_SIZE * DOMAIN_SIZE) as f32;
+
+        let volume: f32 = build_foam(&positions, &[]).areas.iter().sum();
+        assert!((volume - cube).abs() < 1e-3 * cube, "{volume} of {cube}");
+
+        // All at one height, which only the nearest-candidates fallback can cut
+        let flat: Vec<Vec3> = positions.iter().map(|p| p.with_y(0.0)).collect();
+        let foam = build_foam(&flat, &[]);
+        let volume: f32 = foam.areas.iter().sum();
+        assert!(
+            (volume - cube).abs() < 1e-3 * cube,
+            "flat: {volume} of {cube}"
+        );
+        // Prisms over the cells of the plane, but for the odd sliver of a face
+        let plane = build_topology(
+            &flat.iter().map(|p| p.xz()).collect::<Vec<_>>(),
+            &[],
+            &Domain::Square,
+            &[],
+            false,
+        );
+        let same = foam
+            .neighbors
+            .iter()
+            .zip(&plane.neighbors)
+            .filter(|(a, b)| a == b)
+            .count();
+        assert!(
+            same >= 297,
+            "only {same} of 300 cells have their neighbours"
+        );
+    }
+
+    #[test]
+    fn raster_neighbours_match_polygon_neighbours() {
+        let mut rng = ChaCha8Rng::seed_from_u64(25);
+        for (domain, wrap) in flat_domains() {
+            let sites = random_sites(&domain, 100, &mut rng);
+            let polygons = build_topology(&sites, &[], &domain, &[], wrap);
+            let raster = build_raster(&sites, &[], &domain, &[], wrap);
+            let pixel = raster.raster().map_or(0.0, Raster::pixel_size);
+            let label = domain.label();
+
+            let (mut shared, mut polygon_only) = (0, 0);
+            for (i, neighbors) in polygons.neighbors.iter().enumerate() {
+                for (&j, &length) in neighbors.iter().zip(&polygons.edge_lengths[i]) {
+                    if raster.neighbors[i].contains(&j) {
+                        shared += 1;
+                    } else {
+                        // Only edges too short to show in the pixels go missing
+                        assert!(length < 2.0 * pixel, "{label}: {i}-{j} is {length} long");
+                        polygon_only += 1;
+                    }
+                }
+            }
+            let raster_only: usize = raster
+                .neighbors
+                .iter()
+                .zip(&polygons.neighbors)
+                .map(|(a, b)| a.iter().filter(|j| !b.contains(j)).count())
+                .sum();
+            assert!(
+                polygon_only + raster_only < shared / 20,
+                "{label}: {shared} shared, {polygon_only} polygon only, {raster_only} raster only"
+            );
+        }
+    }
+
+    #[test]
+    fn seams_are_not_boundary() {
+        // A box over the annulus's cut, reaching into its hole
+        let outline = Domain::Annulus { inner: 0.4 }.outline();
+        let cell = [(3.0, -1.0), (9.0, -1.0), (9.0, 1.0), (3.0, 1.0)].map(|(x, y)| Vec2::new(x, y));
+        let (polygon, walls) = intersect_convex(&cell, &outline);
+
+        // Only the hole's edge bounds it, crossing the cut once
+        let arc = 4.0 * 2.0 * (0.25f32).asin();
+        let walls_length: f32 = walls.iter().map(|(a, b)| a.distance(*b)).sum();
+        assert!((walls_length - arc).abs() < 0.01, "walls {walls_length}");
+        assert!(walls.iter().all(|(a, b)| a.y != 0.0 || b.y != 0.0));
+
+        // The box less the cap in the hole, and its three straight sides
+        let inside = 15f32.sqrt();
+        let cap = inside + 16.0 * (0.25f32).asin() - 6.0;
+        assert!((polygon_area(&polygon) - (12.0 - cap)).abs() < 0.01);
+        let perimeter = 2.0 * (9.0 - inside) + 2.0 + arc;
+        assert!((polygon_perimeter(&polygon) - perimeter).abs() < 0.01);
+
+        // Shared edges crossing the cut are not shortened by it, and those
+        // running into the hole stop at its edge
+        let across = inside_length(Vec2::new(6.0, -1.0), Vec2::new(6.0, 1.0), &outline);
+        assert!((across - 2.0).abs() < 1e-5);
+        let into = inside_length(Vec2::new(2.0, 0.5), Vec2::new(6.0, 0.5), &outline);
+        assert!((into - (6.0 - 15.75f32.sqrt())).abs() < 0.01);
+    }
+
+    #[test]
+    fn cells_clear_of_the_outline_are_kept_or_dropped_whole() {
+        let outline = Domain::Annulus { inner: 0.4 }.outline();
+        let square = |centre: Vec2| Cell {
+            polygon: [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+                .map(|(x, y)| centre + Vec2::new(x, y))
+                .to_vec(),
+            ..default()
+        };
+        let kept = clip_cell(square(Vec2::new(0.0, 7.0)), &outline);
+        assert_eq!(kept.polygon.len(), 4);
+        assert!(kept.walls.is_empty());
+        // In the hole
+        assert!(clip_cell(square(Vec2::ZERO), &outline).polygon.is_empty());
+    }
+
+    #[test]
+    fn updates_match_rebuilds() {
+        let mut rng = ChaCha8Rng::seed_from_u64(21);
+        let mut partial = 0;
+        for (domain, wrap) in flat_domains() {
+            let mut sites = random_sites(&domain, 200, &mut rng);
+            let weights: Vec<f32> = (0..sites.len()).map(|i| (i % 3) as f32 * 0.01).collect();
+            let mut topology = build_topology(&sites, &weights, &domain, &[], wrap);
+            for _ in 0..10 {
+                for _ in 0..5 {
+                    let i = rng.gen_range(0..sites.len());
+                    let step = Vec2::new(rng.gen_range(-0.3..0.3), rng.gen_range(-0.3..0.3));
+                    sites[i] = domain.confine(sites[i] + step, wrap);
+                }
+                let changed = topology.update(&sites, &weights, &domain, &[], wrap);
+                let fresh = build_topology(&sites, &weights, &domain, &[], wrap);
+                let label = domain.label();
+                if changed.len() < sites.len() {
+                    partial += 1;
+                }
+                assert_eq!(topology.neighbors, fresh.neighbors, "{label}");
+                for (a, b) in topology.areas.iter().zip(&fresh.areas) {
+                    assert!((a - b).abs() < 1e-4, "{label}: area {a} against {b}");
+                }
+            }
+        }
+        // Only a site grazing another should have needed a full rebuild
+        assert!(partial >= 55, "only {partial} of 60 updates were repairs");
     }
 }